@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS correlation_links (
+    correlation_id TEXT PRIMARY KEY NOT NULL,
+    link TEXT NOT NULL
+);
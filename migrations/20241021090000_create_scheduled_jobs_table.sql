@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS scheduled_jobs (
+    name TEXT PRIMARY KEY NOT NULL,
+    last_run TIMESTAMP NOT NULL
+);
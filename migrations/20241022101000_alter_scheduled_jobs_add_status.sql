@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE scheduled_jobs ADD COLUMN next_run TIMESTAMP NULL;
+ALTER TABLE scheduled_jobs ADD COLUMN last_error TEXT NULL;
+ALTER TABLE scheduled_jobs ADD COLUMN paused BOOLEAN NOT NULL DEFAULT 0;
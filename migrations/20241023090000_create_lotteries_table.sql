@@ -0,0 +1,44 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS lotteries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    ticket_price INTEGER NOT NULL,
+    base_prize INTEGER NOT NULL,
+    next_ticket_price INTEGER NOT NULL,
+    next_base_prize INTEGER NOT NULL,
+    schedule TEXT NOT NULL,
+    draw_time TIMESTAMP NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- the existing lottery becomes the daily one, drawn at 18:00 UTC
+INSERT INTO lotteries (name, ticket_price, base_prize, next_ticket_price, next_base_prize, schedule, draw_time)
+VALUES (
+    'daily',
+    COALESCE((SELECT CAST(value AS INTEGER) FROM config WHERE key = 'lottery_ticket_price'), 5),
+    COALESCE((SELECT CAST(value AS INTEGER) FROM config WHERE key = 'lottery_base_prize'), 10),
+    COALESCE((SELECT CAST(value AS INTEGER) FROM config WHERE key = 'future_lottery_ticket_price'), 5),
+    COALESCE((SELECT CAST(value AS INTEGER) FROM config WHERE key = 'future_lottery_base_prize'), 10),
+    '0 0 18 * * *',
+    CASE
+        WHEN time('now') < '18:00:00' THEN datetime(date('now'), '+18 hours')
+        ELSE datetime(date('now'), '+1 day', '+18 hours')
+    END
+);
+
+DELETE FROM config WHERE key IN ('lottery_ticket_price', 'lottery_base_prize', 'future_lottery_ticket_price', 'future_lottery_base_prize');
+
+ALTER TABLE lottery_tickets RENAME TO lottery_tickets_old;
+
+CREATE TABLE IF NOT EXISTS lottery_tickets (
+    lottery_id INTEGER NOT NULL REFERENCES lotteries(id),
+    user_id BIGINT NOT NULL,
+    tickets INT NOT NULL,
+    PRIMARY KEY (lottery_id, user_id)
+);
+
+INSERT INTO lottery_tickets (lottery_id, user_id, tickets)
+SELECT (SELECT id FROM lotteries WHERE name = 'daily'), id, tickets FROM lottery_tickets_old;
+
+DROP TABLE lottery_tickets_old;
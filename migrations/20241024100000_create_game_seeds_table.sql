@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS game_seeds (
+    game_id TEXT PRIMARY KEY NOT NULL,
+    game TEXT NOT NULL,
+    seed TEXT NOT NULL,
+    commitment TEXT NOT NULL,
+    params TEXT NULL,
+    outcome TEXT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    revealed_at TIMESTAMP NULL
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE lotteries ADD COLUMN winner BIGINT NULL;
+ALTER TABLE lotteries ADD COLUMN pot INTEGER NULL;
+ALTER TABLE lotteries ADD COLUMN drawn_at TIMESTAMP NULL;
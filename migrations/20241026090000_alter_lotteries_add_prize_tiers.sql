@@ -0,0 +1,14 @@
+-- Add migration script here
+ALTER TABLE lotteries ADD COLUMN prize_tiers TEXT NOT NULL DEFAULT '100';
+ALTER TABLE lotteries ADD COLUMN min_tickets INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS lottery_prizes (
+    lottery_id INTEGER NOT NULL REFERENCES lotteries(id),
+    tier INTEGER NOT NULL,
+    user_id BIGINT NOT NULL,
+    amount INTEGER NOT NULL,
+    PRIMARY KEY (lottery_id, tier)
+);
+
+INSERT INTO lottery_prizes (lottery_id, tier, user_id, amount)
+SELECT id, 1, winner, pot FROM lotteries WHERE winner IS NOT NULL;
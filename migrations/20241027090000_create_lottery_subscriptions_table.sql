@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS lottery_subscriptions (
+    user_id BIGINT NOT NULL,
+    lottery_name TEXT NOT NULL,
+    tickets INTEGER NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    last_lottery_id INTEGER NULL,
+    PRIMARY KEY (user_id, lottery_name)
+);
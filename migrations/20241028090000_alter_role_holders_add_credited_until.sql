@@ -0,0 +1,3 @@
+-- Add migration script here
+-- crown hours are credited up to this point, NULL means nothing since purchased
+ALTER TABLE role_holders ADD COLUMN credited_until TIMESTAMP NULL;
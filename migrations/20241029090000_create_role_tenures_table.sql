@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS role_tenures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    role_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    acquired TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    lost TIMESTAMP NULL,
+    price INTEGER NULL,
+    lost_how TEXT NULL
+);
+CREATE INDEX IF NOT EXISTS role_tenures_role_id ON role_tenures (role_id, acquired);
+
+-- the current holders are all we know about, what they paid is lost
+INSERT INTO role_tenures (role_id, user_id, acquired)
+SELECT role_id, user_id, COALESCE(purchased, CURRENT_TIMESTAMP) FROM role_holders;
@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER TABLE purchaseable_roles ADD COLUMN rent_price INTEGER NULL;
+ALTER TABLE purchaseable_roles ADD COLUMN rent_days INTEGER NULL;
+
+CREATE TABLE IF NOT EXISTS role_rentals (
+    role_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    reminded BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (role_id, user_id)
+);
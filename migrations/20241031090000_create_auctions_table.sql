@@ -0,0 +1,17 @@
+-- Add migration script here
+-- the current high bid is held in escrow under auction-<id>
+CREATE TABLE IF NOT EXISTS auctions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    role_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    message_id BIGINT NULL,
+    reserve INTEGER NOT NULL,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL,
+    -- pending, open, sold, unsold or cancelled
+    status TEXT NOT NULL DEFAULT 'pending',
+    high_bidder BIGINT NULL,
+    high_bid INTEGER NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
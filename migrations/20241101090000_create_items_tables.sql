@@ -0,0 +1,26 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    description TEXT NOT NULL DEFAULT '',
+    price INTEGER NOT NULL,
+    -- NULL means there's no limit
+    stock INTEGER NULL,
+    max_per_user INTEGER NULL,
+    tradeable BOOLEAN NOT NULL DEFAULT FALSE,
+    -- bought items run out this many hours after purchase, NULL means never
+    expires_after_hours INTEGER NULL,
+    for_sale BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- one row per lot, items bought together expire together
+CREATE TABLE IF NOT EXISTS inventories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id BIGINT NOT NULL,
+    item_id INTEGER NOT NULL REFERENCES items (id),
+    quantity INTEGER NOT NULL,
+    acquired_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NULL
+);
+CREATE INDEX IF NOT EXISTS inventories_user_id ON inventories (user_id, item_id);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- what the item does when it's used up, NULL for items that are only for show
+ALTER TABLE items ADD COLUMN effect TEXT NULL;
@@ -0,0 +1,25 @@
+-- Add migration script here
+-- limit orders for bones, what's left of an order stays escrowed while it's open
+CREATE TABLE IF NOT EXISTS market_orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id BIGINT NOT NULL,
+    -- 'buy' or 'sell'
+    side TEXT NOT NULL,
+    -- bucks per bone
+    price INTEGER NOT NULL,
+    -- bones left to fill
+    quantity INTEGER NOT NULL,
+    -- open, filled, cancelled or expired
+    status TEXT NOT NULL DEFAULT 'open',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS market_orders_open ON market_orders (status, side, price);
+
+CREATE TABLE IF NOT EXISTS market_trades (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    buy_order INTEGER NOT NULL REFERENCES market_orders (id),
+    sell_order INTEGER NOT NULL REFERENCES market_orders (id),
+    price INTEGER NOT NULL,
+    quantity INTEGER NOT NULL,
+    traded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
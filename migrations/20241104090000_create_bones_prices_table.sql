@@ -0,0 +1,19 @@
+-- Add migration script here
+-- every price Johnny sets for bones
+CREATE TABLE IF NOT EXISTS bones_prices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    price INTEGER NOT NULL,
+    recorded_at TIMESTAMP NOT NULL
+);
+CREATE INDEX IF NOT EXISTS bones_prices_recorded_at ON bones_prices (recorded_at);
+
+-- start the history with the current price
+INSERT INTO bones_prices (price, recorded_at)
+SELECT
+    CAST(price.value AS INTEGER),
+    COALESCE(
+        (SELECT strftime('%Y-%m-%dT%H:%M:%S+00:00', CAST(value AS INTEGER), 'unixepoch') FROM config WHERE key = 'bones_price_updated'),
+        strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now')
+    )
+FROM config price
+WHERE price.key = 'bones_price';
@@ -0,0 +1,21 @@
+-- Add migration script here
+-- bones by when they were acquired so decay can go by age, spending takes the oldest first
+CREATE TABLE IF NOT EXISTS bones_lots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id BIGINT NOT NULL,
+    quantity INTEGER NOT NULL,
+    acquired_at TIMESTAMP NOT NULL
+);
+CREATE INDEX IF NOT EXISTS bones_lots_user_id ON bones_lots (user_id);
+
+-- bones held today count as acquired now
+INSERT INTO bones_lots (user_id, quantity, acquired_at)
+SELECT id, bones, strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now')
+FROM balances
+WHERE bones > 0;
+
+-- the decay schedule carries on from the last weekly decay
+INSERT OR IGNORE INTO config (key, value)
+SELECT 'bones_decayed_at', strftime('%s', last_run)
+FROM scheduled_jobs
+WHERE name = 'bones_decay' AND strftime('%s', last_run) IS NOT NULL;
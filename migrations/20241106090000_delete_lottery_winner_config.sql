@@ -0,0 +1,3 @@
+-- Add migration script here
+-- lottery seeds are committed when a round opens, so the winner can't be picked by hand anymore
+DELETE FROM config WHERE key = 'lottery_winner';
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- when the oldest bones in a sell order were acquired, so they keep their age for decay
+ALTER TABLE market_orders ADD COLUMN acquired_at TIMESTAMP;
+UPDATE market_orders SET acquired_at = created_at WHERE side = 'sell';
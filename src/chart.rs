@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use tiny_skia::{Color, Paint, PathBuilder, Pixmap, Stroke, StrokeDash, Transform};
+
+use crate::Error;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 300;
+const MARGIN: f32 = 20.0;
+
+#[derive(Debug, PartialEq)]
+pub struct PriceStats {
+    pub min: i32,
+    pub max: i32,
+    pub average: f32,
+}
+
+/// Lowest, highest and average of the recorded prices
+pub fn price_stats(prices: &[(DateTime<Utc>, i32)]) -> Option<PriceStats> {
+    let min = prices.iter().map(|p| p.1).min()?;
+    let max = prices.iter().map(|p| p.1).max()?;
+    let average = prices.iter().map(|p| p.1 as f32).sum::<f32>() / prices.len() as f32;
+    Some(PriceStats { min, max, average })
+}
+
+/// PNG line chart of the prices between `from` and `to`, each price holds until the next one.
+/// Faint lines mark the lowest and highest price, the dashed one the average.
+pub fn price_chart(
+    prices: &[(DateTime<Utc>, i32)],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<u8>, Error> {
+    let stats = price_stats(prices).ok_or("No prices to chart")?;
+    let mut pixmap = Pixmap::new(WIDTH, HEIGHT).ok_or("Chart has no size")?;
+    pixmap.fill(Color::from_rgba8(43, 45, 49, 255));
+
+    let (low, high) = (stats.min as f32 - 1.0, stats.max as f32 + 1.0);
+    let span = (to - from).num_seconds().max(1) as f32;
+    let x = |t: DateTime<Utc>| {
+        MARGIN + (t.max(from) - from).num_seconds() as f32 / span * (WIDTH as f32 - 2.0 * MARGIN)
+    };
+    let y = |price: f32| {
+        HEIGHT as f32 - MARGIN - (price - low) / (high - low) * (HEIGHT as f32 - 2.0 * MARGIN)
+    };
+
+    let mut paint = Paint {
+        anti_alias: true,
+        ..Default::default()
+    };
+    let mut stroke = Stroke {
+        width: 1.0,
+        ..Default::default()
+    };
+    paint.set_color_rgba8(78, 80, 88, 255);
+    for price in [stats.min as f32, stats.max as f32] {
+        horizontal_line(&mut pixmap, y(price), &paint, &stroke)?;
+    }
+    paint.set_color_rgba8(148, 155, 164, 255);
+    stroke.dash = StrokeDash::new(vec![8.0, 6.0], 0.0);
+    horizontal_line(&mut pixmap, y(stats.average), &paint, &stroke)?;
+
+    let mut path = PathBuilder::new();
+    path.move_to(x(prices[0].0), y(prices[0].1 as f32));
+    for pair in prices.windows(2) {
+        path.line_to(x(pair[1].0), y(pair[0].1 as f32));
+        path.line_to(x(pair[1].0), y(pair[1].1 as f32));
+    }
+    path.line_to(x(to), y(prices[prices.len() - 1].1 as f32));
+    let path = path.finish().ok_or("Couldn't draw the prices")?;
+    paint.set_color_rgba8(240, 230, 205, 255);
+    stroke.width = 3.0;
+    stroke.dash = None;
+    pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+
+    Ok(pixmap.encode_png()?)
+}
+
+fn horizontal_line(
+    pixmap: &mut Pixmap,
+    y: f32,
+    paint: &Paint,
+    stroke: &Stroke,
+) -> Result<(), Error> {
+    let mut path = PathBuilder::new();
+    path.move_to(MARGIN, y);
+    path.line_to(WIDTH as f32 - MARGIN, y);
+    let path = path.finish().ok_or("Couldn't draw the line")?;
+    pixmap.stroke_path(&path, paint, stroke, Transform::identity(), None);
+    Ok(())
+}
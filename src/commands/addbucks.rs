@@ -1,4 +1,4 @@
-use crate::database::BalanceDatabase;
+use crate::database::{BalanceDatabase, Reason};
 use crate::{Context, Error};
 use poise::serenity_prelude::User;
 use poise::CreateReply;
@@ -33,7 +33,10 @@ pub async fn add_bucks(
         return Err("You can't do that".into());
     }
     let user_id = user.id.get();
-    ctx.data().db.award_balances(vec![user_id], amount).await?;
+    ctx.data()
+        .db
+        .award_balances(vec![user_id], amount, Reason::AddBucks.into())
+        .await?;
     let reply =
         { CreateReply::default().content(format!("{} was given {} J-Buck(s)", user, amount,)) };
     ctx.send(reply).await?;
@@ -0,0 +1,352 @@
+use chrono::{Duration, Utc};
+use poise::{
+    serenity_prelude::{self as serenity, CreateAllowedMentions},
+    CreateReply,
+};
+
+use crate::{
+    database::{self, Auction, AuctionDatabase},
+    discord::JBUCK_EMOJI,
+    Context, Error,
+};
+
+/// Button on the auction message, `auction-bid-<id>`, bids the minimum
+pub const BID_BUTTON: &str = "auction-bid-";
+
+/// A bid this close to the end pushes the end back so there's this long left
+pub const SNIPE_WINDOW_MINUTES: i64 = 2;
+
+/// Whether the role is up for auction or about to be, it can't be bought or rented then
+pub async fn is_auctioned(db: &database::Database, role_id: u64) -> Result<bool, Error> {
+    Ok(db
+        .get_active_auctions()
+        .await?
+        .iter()
+        .any(|a| a.role_id as u64 == role_id))
+}
+
+pub fn auction_text(auction: &Auction) -> String {
+    let mut text = format!(
+        "> ### Auction #{} for <@&{}>\n> Reserve: {} {}\n",
+        auction.id, auction.role_id, auction.reserve, JBUCK_EMOJI
+    );
+    let high_bid = auction.high_bidder.zip(auction.high_bid);
+    match (auction.status.as_str(), high_bid) {
+        ("sold", Some((user, bid))) => text.push_str(&format!(
+            "> Sold to <@{}> for {} {}!\n",
+            user, bid, JBUCK_EMOJI
+        )),
+        ("unsold", _) => text.push_str("> Ended without any bids\n"),
+        ("cancelled", _) => text.push_str("> Cancelled, the high bid was refunded\n"),
+        (_, bid) => {
+            match bid {
+                Some((user, bid)) => text.push_str(&format!(
+                    "> High bid: {} {} by <@{}>\n",
+                    bid, JBUCK_EMOJI, user
+                )),
+                None => text.push_str("> No bids yet\n"),
+            }
+            text.push_str(&format!(
+                "> Ends <t:{}:R>\n> -# Bid with the button or `/bid`, late bids push the end back {} minutes",
+                auction.end_time.timestamp(),
+                SNIPE_WINDOW_MINUTES
+            ));
+        }
+    }
+    text
+}
+
+pub fn auction_buttons(auction: &Auction) -> Vec<serenity::CreateActionRow> {
+    if auction.status != "open" {
+        return vec![];
+    }
+    vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(format!("{}{}", BID_BUTTON, auction.id))
+            .label(format!("Bid {}", auction.min_bid()))
+            .style(serenity::ButtonStyle::Primary),
+    ])]
+}
+
+/// Posts the auction in its channel and opens it for bids
+pub async fn post_auction(
+    http: &serenity::Http,
+    db: &database::Database,
+    mut auction: Auction,
+) -> Result<(), Error> {
+    auction.status = "open".to_string();
+    let message = serenity::CreateMessage::default()
+        .content(auction_text(&auction))
+        .components(auction_buttons(&auction))
+        .allowed_mentions(CreateAllowedMentions::new().empty_users().empty_roles());
+    let message = serenity::ChannelId::new(auction.channel_id as u64)
+        .send_message(http, message)
+        .await?;
+    db.open_auction(auction.id, message.id.get()).await?;
+    Ok(())
+}
+
+/// Brings the auction message up to date with the auction
+pub async fn update_auction_message(http: &serenity::Http, auction: &Auction) {
+    let message_id = match auction.message_id {
+        Some(id) => serenity::MessageId::new(id as u64),
+        None => return,
+    };
+    let edit = serenity::EditMessage::default()
+        .content(auction_text(auction))
+        .components(auction_buttons(auction));
+    if let Err(e) = serenity::ChannelId::new(auction.channel_id as u64)
+        .edit_message(http, message_id, edit)
+        .await
+    {
+        tracing::error!("Error updating auction {} message: {e}", auction.id);
+    }
+}
+
+/// Bids on an auction, the minimum bid if there's no amount. The inner error is why the
+/// bid wasn't taken, to show the user.
+pub async fn bid_on(
+    http: &serenity::Http,
+    db: &database::Database,
+    auction_id: i64,
+    user_id: serenity::UserId,
+    amount: Option<i32>,
+) -> Result<Result<String, String>, Error> {
+    let amount = match amount {
+        Some(amount) => amount,
+        None => match db.get_auction(auction_id).await? {
+            Some(auction) => auction.min_bid(),
+            None => return Ok(Err("There's no such auction".to_string())),
+        },
+    };
+    let placed = match db
+        .place_bid(
+            auction_id,
+            user_id.get(),
+            amount,
+            Utc::now(),
+            Duration::minutes(SNIPE_WINDOW_MINUTES),
+        )
+        .await?
+    {
+        Ok(placed) => placed,
+        Err(refused) => return Ok(Err(refused.to_string())),
+    };
+
+    if let Some((outbid, refund)) = placed.outbid.filter(|(u, _)| *u != user_id.get()) {
+        let m = serenity::CreateMessage::new().content(format!(
+            "You were outbid in auction #{}, your {} {} are back. Bid again with `/bid`",
+            auction_id, refund, JBUCK_EMOJI
+        ));
+        if let Err(e) = serenity::UserId::new(outbid).dm(http, m).await {
+            tracing::error!("Error when dm'ing {outbid}: {e}");
+        }
+    }
+    update_auction_message(http, &placed.auction).await;
+
+    let mut response = format!("You're the high bidder with {} {}", amount, JBUCK_EMOJI);
+    if placed.extended {
+        response.push_str(&format!(
+            ", the auction now ends <t:{}:R>",
+            placed.auction.end_time.timestamp()
+        ));
+    }
+    Ok(Ok(response))
+}
+
+///
+/// auctions for unique roles
+///
+#[poise::command(slash_command, subcommands("list", "open", "cancel"))]
+pub async fn auction(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+///
+/// see the running auctions
+///
+/// Enter `/auction list`
+/// ```
+/// /auction list
+/// ```
+#[poise::command(slash_command)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let auctions = ctx.data().db.get_active_auctions().await?;
+    let content = if auctions.is_empty() {
+        "There are no auctions running".to_string()
+    } else {
+        auctions
+            .iter()
+            .map(|a| match a.status.as_str() {
+                "pending" => format!(
+                    "> **#{}** <@&{}> starts <t:{}:R>",
+                    a.id,
+                    a.role_id,
+                    a.start_time.timestamp()
+                ),
+                _ => format!(
+                    "> **#{}** <@&{}> - next bid {} {}, ends <t:{}:R>",
+                    a.id,
+                    a.role_id,
+                    a.min_bid(),
+                    JBUCK_EMOJI,
+                    a.end_time.timestamp()
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let reply = CreateReply::default().content(content).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// auction off a unique role
+///
+/// Enter `/auction open <role> <reserve> <minutes>`
+/// ```
+/// /auction open @Crown 100 60
+/// ```
+#[poise::command(slash_command, required_permissions = "ADMINISTRATOR")]
+pub async fn open(
+    ctx: Context<'_>,
+    #[description = "A role only one person can have"] role: serenity::Role,
+    #[min = 1]
+    #[description = "The lowest first bid"]
+    reserve: i32,
+    #[min = 1]
+    #[description = "How long the auction runs"]
+    minutes: i64,
+    #[min = 1]
+    #[description = "Open it later, Johnny posts it when it starts"]
+    starts_in_minutes: Option<i64>,
+) -> Result<(), Error> {
+    if !ctx.data().unique_roles.lock().unwrap().contains(&role.id) {
+        let reply = CreateReply::default()
+            .content(format!("{} isn't a unique role", role))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+    if is_auctioned(&ctx.data().db, role.id.get()).await? {
+        let reply = CreateReply::default()
+            .content(format!("{} is already up for auction", role))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    let start_time = Utc::now() + Duration::minutes(starts_in_minutes.unwrap_or(0));
+    let end_time = start_time + Duration::minutes(minutes);
+    let db = &ctx.data().db;
+    let id = db
+        .create_auction(
+            role.id.get(),
+            ctx.guild_id()
+                .ok_or("Auctions have to be in a server")?
+                .get(),
+            ctx.channel_id().get(),
+            reserve,
+            start_time,
+            end_time,
+        )
+        .await?;
+    if starts_in_minutes.is_none() {
+        let auction = db.get_auction(id).await?.ok_or("Auction went missing")?;
+        post_auction(&ctx.serenity_context().http, db, auction).await?;
+    }
+
+    let reply = CreateReply::default()
+        .content(format!(
+            "Auction #{} for {} starts <t:{}:R> and ends <t:{}:R>",
+            id,
+            role,
+            start_time.timestamp(),
+            end_time.timestamp()
+        ))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// call off an auction and refund the high bid
+///
+/// Enter `/auction cancel <id>`
+/// ```
+/// /auction cancel 3
+/// ```
+#[poise::command(slash_command, required_permissions = "ADMINISTRATOR")]
+pub async fn cancel(
+    ctx: Context<'_>,
+    #[description = "The auction number"] id: i64,
+) -> Result<(), Error> {
+    let db = &ctx.data().db;
+    let mut auction = match db.get_auction(id).await? {
+        Some(a) if a.status == "pending" || a.status == "open" => a,
+        _ => {
+            let reply = CreateReply::default()
+                .content("That auction isn't running")
+                .ephemeral(true);
+            ctx.send(reply).await?;
+            return Ok(());
+        }
+    };
+    db.cancel_auction(id).await?;
+    auction.status = "cancelled".to_string();
+    update_auction_message(&ctx.serenity_context().http, &auction).await;
+
+    let reply = CreateReply::default()
+        .content(format!("Cancelled auction #{}", id))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// bid on an auction
+///
+/// Enter `/bid <amount>`, the auction ending soonest unless you pick one
+/// ```
+/// /bid 150
+/// ```
+#[poise::command(slash_command)]
+pub async fn bid(
+    ctx: Context<'_>,
+    #[min = 1]
+    #[description = "How much to bid"]
+    amount: i32,
+    #[description = "The auction number"] auction: Option<i64>,
+) -> Result<(), Error> {
+    let auction_id = match auction {
+        Some(id) => Some(id),
+        None => ctx
+            .data()
+            .db
+            .get_active_auctions()
+            .await?
+            .iter()
+            .find(|a| a.status == "open")
+            .map(|a| a.id),
+    };
+    let result = match auction_id {
+        Some(id) => {
+            bid_on(
+                &ctx.serenity_context().http,
+                &ctx.data().db,
+                id,
+                ctx.author().id,
+                Some(amount),
+            )
+            .await?
+        }
+        None => Err("There are no auctions running".to_string()),
+    };
+
+    let content = match result {
+        Ok(content) | Err(content) => content,
+    };
+    let reply = CreateReply::default().content(content).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::database::BalanceDatabase;
+use crate::database::{BalanceDatabase, Reason};
 use crate::{Context, Error};
 use poise::serenity_prelude::User;
 use poise::CreateReply;
@@ -34,7 +34,10 @@ pub async fn award(
         return Err("You can't afford to do that".into());
     }
     let user_id = user.id.get();
-    ctx.data().db.award_balances(vec![user_id], amount).await?;
+    ctx.data()
+        .db
+        .award_balances(vec![user_id], amount, Reason::Award.into())
+        .await?;
 
     // if show_caller is true, send as a reply
     let msg = match reason {
@@ -1,8 +1,11 @@
 use crate::commands::robbingevent::week_bounds;
-use crate::database::{BalanceDatabase, LotteryDatabase, RobberyDatabase};
+use crate::database::{BalanceDatabase, Currency, LotteryDatabase, RobberyDatabase};
 use crate::discord::JBUCK_EMOJI;
 use crate::{Context, Error};
 use chrono::Datelike;
+use poise::serenity_prelude as serenity;
+
+const HISTORY_PAGE_SIZE: i64 = 10;
 
 ///
 /// Check your balance
@@ -21,6 +24,21 @@ pub async fn balance(ctx: Context<'_>) -> Result<(), Error> {
         .db
         .get_user_tickets(ctx.author().id.get())
         .await?;
+    let subscriptions = ctx
+        .data()
+        .db
+        .get_user_subscriptions(ctx.author().id.get())
+        .await?
+        .iter()
+        .map(|s| {
+            format!(
+                "\n> **Lottery Subscription:** {} :tickets: {} until <t:{}:d>",
+                s.tickets,
+                s.lottery_name,
+                s.expires_at.timestamp()
+            )
+        })
+        .collect::<String>();
 
     let robbery_status: String;
     {
@@ -40,7 +58,7 @@ pub async fn balance(ctx: Context<'_>) -> Result<(), Error> {
         let nitro_role = ctx.guild().unwrap().role_by_name("Nitro Dealers").cloned();
 
         let has_nitro_role = match nitro_role {
-            Some(x) => a.roles.iter().any(|&y| y == x.id),
+            Some(x) => a.roles.contains(&x.id),
             None => false,
         };
 
@@ -71,12 +89,13 @@ pub async fn balance(ctx: Context<'_>) -> Result<(), Error> {
     let minutes = (((crown_time.1.fract() * 100.0).round() / 100.0) * 60.0) as i32;
 
     let response = format!(
-        "> **{}'s Balance** \n> \n> **Balance:** {} {}\n> **Bones**: {} :bone:\n> **Lottery Tickets:** {} :tickets:\n> **Crown Time**: {:0>2}:{:0>2} :clock1:\n> **Robbery Status**: {} :moneybag:",
+        "> **{}'s Balance** \n> \n> **Balance:** {} {}\n> **Bones**: {} :bone:\n> **Lottery Tickets:** {} :tickets:{}\n> **Crown Time**: {:0>2}:{:0>2} :clock1:\n> **Robbery Status**: {} :moneybag:",
         ctx.author(),
         response,
         JBUCK_EMOJI,
         bones,
         lottery_tickets,
+        subscriptions,
         hours, minutes,
         robbery_status
     );
@@ -88,3 +107,123 @@ pub async fn balance(ctx: Context<'_>) -> Result<(), Error> {
     ctx.send(reply).await?;
     Ok(())
 }
+
+///
+/// See where your bucks and bones went
+///
+/// Enter `/history` to see your latest balance changes
+/// ```
+/// /history
+/// /history Bones
+/// ```
+#[poise::command(slash_command)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Only show one currency"] currency: Option<Currency>,
+) -> Result<(), Error> {
+    show_history(ctx, ctx.author(), currency).await
+}
+
+async fn history_page(
+    ctx: Context<'_>,
+    user: &serenity::User,
+    currency: Option<Currency>,
+    page: i64,
+) -> Result<(String, bool), Error> {
+    // fetch one extra row to know if there is a next page
+    let mut rows = ctx
+        .data()
+        .db
+        .get_transactions(
+            user.id.get(),
+            currency,
+            HISTORY_PAGE_SIZE + 1,
+            page * HISTORY_PAGE_SIZE,
+        )
+        .await?;
+    let has_next = rows.len() as i64 > HISTORY_PAGE_SIZE;
+    rows.truncate(HISTORY_PAGE_SIZE as usize);
+
+    let mut content = format!("> **{}'s History** (page {})\n> \n", user.name, page + 1);
+    if rows.is_empty() {
+        content.push_str("> Nothing here yet");
+    }
+    for row in rows {
+        let emoji = match row.currency.as_str() {
+            "bones" => ":bone:",
+            _ => JBUCK_EMOJI,
+        };
+        let link = match row.link {
+            Some(link) => format!(" [game]({})", link),
+            None => String::new(),
+        };
+        content.push_str(&format!(
+            "> **{:+}** {} {} - balance {} <t:{}:R>{}\n",
+            row.amount,
+            emoji,
+            row.reason.replace('_', " "),
+            row.balance,
+            row.created_at.timestamp(),
+            link
+        ));
+    }
+    Ok((content, has_next))
+}
+
+fn history_buttons(ctx_id: u64, page: i64, has_next: bool) -> Vec<serenity::CreateActionRow> {
+    vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(format!("{}prev", ctx_id))
+            .label("⬅️")
+            .style(serenity::ButtonStyle::Secondary)
+            .disabled(page == 0),
+        serenity::CreateButton::new(format!("{}next", ctx_id))
+            .label("➡️")
+            .style(serenity::ButtonStyle::Secondary)
+            .disabled(!has_next),
+    ])]
+}
+
+/// Pages through a user's transactions, shared by `/history` and `/checkbucks`
+pub async fn show_history(
+    ctx: Context<'_>,
+    user: &serenity::User,
+    currency: Option<Currency>,
+) -> Result<(), Error> {
+    let ctx_id = ctx.id();
+    let mut page = 0;
+    let (content, has_next) = history_page(ctx, user, currency, page).await?;
+    let reply = poise::CreateReply::default()
+        .content(content)
+        .components(history_buttons(ctx_id, page, has_next))
+        .ephemeral(true);
+    let handle = ctx.send(reply).await?;
+    let message_id = handle.message().await?.id;
+
+    while let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
+        .message_id(message_id)
+        .author_id(ctx.author().id)
+        .timeout(std::time::Duration::from_secs(120))
+        .await
+    {
+        if mci.data.custom_id == format!("{}next", ctx_id) {
+            page += 1;
+        } else if page > 0 {
+            page -= 1;
+        }
+        let (content, has_next) = history_page(ctx, user, currency, page).await?;
+        mci.create_response(
+            ctx,
+            serenity::CreateInteractionResponse::UpdateMessage(
+                serenity::CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(history_buttons(ctx_id, page, has_next)),
+            ),
+        )
+        .await?;
+    }
+
+    handle
+        .edit(ctx, poise::CreateReply::default().components(vec![]))
+        .await?;
+    Ok(())
+}
@@ -1,12 +1,20 @@
-use std::cmp::Ordering;
 use std::fmt::Write;
 use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::database::BalanceDatabase;
-use crate::{game::Blackjack, Context, Error};
+use crate::commands::cardtable::{ephemeral_response, robbery_refusal, time_left};
+use crate::database::{BalanceDatabase, EscrowDatabase, Reason};
+use crate::discord::JBUCK_EMOJI;
+use crate::game::{Blackjack, BlackjackError, Card, Hand, Shoe};
+use crate::{Context, Error};
 use poise::{serenity_prelude as serenity, CreateReply, ReplyHandle};
-use rand::{seq::SliceRandom, Rng};
+use rand::seq::SliceRandom;
+
+/// Decks in the shoe, plenty for a full table splitting every hand
+const DECKS: usize = 6;
+
+/// The biggest bet at the table, keep it in line with the `#[max]` on `/blackjack`
+const MAX_BET: i32 = 5;
 
 async fn in_blackjack(ctx: Context<'_>) -> Result<bool, Error> {
     if *ctx.data().blackjack_active.lock().unwrap() {
@@ -25,9 +33,10 @@ async fn in_blackjack(ctx: Context<'_>) -> Result<bool, Error> {
 ///
 /// Start a blackjack game
 ///
-/// Enter `/blackjack`
+/// Enter `/blackjack <amount>`, everyone who joins picks their own bet against the dealer.
+/// Hit, stand, double down or split your pairs. Blackjack pays 3:2 and the dealer draws to 17.
 /// ```
-/// /blackjack
+/// /blackjack 5
 /// ```
 #[poise::command(slash_command, check = "in_blackjack")]
 #[tracing::instrument(level = "info")]
@@ -41,11 +50,24 @@ pub async fn blackjack(
     {
         *ctx.data().blackjack_active.lock().unwrap() = true;
     }
+    let result = play(ctx, amount).await;
+    ctx.serenity_context().shard.set_activity(None);
+    {
+        *ctx.data().blackjack_active.lock().unwrap() = false;
+    }
+    if result.is_err() {
+        // whatever broke the game, the stakes it still holds go back
+        ctx.data().db.refund_escrow(&ctx.id().to_string()).await?;
+    }
+    result
+}
+
+async fn play(ctx: Context<'_>, amount: i32) -> Result<(), Error> {
     let game_length = { ctx.data().config.read().unwrap().game_length_seconds };
     let db = &ctx.data().db;
-    // let game_starter = ctx.author().id.to_string();
-    let player_balance = db.get_balance(ctx.author().id.get()).await?;
-    if player_balance < amount {
+    let game_id = ctx.id().to_string();
+    let memo = || Reason::Blackjack.with_id(ctx.id());
+    if db.get_balance(ctx.author().id.get()).await? < amount {
         let reply = {
             CreateReply::default()
                 .content("You don't have enough to play.")
@@ -53,306 +75,282 @@ pub async fn blackjack(
                 .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users())
         };
         ctx.send(reply).await?;
-        {
-            *ctx.data().blackjack_active.lock().unwrap() = false;
-        }
         return Ok(());
     }
+    db.escrow_stake(ctx.author().id.get(), amount, memo())
+        .await?;
 
-    let start_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    let time_to_play = game_length;
-    let reply = {
-        CreateReply::default()
-            .content(format!(
-                "> ### It's Blackjack time, roll the :game_die: to play!\n{}> **You have <t:{}:R> seconds to play.**",
-                "",
-                start_time + time_to_play as u64
-            ))
-            .components(
-                vec![serenity::CreateActionRow::Buttons(vec![
-                    new_twodice_button(),
-                    new_player_count_button(1),
-                ])]
-            )
+    let game = {
+        let shoe = Shoe::new(DECKS, &mut *ctx.data().rng.lock().unwrap());
+        let mut game = Blackjack::new(shoe);
+        game.join(ctx.author().id.get(), amount)?;
+        Mutex::new(game)
     };
 
-    let a = ctx.send(reply).await?;
-    let id = a.message().await?.id;
+    let deadline = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + game_length as u64;
+    let reply = CreateReply::default()
+        .content(lobby_message(deadline))
+        .components(lobby_components(&game.lock().unwrap()));
+    let handle = ctx.send(reply).await?;
+    let message = handle.message().await?;
+    db.link_transactions(&game_id, &message.link()).await?;
     ctx.serenity_context()
         .shard
         .set_activity(Some(serenity::ActivityData::playing("Blackjack!")));
 
-    let game = Mutex::new(Blackjack::new());
-    let bot_idx = {
-        let mut g = game.lock().unwrap();
-        g.player_joined(ctx.data().bot_id);
-        g.pot += amount * 2;
-        g.players
-            .iter()
-            .enumerate()
-            .find(|x| x.1 == &ctx.data().bot_id)
-            .unwrap()
-            .0
-    };
-
     while let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
-        .channel_id(ctx.channel_id())
-        .custom_ids(vec![
-            "twodice".to_string(),
-            "onedice".to_string(),
-            "stand".to_string(),
-        ])
-        .timeout(std::time::Duration::from_secs(
-            (start_time + time_to_play as u64 - 1)
-                - SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
-        ))
+        .message_id(message.id)
+        .custom_ids((1..=MAX_BET).map(join_id).collect())
+        .timeout(time_left(deadline)?)
         .await
     {
         let player = mci.user.id.get();
-        if ctx
-            .data()
-            .locked_balances
-            .lock()
-            .unwrap()
-            .contains(&(mci.user.id.get()))
-        {
-            mci.create_response(
-                ctx,
-                serenity::CreateInteractionResponse::Message(
-                    serenity::CreateInteractionResponseMessage::new()
-                        .content(
-                                "Nice try, but you can't do that while the robbing event is happening. You can play again after.",
-                        )
-                        .ephemeral(true),
-                ),
-            )
-            .await?;
-            continue;
-        }
-        if mci.data.custom_id == "stand" {
-            mci.create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
-                .await?;
-            if mci.message.id != id {
-                mci.delete_followup(ctx, mci.message.id).await?;
-            }
+        let bet = match (1..=MAX_BET).find(|bet| join_id(*bet) == mci.data.custom_id) {
+            Some(bet) => bet,
+            None => continue,
+        };
+        if let Some(refusal) = robbery_refusal(ctx, player) {
+            ephemeral_response(ctx, &mci, refusal).await?;
             continue;
         }
-
-        let mut msg = String::new();
-
-        let new_player: bool = {
+        let seated = {
             let game = game.lock().unwrap();
-            !game.players.contains(&player)
+            game.seats.iter().any(|s| s.player == player)
         };
-        if new_player {
-            let player_balance = db.get_balance(player).await?;
-            if player_balance < amount {
-                let reply = {
-                    CreateReply::default()
-                        .content("You don't have enough to play.")
-                        .ephemeral(true)
-                        .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users())
-                };
-                ctx.send(reply).await?;
-                continue;
-            }
-            game.lock().unwrap().player_joined(player);
-            db.subtract_balances(vec![player], amount).await?;
-            game.lock().unwrap().pot += amount;
+        if seated {
+            ephemeral_response(ctx, &mci, BlackjackError::AlreadySeated.to_string()).await?;
+            continue;
         }
-
-        let idx = {
-            game.lock()
-                .unwrap()
-                .players
-                .iter()
-                .enumerate()
-                .find(|x| x.1 == &player)
-                .unwrap()
-                .0
-        };
-
-        {
-            if game.lock().unwrap().players_scores[idx] >= 21 {
-                msg = format!(
-                    "You already have a score of {}.\nYou can't roll anymore.",
-                    game.lock().unwrap().players_scores[idx]
-                );
-                if mci.message.id != id {
-                    mci.delete_followup(ctx, mci.message.id).await?;
-                }
-                mci.create_response(
-                    ctx,
-                    serenity::CreateInteractionResponse::Message(
-                        serenity::CreateInteractionResponseMessage::new()
-                            .content(msg)
-                            .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users())
-                            .ephemeral(true),
-                    ),
-                )
-                .await?;
-                continue;
-            }
+        // the seat is only taken once the stake is in escrow
+        let mut joined = { game.lock().unwrap().clone() };
+        if let Err(e) = joined.join(player, bet) {
+            ephemeral_response(ctx, &mci, e.to_string()).await?;
+            continue;
         }
-
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-
-        if mci.data.custom_id == "twodice" {
-            let one = ctx.data().rng.lock().unwrap().gen_range(1..=6);
-            let two = ctx.data().rng.lock().unwrap().gen_range(1..=6);
-            let total = one + two;
-            let mut game = game.lock().unwrap();
-            game.players_scores[idx] += total;
-            msg = format!(
-                "You rolled a {} and a {} for a total of {}.\nYour current score is {}.\nGame Ends: <t:{}:R>",
-                one, two, total, game.players_scores[idx], now + (start_time + time_to_play as u64 - now)
-            );
-        } else if mci.data.custom_id == "onedice" {
-            let total = ctx.data().rng.lock().unwrap().gen_range(1..=6);
-            let mut game = game.lock().unwrap();
-            game.players_scores[idx] += total;
-            msg = format!(
-                "You rolled a {}.\nYour current score is {}.\nGame Ends: <t:{}:R>",
-                total,
-                game.players_scores[idx],
-                now + (start_time + time_to_play as u64 - now)
-            );
+        if let Err(e) = db.escrow_stake(player, bet, memo()).await {
+            tracing::warn!("{player} couldn't join blackjack {game_id}: {e}");
+            ephemeral_response(ctx, &mci, "You don't have enough to play.".to_string()).await?;
+            continue;
         }
+        *game.lock().unwrap() = joined;
+        mci.create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+            .await?;
+        let reply = CreateReply::default()
+            .content(lobby_message(deadline))
+            .components(lobby_components(&game.lock().unwrap()));
+        handle.edit(ctx, reply).await?;
+    }
 
-        let mut components = {
-            let g = game.lock().unwrap();
-            match g.players_scores[idx] >= 16 {
-                true => vec![serenity::CreateActionRow::Buttons(vec![
-                    new_twodice_button(),
-                    new_onedice_button(),
-                    new_hold_button(),
-                    new_player_count_button(g.players.len() as i32),
-                    new_pot_counter_button(g.pot),
-                ])],
-                false => vec![serenity::CreateActionRow::Buttons(vec![
-                    new_twodice_button(),
-                    new_hold_button(),
-                    new_player_count_button(g.players.len() as i32),
-                    new_pot_counter_button(g.pot),
-                ])],
-            }
+    let g = {
+        let mut game = game.lock().unwrap();
+        game.deal()?;
+        game.clone()
+    };
+    let deadline = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + game_length as u64;
+    update_table(ctx, &handle, &g, Some(deadline)).await?;
+
+    while !game.lock().unwrap().is_finished() {
+        let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
+            .message_id(message.id)
+            .custom_ids(vec![
+                "blackjack_hit".to_string(),
+                "blackjack_stand".to_string(),
+                "blackjack_double".to_string(),
+                "blackjack_split".to_string(),
+            ])
+            .timeout(time_left(deadline)?)
+            .await
+        else {
+            break;
         };
+        let player = mci.user.id.get();
+        if let Some(refusal) = robbery_refusal(ctx, player) {
+            ephemeral_response(ctx, &mci, refusal).await?;
+            continue;
+        }
 
-        match game.lock().unwrap().players_scores[idx].cmp(&21) {
-            Ordering::Greater => {
-                msg += "\nYou busted!";
-                components = vec![];
-            }
-            Ordering::Equal => {
-                msg += "\nYou got a blackjack!";
-                components = vec![];
+        let acted = match mci.data.custom_id.as_str() {
+            "blackjack_hit" => game.lock().unwrap().hit(player).map(|_| ()),
+            "blackjack_stand" => game.lock().unwrap().stand(player),
+            action => {
+                // doubling and splitting put up another bet, the move is played on a copy
+                // and only kept once that bet is in escrow
+                let mut next = { game.lock().unwrap().clone() };
+                let played = match action {
+                    "blackjack_double" => next
+                        .double_cost(player)
+                        .and_then(|cost| next.double(player).map(|_| cost)),
+                    _ => next
+                        .split_cost(player)
+                        .and_then(|cost| next.split(player).map(|_| cost)),
+                };
+                match played {
+                    Ok(cost) => {
+                        if let Err(e) = db.escrow_stake(player, cost, memo()).await {
+                            tracing::warn!("{player} couldn't raise in blackjack {game_id}: {e}");
+                            ephemeral_response(
+                                ctx,
+                                &mci,
+                                "You don't have enough bucks for that.".to_string(),
+                            )
+                            .await?;
+                            continue;
+                        }
+                        *game.lock().unwrap() = next;
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                }
             }
-            _ => {}
+        };
+        if let Err(e) = acted {
+            ephemeral_response(ctx, &mci, e.to_string()).await?;
+            continue;
         }
-
         mci.create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
             .await?;
-        if mci.message.id != id {
-            mci.delete_followup(ctx, mci.message.id).await?;
-        }
-
-        mci.create_followup(
-            ctx,
-            serenity::CreateInteractionResponseFollowup::new()
-                .content(msg)
-                .ephemeral(true)
-                .components(components),
-        )
-        .await?;
-
-        {
-            update_bot_score(&ctx, &mut game.lock().unwrap());
-        };
         let g = { game.lock().unwrap().clone() };
-        update_parent_message(&ctx, &a, &g, now + (start_time + time_to_play as u64 - now)).await?;
+        update_table(ctx, &handle, &g, Some(deadline)).await?;
     }
 
-    while game.lock().unwrap().players_scores[bot_idx] < 18 {
-        update_bot_score(&ctx, &mut game.lock().unwrap());
-    }
-
-    let g = { game.lock().unwrap().clone() };
-    update_parent_message(&ctx, &a, &g, 0).await?;
-
-    let winners = game.lock().unwrap().get_winners();
-    let prize = match !winners.is_empty() {
-        true => game.lock().unwrap().pot / winners.len() as i32,
-        false => 0,
+    let g = {
+        let mut game = game.lock().unwrap();
+        game.stand_all();
+        game.play_dealer()?;
+        game.clone()
     };
-    ctx.data().db.award_balances(winners.clone(), prize).await?;
-    let losers = g
-        .players
-        .iter()
-        .filter(|x| !winners.contains(*x))
-        .clone()
-        .collect::<Vec<&u64>>();
-
-    let reply = {
-        CreateReply::default().content(format!(
-            "> ### The game is over!\n{}\n{}",
-            if !winners.is_empty() {
-                format!(
-                    "> The winners are: {}\n> Congrats on {} <:jbuck:1228663982462865450>!",
-                    winners.iter().fold(String::new(), |mut output, x| {
-                        let _ = write!(output, "<@{}> ", x);
-                        output
-                    }),
-                    prize
-                )
-            } else {
-                "> There were no winners.".to_string()
-            },
-            if !losers.is_empty() {
-                format!(
-                    "> The losers are: {} {}!",
-                    losers.iter().fold(String::new(), |mut output, x| {
-                        let _ = write!(output, "<@{}> ", x);
-                        output
-                    }),
-                    get_troll_emoji()
-                )
-            } else {
-                "".to_string()
+    update_table(ctx, &handle, &g, None).await?;
+
+    let payouts = g.payouts();
+    db.settle_escrow(&game_id, &payouts, memo()).await?;
+
+    let mut results = String::new();
+    let mut losers = vec![];
+    for (seat, (player, payout)) in g.seats.iter().zip(payouts.iter()) {
+        let net = payout - seat.staked();
+        let _ = match net {
+            0 => writeln!(results, "> <@{}> pushed", player),
+            net if net > 0 => writeln!(results, "> <@{}> won {} {}", player, net, JBUCK_EMOJI),
+            net => {
+                losers.push(*player);
+                writeln!(results, "> <@{}> lost {} {}", player, -net, JBUCK_EMOJI)
             }
-        ))
-    };
-
-    ctx.send(reply).await?;
-    ctx.serenity_context().shard.set_activity(None);
-    {
-        *ctx.data().blackjack_active.lock().unwrap() = false;
+        };
     }
+    let reply = CreateReply::default().content(format!(
+        "> ### The game is over!\n{}{}",
+        results,
+        if losers.is_empty() {
+            String::new()
+        } else {
+            format!("> Better luck next time {}", get_troll_emoji())
+        }
+    ));
+    ctx.send(reply).await?;
     Ok(())
 }
 
-fn update_bot_score(ctx: &Context<'_>, game: &mut Blackjack) {
-    let idx = game
-        .players
+fn lobby_message(deadline: u64) -> String {
+    format!(
+        "> ### :black_joker: It's Blackjack time, pick your bet to join!\n> **Cards are dealt <t:{}:R>**",
+        deadline
+    )
+}
+
+fn join_id(bet: i32) -> String {
+    format!("blackjack_join_{}", bet)
+}
+
+fn lobby_components(game: &Blackjack) -> Vec<serenity::CreateActionRow> {
+    let pot = game.seats.iter().map(|s| s.staked()).sum();
+    let bets = (1..=MAX_BET)
+        .map(|bet| {
+            serenity::CreateButton::new(join_id(bet))
+                .label(format!("Bet {}", bet))
+                .style(serenity::ButtonStyle::Primary)
+                .emoji(serenity::ReactionType::Unicode("🃏".to_string()))
+        })
+        .collect();
+    vec![
+        serenity::CreateActionRow::Buttons(bets),
+        serenity::CreateActionRow::Buttons(vec![
+            new_player_count_button(game.seats.len() as i32),
+            new_pot_counter_button(pot),
+        ]),
+    ]
+}
+
+fn cards(cards: &[Card]) -> String {
+    cards
         .iter()
-        .enumerate()
-        .find(|x| x.1 == &ctx.data().bot_id)
-        .unwrap()
-        .0;
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    let bot_score = game.players_scores[idx];
+fn hand_line(hand: &Hand) -> String {
+    let status = if hand.is_blackjack() {
+        " **blackjack!**"
+    } else if hand.is_bust() {
+        " bust"
+    } else if hand.doubled {
+        " doubled"
+    } else {
+        ""
+    };
+    format!("{} ({}){}", cards(&hand.cards), hand.total(), status)
+}
 
-    if bot_score >= 21 {
-        return;
+/// The table with the dealer's hole card face down until the round is over
+async fn update_table(
+    ctx: Context<'_>,
+    msg: &ReplyHandle<'_>,
+    game: &Blackjack,
+    deadline: Option<u64>,
+) -> Result<(), Error> {
+    let dealer = match deadline {
+        Some(_) => format!("{} 🂠", game.dealer[0]),
+        None => format!("{} ({})", cards(&game.dealer), game.dealer_total()),
+    };
+    let mut content = format!("> ### :black_joker: Blackjack\n> Dealer: {}\n", dealer);
+    for seat in game.seats.iter() {
+        let hands = seat
+            .hands
+            .iter()
+            .map(hand_line)
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let _ = writeln!(content, "> <@{}>: {}", seat.player, hands);
     }
+    let components = match deadline {
+        Some(deadline) => {
+            let _ = write!(content, "> **Game Ends <t:{}:R>**", deadline);
+            vec![serenity::CreateActionRow::Buttons(vec![
+                new_action_button("blackjack_hit", "Hit", serenity::ButtonStyle::Primary),
+                new_action_button("blackjack_stand", "Stand", serenity::ButtonStyle::Danger),
+                new_action_button("blackjack_double", "Double", serenity::ButtonStyle::Success),
+                new_action_button("blackjack_split", "Split", serenity::ButtonStyle::Secondary),
+            ])]
+        }
+        None => {
+            content.push_str("> **Game is over!**");
+            vec![]
+        }
+    };
+    let reply = CreateReply::default()
+        .content(content)
+        .components(components)
+        .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users());
+    msg.edit(ctx, reply).await?;
+    Ok(())
+}
 
-    if bot_score < 17 {
-        let one = ctx.data().rng.lock().unwrap().gen_range(1..=6);
-        let two = ctx.data().rng.lock().unwrap().gen_range(1..=6);
-        game.players_scores[idx] += one + two;
-    } else if bot_score < 19 || ctx.data().rng.lock().unwrap().gen_range(0..=1) == 1 {
-        let one = ctx.data().rng.lock().unwrap().gen_range(1..=6);
-        game.players_scores[idx] += one;
-    }
+fn new_action_button(
+    id: &str,
+    label: &str,
+    style: serenity::ButtonStyle,
+) -> serenity::CreateButton {
+    serenity::CreateButton::new(id).label(label).style(style)
 }
 
 pub(crate) fn new_player_count_button(amount: i32) -> serenity::CreateButton {
@@ -368,26 +366,6 @@ pub(crate) fn new_pot_counter_button(amount: i32) -> serenity::CreateButton {
         .style(poise::serenity_prelude::ButtonStyle::Success)
 }
 
-fn new_onedice_button() -> serenity::CreateButton {
-    serenity::CreateButton::new("onedice")
-        .label("Roll One Dice")
-        .style(poise::serenity_prelude::ButtonStyle::Secondary)
-        .emoji(serenity::ReactionType::Unicode("🎲".to_string()))
-}
-
-fn new_twodice_button() -> serenity::CreateButton {
-    serenity::CreateButton::new("twodice")
-        .label("Roll Two Dice")
-        .style(poise::serenity_prelude::ButtonStyle::Primary)
-        .emoji(serenity::ReactionType::Unicode("🎲".to_string()))
-}
-
-fn new_hold_button() -> serenity::CreateButton {
-    serenity::CreateButton::new("stand")
-        .label("Stand")
-        .style(poise::serenity_prelude::ButtonStyle::Danger)
-}
-
 pub fn get_troll_emoji() -> String {
     let emoji = [
         "<:dogeTroll:1160530414490886264>",
@@ -398,38 +376,3 @@ pub fn get_troll_emoji() -> String {
     .to_string();
     emoji
 }
-
-async fn update_parent_message(
-    ctx: &Context<'_>,
-    msg: &ReplyHandle<'_>,
-    game: &Blackjack,
-    deadline: u64,
-) -> Result<(), Error> {
-    let components = vec![serenity::CreateActionRow::Buttons(vec![
-        new_twodice_button(),
-        new_player_count_button(game.players.len() as i32),
-    ])];
-    let leaderboard_msg = game
-        .get_leaderboard()
-        .iter()
-        .map(|(id, score)| format!("> <@{}> has a score of {}", id, score))
-        .collect::<Vec<String>>()
-        .join("\n");
-    let reply = {
-        CreateReply::default()
-            .content(format!(
-                "> ### It's Blackjack time, roll the :game_die: to play!\n{}{}",
-                leaderboard_msg + "\n",
-                if deadline > 0 {
-                    format!("> **Game Ends <t:{}:R>**", deadline)
-                } else {
-                    "> **Game is over!**".to_string()
-                }
-            ))
-            .components(components)
-            .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users())
-    };
-
-    msg.edit(*ctx, reply).await?;
-    Ok(())
-}
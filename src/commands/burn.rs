@@ -28,10 +28,6 @@ pub async fn bury(
         ctx.send(reply).await?;
         return Err("Not enough money".into());
     }
-    ctx.data()
-        .db
-        .subtract_balances(vec![ctx.author().id.get()], amount)
-        .await?;
     ctx.data()
         .db
         .bury_balance(ctx.author().id.get(), amount)
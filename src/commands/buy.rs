@@ -1,14 +1,25 @@
 use crate::{
-    commands::{lottery::buylotteryticket, robbingevent::get_discord_name},
-    database::{BalanceDatabase, RoleDatabase, ShopDatabase},
+    chart::{price_chart, price_stats},
+    commands::{
+        auction,
+        item::{buyitem, shop_line},
+        lottery::buylotteryticket,
+        robbingevent::get_discord_name,
+    },
+    database::{
+        BalanceDatabase, BonesPriceDatabase, Database, EscrowDatabase, ItemDatabase,
+        MarketDatabase, Reason, RoleDatabase, ShopDatabase, TenureEnd,
+    },
     discord::JBUCK_EMOJI,
-    johnny::is_weekend,
-    Context, Error,
+    game,
+    johnny::{describe_bones_decay, is_weekend},
+    scheduler, Context, Error, RolePrice,
 };
 use base64::{engine::general_purpose, Engine as _};
 use chrono::{Datelike, Days, NaiveTime};
 use poise::CreateReply;
 use serenity::all::Emoji;
+use std::{collections::HashMap, sync::RwLock};
 
 ///
 /// List the items for sale in the shop
@@ -25,6 +36,15 @@ pub async fn shop(ctx: Context<'_>) -> Result<(), Error> {
             .get_unique_role_holder(ctx.data().crown_role_id)
             .await?
     };
+    let rents = ctx
+        .data()
+        .db
+        .get_purchasable_roles()
+        .await?
+        .iter()
+        .filter_map(|r| Some((r.role_id, r.rent()?)))
+        .collect::<std::collections::HashMap<_, _>>();
+    let items = ctx.data().db.get_items().await?;
     let reply = {
         let roles = { ctx.data().roles.read().unwrap().clone() };
         let mut a = ctx
@@ -48,10 +68,16 @@ pub async fn shop(ctx: Context<'_>) -> Result<(), Error> {
             .iter()
             .map(|(role_id, _)| {
                 format!(
-                    "> <@&{}> - {} {}{}{}",
+                    "> <@&{}> - {} {}{}{}{}",
                     role_id,
                     roles.get(role_id).unwrap().0,
                     JBUCK_EMOJI,
+                    match rents.get(&role_id.get()) {
+                        Some((price, days)) => {
+                            format!(" or rent for {} days: {} {}", days, price, JBUCK_EMOJI)
+                        }
+                        None => "".to_string(),
+                    },
                     if uniques.contains(role_id) {
                         if role_id.get() == ctx.data().crown_role_id {
                             if let Some(crown_holder) = &crown_holder {
@@ -81,16 +107,77 @@ pub async fn shop(ctx: Context<'_>) -> Result<(), Error> {
             ctx.data().config.read().unwrap().bones_price,
             JBUCK_EMOJI
         );
+        let formatted_items = match items.is_empty() {
+            true => "".to_string(),
+            false => format!(
+                "**Items:**\n{}\n\n",
+                items.iter().map(shop_line).collect::<Vec<_>>().join("\n")
+            ),
+        };
         let header = format!("### {} Shop {} ###\n\n", JBUCK_EMOJI, JBUCK_EMOJI);
-        let footer = String::from("\n\nMore info on roles at: https://canary.discord.com/channels/1128350000343167130/1227274968312844320\nTo buy a role use the **/buy role** command.");
+        let footer = String::from("\n\nMore info on roles at: https://canary.discord.com/channels/1128350000343167130/1227274968312844320\nTo buy a role use the **/buy role** command, to rent one use **/role rent**.\nTo buy an item use **/buy item**, see yours with **/inventory**.");
 
-        CreateReply::default().content(format!("{header}{formatted_bones_prices}{formatted_emoji_prices}{formatted_role_prices}{footer}")).ephemeral(true)
+        CreateReply::default().content(format!("{header}{formatted_bones_prices}{formatted_emoji_prices}{formatted_items}{formatted_role_prices}{footer}")).ephemeral(true)
     };
     ctx.send(reply).await?;
 
     Ok(())
 }
 
+///
+/// Let a role be rented for a number of days
+///
+/// Enter `/setrolerent [role] [price] [days]`, a price of 0 stops renting
+/// ```
+/// /setrolerent @VIP 50 7
+/// ```
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    default_member_permissions = "ADMINISTRATOR",
+    hide_in_help
+)]
+pub async fn setrolerent(
+    ctx: Context<'_>,
+    #[description = "The role to rent out"] role: poise::serenity_prelude::Role,
+    #[min = 0]
+    #[description = "The price of one rental"]
+    price: i32,
+    #[min = 1]
+    #[description = "How many days a rental lasts"]
+    days: i32,
+) -> Result<(), Error> {
+    if !ctx.data().roles.read().unwrap().contains_key(&role.id) {
+        let reply = CreateReply::default()
+            .content(format!("Set a price for {} first", role))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+    if ctx.data().unique_roles.lock().unwrap().contains(&role.id) {
+        let reply = CreateReply::default()
+            .content("Unique roles can't be rented")
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    ctx.data()
+        .db
+        .set_role_rent(role.id.get(), price, days)
+        .await?;
+    let content = match price {
+        0 => format!("{} can no longer be rented", role),
+        _ => format!(
+            "{} can be rented for {} days for {} {}",
+            role, days, price, JBUCK_EMOJI
+        ),
+    };
+    let reply = CreateReply::default().content(content).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
 ///
 /// Set the price for a role
 ///
@@ -178,9 +265,60 @@ pub async fn setroleprice(
 
 pub async fn incrementroleprice(ctx: Context<'_>, role_id: String) -> Result<(), Error> {
     ctx.data().db.increment_role_price(role_id).await?;
-    let prices = ctx.data().db.get_purchasable_roles().await?;
+    reload_role_prices(&ctx.data().db, &ctx.data().roles).await
+}
+
+/// Takes a unique role off whoever holds it and records `user_id` as the new holder,
+/// keeping crown time right. The new holder is given the role by the caller.
+#[allow(clippy::too_many_arguments)]
+pub async fn take_unique_role(
+    http: &poise::serenity_prelude::Http,
+    db: &Database,
+    guild_id: poise::serenity_prelude::GuildId,
+    role_id: poise::serenity_prelude::RoleId,
+    user_id: poise::serenity_prelude::UserId,
+    price: i32,
+    crown_role_id: u64,
+    how: TenureEnd,
+) -> Result<(), Error> {
+    let is_crown = role_id.get() == crown_role_id;
+    if is_crown {
+        // close out the old holder's time before the crown changes hands
+        db.accrue_crown_time(role_id.get(), chrono::Utc::now())
+            .await?;
+    }
+    if let Some(holder) = db.get_unique_role_holder(role_id.get()).await? {
+        if holder.user_id != user_id.get() {
+            let reason = match how {
+                TenureEnd::Auction => format!("{} won it at auction", user_id),
+                _ => format!("{} bought it", user_id),
+            };
+            http.remove_member_role(
+                guild_id,
+                poise::serenity_prelude::UserId::new(holder.user_id),
+                role_id,
+                Some(reason.as_str()),
+            )
+            .await?;
+        }
+    };
+    db.set_unique_role_holder(role_id.get(), user_id.get(), price, how)
+        .await?;
+
+    if is_crown {
+        db.update_crown_timer(user_id.get(), 0.0).await?;
+    }
+    Ok(())
+}
+
+/// Refreshes the shop's prices after they changed in the database
+pub async fn reload_role_prices(
+    db: &Database,
+    roles: &RwLock<HashMap<poise::serenity_prelude::RoleId, RolePrice>>,
+) -> Result<(), Error> {
+    let prices = db.get_purchasable_roles().await?;
     {
-        let mut roles = ctx.data().roles.write().unwrap();
+        let mut roles = roles.write().unwrap();
         for price in prices {
             roles.insert(
                 poise::serenity_prelude::RoleId::new(price.role_id),
@@ -205,7 +343,7 @@ pub async fn incrementroleprice(ctx: Context<'_>, role_id: String) -> Result<(),
 /// ```
 #[poise::command(
     slash_command,
-    subcommands("role", "emoji", "bones", "buylotteryticket"),
+    subcommands("role", "emoji", "bones", "buylotteryticket", "buyitem"),
     subcommand_required
 )]
 pub async fn buy(_: Context<'_>) -> Result<(), Error> {
@@ -303,11 +441,19 @@ pub async fn bones(
 
     ctx.data()
         .db
-        .subtract_balances(vec![ctx.author().id.get()], price * amount)
+        .subtract_balances(
+            vec![ctx.author().id.get()],
+            price * amount,
+            Reason::BonesPurchase.with_id(ctx.id()),
+        )
         .await?;
     ctx.data()
         .db
-        .add_bones(ctx.author().id.get(), amount)
+        .add_bones(
+            ctx.author().id.get(),
+            amount,
+            Reason::BonesPurchase.with_id(ctx.id()),
+        )
         .await?;
     let reply = {
         CreateReply::default()
@@ -352,11 +498,19 @@ pub async fn sellbones(
 
     ctx.data()
         .db
-        .award_balances(vec![ctx.author().id.get()], price * amount)
+        .award_balances(
+            vec![ctx.author().id.get()],
+            price * amount,
+            Reason::BonesSale.with_id(ctx.id()),
+        )
         .await?;
     ctx.data()
         .db
-        .remove_bones(ctx.author().id.get(), amount)
+        .remove_bones(
+            ctx.author().id.get(),
+            amount,
+            Reason::BonesSale.with_id(ctx.id()),
+        )
         .await?;
     let reply = {
         CreateReply::default()
@@ -372,6 +526,93 @@ pub async fn sellbones(
     Ok(())
 }
 
+///
+/// Sell a role back to the shop
+///
+/// Enter `/sell role <role>`, you get back part of what you paid
+/// ```
+/// /sell role @role
+/// ```
+#[poise::command(slash_command, rename = "role")]
+pub async fn sellrole(
+    ctx: Context<'_>,
+    #[description = "The role to sell"] role: poise::serenity_prelude::Role,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id.get();
+    let guild_id = ctx.guild_id().ok_or("Roles can only be sold in a server")?;
+    let refuse = |content: &str| CreateReply::default().content(content).ephemeral(true);
+
+    if !ctx.author().has_role(ctx, guild_id, role.id).await? {
+        ctx.send(refuse("You don't have that role!")).await?;
+        return Ok(());
+    }
+    if ctx
+        .data()
+        .db
+        .get_rental(role.id.get(), user_id)
+        .await?
+        .is_some()
+    {
+        ctx.send(refuse("You're renting that role, it can't be sold!"))
+            .await?;
+        return Ok(());
+    }
+    let paid = match ctx
+        .data()
+        .db
+        .get_role_tenure(role.id.get(), user_id)
+        .await?
+    {
+        Some(tenure) => tenure.price.unwrap_or(0),
+        None => {
+            ctx.send(refuse("There's no record of you buying that role!"))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if role.id.get() == ctx.data().crown_role_id {
+        ctx.data()
+            .db
+            .accrue_crown_time(role.id.get(), chrono::Utc::now())
+            .await?;
+    }
+    // the role comes off first so a failure here leaves the sale unrecorded
+    ctx.serenity_context()
+        .http
+        .remove_member_role(guild_id, ctx.author().id, role.id, Some("Sold the role"))
+        .await?;
+
+    let percent = ctx.data().config.read().unwrap().role_refund_percent;
+    let refund = paid * percent / 100;
+    if !ctx
+        .data()
+        .db
+        .sell_role(
+            role.id.get(),
+            user_id,
+            refund,
+            Reason::RoleSale.with_id(role.id),
+        )
+        .await?
+    {
+        ctx.send(refuse("There's no record of you buying that role!"))
+            .await?;
+        return Ok(());
+    }
+    reload_role_prices(&ctx.data().db, &ctx.data().roles).await?;
+
+    let reply = CreateReply::default().content(format!(
+        "{} sold {} for {} {}!",
+        ctx.author(),
+        role,
+        refund,
+        JBUCK_EMOJI
+    ));
+    ctx.send(reply).await?;
+    Ok(())
+}
+
 ///
 /// Buy an emoji for the server
 ///
@@ -438,7 +679,11 @@ pub async fn emoji(
 
     ctx.data()
         .db
-        .subtract_balances(vec![ctx.author().id.get()], price)
+        .subtract_balances(
+            vec![ctx.author().id.get()],
+            price,
+            Reason::EmojiPurchase.with_id(ctx.id()),
+        )
         .await?;
 
     let emoji = ctx.data().db.get_oldest_community_emoji().await.unwrap();
@@ -488,14 +733,17 @@ pub async fn emoji(
             tracing::debug!("{e}");
             ctx.data()
                 .db
-                .award_balances(vec![ctx.author().id.into()], price)
+                .award_balances(
+                    vec![ctx.author().id.into()],
+                    price,
+                    Reason::EmojiRefund.with_id(ctx.id()),
+                )
                 .await?;
             let reply = {
                 CreateReply::default()
                     .content("There was an error creating the emoji!")
                     .ephemeral(true)
             };
-            // TODO: refund
             ctx.send(reply).await?;
         }
     };
@@ -525,6 +773,15 @@ pub async fn role(
         ctx.send(reply).await?;
         return Err("Role not for sale".into());
     }
+    if auction::is_auctioned(&ctx.data().db, role.id.get()).await? {
+        let reply = {
+            CreateReply::default()
+                .content("That role is up for auction, bid on it instead!")
+                .ephemeral(true)
+        };
+        ctx.send(reply).await?;
+        return Err("Role is being auctioned".into());
+    }
 
     // check if user has the role already
     if ctx
@@ -590,35 +847,29 @@ pub async fn role(
 
     ctx.data()
         .db
-        .subtract_balances(vec![ctx.author().id.get()], price.0)
+        .subtract_balances(
+            vec![ctx.author().id.get()],
+            price.0,
+            Reason::RolePurchase.with_id(role.id),
+        )
         .await?;
 
     if ctx.data().unique_roles.lock().unwrap().contains(&role.id) {
-        if let Some(user) = ctx.data().db.get_unique_role_holder(role.id.into()).await? {
-            let now = chrono::Utc::now();
-            let bought = user.purchased;
-            let time_since_purchase = now - bought;
-            ctx.serenity_context()
-                .http
-                .remove_member_role(
-                    ctx.guild_id().unwrap(),
-                    poise::serenity_prelude::UserId::new(user.user_id),
-                    role.id,
-                    Some(format!("{} bought it", ctx.author().id).as_str()),
-                )
-                .await?;
-
-            let v: f32 = time_since_purchase.num_minutes() as f32 / 60.0;
-            ctx.data().db.update_crown_timer(user.user_id, v).await?;
-        };
-        ctx.data()
-            .db
-            .set_unique_role_holder(role.id.into(), ctx.author().id.into())
-            .await?;
-
+        take_unique_role(
+            &ctx.serenity_context().http,
+            &ctx.data().db,
+            ctx.guild_id().unwrap(),
+            role.id,
+            ctx.author().id,
+            price.0,
+            ctx.data().crown_role_id,
+            TenureEnd::Bought,
+        )
+        .await?;
+    } else {
         ctx.data()
             .db
-            .update_crown_timer(ctx.author().id.into(), 0.0)
+            .record_role_purchase(role.id.into(), ctx.author().id.into(), price.0)
             .await?;
     }
 
@@ -789,15 +1040,151 @@ pub async fn list_prices(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+///
+/// the bones market
+///
+#[poise::command(
+    slash_command,
+    rename = "bones",
+    subcommands("bones_status", "predict"),
+    subcommand_required
+)]
+pub async fn bonesmarket(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+        }
+    }
+
+    /// The escrow the stakes on this direction are held under until the next price
+    pub fn game_id(&self) -> &'static str {
+        match self {
+            Direction::Up => "bones-prediction-up",
+            Direction::Down => "bones-prediction-down",
+        }
+    }
+
+    fn opposite(&self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+        }
+    }
+}
+
+///
+/// bet on which way the bones price moves next
+///
+/// Enter `/bones predict <up|down> <amount>`, your bucks are held until the next price is set.
+/// The winners split what the losers staked, less a cut for the crown. Betting closes half an
+/// hour before the price is set.
+/// ```
+/// /bones predict up 100
+/// ```
+#[poise::command(slash_command)]
+pub async fn predict(
+    ctx: Context<'_>,
+    #[description = "Whether the next price is higher or lower"] direction: Direction,
+    #[min = 1]
+    #[description = "How many bucks to stake"]
+    amount: i32,
+) -> Result<(), Error> {
+    let db = &ctx.data().db;
+    let user_id = ctx.author().id.get();
+    // the dev schedule sets a price every few minutes, a minute is enough there
+    let dev_env = std::env::var("DEV_SETTINGS").is_ok();
+    let cutoff = if dev_env {
+        chrono::Duration::minutes(1)
+    } else {
+        chrono::Duration::minutes(game::PREDICTION_CUTOFF_MINUTES)
+    };
+    let now = chrono::Utc::now();
+    if let Some(next) = scheduler::next_run(&scheduler::Job::BonesPrice.schedule(dev_env), now) {
+        if next - now < cutoff {
+            let reply = CreateReply::default()
+                .content(format!(
+                    "Betting on this price is closed, the next one is set <t:{}:R>",
+                    next.timestamp()
+                ))
+                .ephemeral(true);
+            ctx.send(reply).await?;
+            return Ok(());
+        }
+    }
+    let hedged = db
+        .get_stakes(direction.opposite().game_id())
+        .await?
+        .iter()
+        .any(|(user, _)| *user == user_id);
+    if hedged {
+        let reply = CreateReply::default()
+            .content(format!(
+                "You already predicted the price goes {}",
+                direction.opposite().as_str()
+            ))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+    if db.get_balance(user_id).await? < amount {
+        let reply = CreateReply::default()
+            .content("You don't have enough bucks")
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+    db.escrow_stake(
+        user_id,
+        amount,
+        Reason::BonesPrediction.with_id(direction.game_id()),
+    )
+    .await?;
+
+    let total = |stakes: Vec<(u64, i32)>| stakes.iter().map(|(_, s)| s).sum::<i32>();
+    let up = total(db.get_stakes(Direction::Up.game_id()).await?);
+    let down = total(db.get_stakes(Direction::Down.game_id()).await?);
+    let content = format!(
+        "> {} staked {} {} on the bones price going **{}**\n> Riding on up: {} {}, on down: {} {}",
+        ctx.author(),
+        amount,
+        JBUCK_EMOJI,
+        direction.as_str(),
+        up,
+        JBUCK_EMOJI,
+        down,
+        JBUCK_EMOJI
+    );
+    ctx.send(CreateReply::default().content(content)).await?;
+    Ok(())
+}
+
 ///
 /// Get the status of the bones market
 ///
-/// Enter `/bones`
+/// Enter `/bones status [days]` to see the price and a chart of how it moved
 /// ```
-/// /bones
+/// /bones status
+/// /bones status 30
 /// ```
-#[poise::command(slash_command, rename = "bones")]
-pub async fn bones_status(ctx: Context<'_>) -> Result<(), Error> {
+#[poise::command(slash_command, rename = "status")]
+pub async fn bones_status(
+    ctx: Context<'_>,
+    #[min = 1]
+    #[max = 90]
+    #[description = "How many days of prices to chart, 7 by default"]
+    days: Option<i64>,
+) -> Result<(), Error> {
     match ctx.defer().await {
         Ok(_) => {}
         Err(e) => {
@@ -827,23 +1214,35 @@ pub async fn bones_status(ctx: Context<'_>) -> Result<(), Error> {
             format!("> Buying time ends: <t:{}:R>", deadline.timestamp())
         }
         false => {
-            // deadline is Saturday 00:00 UTC
-            let mut deadline = chrono::Utc::now();
-            while deadline.weekday() != chrono::Weekday::Sat {
-                tracing::debug!("{}", deadline.weekday());
-                deadline = deadline.checked_add_days(Days::new(1)).unwrap();
+            let (schedule, percent, min_age_days) = {
+                let config = ctx.data().config.read().unwrap();
+                (
+                    config.bones_decay_schedule.clone(),
+                    config.bones_decay_percent,
+                    config.bones_decay_min_age_days,
+                )
+            };
+            let what = describe_bones_decay(percent, min_age_days);
+            match scheduler::next_draw(&schedule, chrono::Utc::now()) {
+                Some(deadline) => format!("> Next decay: {} <t:{}:R>", what, deadline.timestamp()),
+                None => format!("> Next decay: {} never", what),
             }
-            deadline = deadline
-                .with_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-                .unwrap();
-            format!("> Bones expire: <t:{}:R>", deadline.timestamp())
         }
     };
     let footer = match is_weekend() {
         true => "> Buy more via** /buy bones** command!",
         false => "> Sell your stock via** /sell bones** command!",
     };
-    let formatted_price = format!("> Price: **{}** {}", price, JBUCK_EMOJI);
+    let footer = format!(
+        "{footer}\n> Trade with other players any day via** /market**!\n> Bet on the next price via** /bones predict**!"
+    );
+    let formatted_price = match ctx.data().db.get_last_trade_price().await? {
+        Some(last) => format!(
+            "> Price: **{}** {} (last traded between players at **{}** {})",
+            price, JBUCK_EMOJI, last, JBUCK_EMOJI
+        ),
+        None => format!("> Price: **{}** {}", price, JBUCK_EMOJI),
+    };
 
     let lb = ctx.data().db.get_bones_leaderboard().await?;
     let named_players = {
@@ -865,10 +1264,34 @@ pub async fn bones_status(ctx: Context<'_>) -> Result<(), Error> {
         bones_leaderboard = "> Nobody has any :bone: yet!".to_string();
     }
     let formatted_bones_leaderboard = format!("> Bone Holders:\n{}", bones_leaderboard.trim_end());
+
+    let days = days.unwrap_or(7);
+    let now = chrono::Utc::now();
+    let from = now - chrono::Duration::days(days);
+    let mut prices = ctx.data().db.get_bones_prices(from).await?;
+    if prices.is_empty() {
+        prices.push((from, price));
+    }
+    let formatted_history = match price_stats(&prices) {
+        Some(stats) => format!(
+            "> Last {} days: low **{}**, high **{}**, average **{:.1}** {}",
+            days, stats.min, stats.max, stats.average, JBUCK_EMOJI
+        ),
+        None => String::new(),
+    };
     let message = format!(
-        "> **BONE MARKET**\n{status}\n{formatted_price}\n{deadline}\n{formatted_bones_leaderboard}\n{footer}"
+        "> **BONE MARKET**\n{status}\n{formatted_price}\n{formatted_history}\n{deadline}\n{formatted_bones_leaderboard}\n{footer}"
     );
-    let reply = { CreateReply::default().content(message).reply(true) };
+    let mut reply = CreateReply::default().content(message).reply(true);
+    match price_chart(&prices, from, now) {
+        Ok(png) => {
+            reply = reply.attachment(poise::serenity_prelude::CreateAttachment::bytes(
+                png,
+                "bones.png",
+            ))
+        }
+        Err(e) => tracing::error!("Error drawing the bones chart: {e}"),
+    }
     ctx.send(reply).await?;
     Ok(())
 }
@@ -880,7 +1303,11 @@ pub async fn bones_status(ctx: Context<'_>) -> Result<(), Error> {
 /// ```
 /// /sell bones 1
 /// ```
-#[poise::command(slash_command, subcommands("sellbones"), subcommand_required)]
+#[poise::command(
+    slash_command,
+    subcommands("sellbones", "sellrole"),
+    subcommand_required
+)]
 pub async fn sell(_: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
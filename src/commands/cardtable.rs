@@ -0,0 +1,36 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Context, Error};
+use poise::serenity_prelude as serenity;
+
+/// How long until the deadline, nothing once it has passed
+pub(crate) fn time_left(deadline: u64) -> Result<std::time::Duration, Error> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(std::time::Duration::from_secs(deadline.saturating_sub(now)))
+}
+
+/// Players being robbed can't bet their balance away until the robbery is over
+pub(crate) fn robbery_refusal(ctx: Context<'_>, player: u64) -> Option<String> {
+    if ctx.data().locked_balances.lock().unwrap().contains(&player) {
+        return Some("Nice try, but you can't do that while the robbing event is happening. You can play again after.".to_string());
+    }
+    None
+}
+
+/// Answers a button press with a message only the player sees
+pub(crate) async fn ephemeral_response(
+    ctx: Context<'_>,
+    mci: &serenity::ComponentInteraction,
+    content: String,
+) -> Result<(), Error> {
+    mci.create_response(
+        ctx,
+        serenity::CreateInteractionResponse::Message(
+            serenity::CreateInteractionResponseMessage::new()
+                .content(content)
+                .ephemeral(true),
+        ),
+    )
+    .await?;
+    Ok(())
+}
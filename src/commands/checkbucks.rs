@@ -1,4 +1,5 @@
-use crate::database::BalanceDatabase;
+use crate::commands::balance::show_history;
+use crate::database::{BalanceDatabase, Currency};
 use crate::{Context, Error};
 use poise::serenity_prelude as serenity;
 use poise::CreateReply;
@@ -9,6 +10,7 @@ use poise::CreateReply;
 /// Enter `/checkbucks @Name` to check
 /// ```
 /// /checkbucks @John
+/// /checkbucks @John True
 /// ```
 ///
 #[poise::command(
@@ -20,7 +22,12 @@ use poise::CreateReply;
 pub async fn checkbucks(
     ctx: Context<'_>,
     #[description = "Who to check"] user: serenity::User,
+    #[description = "Show their transaction history"] history: Option<bool>,
+    #[description = "Only show one currency"] currency: Option<Currency>,
 ) -> Result<(), Error> {
+    if history.unwrap_or(false) {
+        return show_history(ctx, &user, currency).await;
+    }
     let response = match user.bot {
         true => 0,
         false => ctx.data().db.get_balance(user.id.get()).await?,
@@ -4,10 +4,11 @@ use std::{
 };
 
 use crate::{
-    database::BalanceDatabase,
+    database::{BalanceDatabase, EscrowDatabase, Reason},
     discord::{
         DOGE_CRY_EMOJI, DOGE_PRAY_EMOJI, DOGE_TROLL_EMOJI_1, DOGE_TROLL_EMOJI_2, JBUCK_EMOJI,
     },
+    fairness,
     game::{CoinGame, CoinSides, GameError},
     texts::landedside::LANDEDSIDE,
     Context, Error,
@@ -70,11 +71,43 @@ pub async fn coingamble(
         ctx.send(reply).await?;
         return Err("can't afford to do that".into());
     }
-    db.subtract_balances(vec![game_starter.parse().unwrap()], amount)
-        .await?;
+    db.escrow_stake(
+        game_starter.parse().unwrap(),
+        amount,
+        Reason::CoinGamble.with_id(ctx.id()),
+    )
+    .await?;
+
+    let game_id = ctx.id().to_string();
+    let result = play(ctx, amount, choice, game_length).await;
+    if result.is_err() {
+        // whatever broke the game, the stakes it still holds go back
+        db.refund_escrow(&game_id).await?;
+    }
+    result
+}
 
+async fn play(
+    ctx: Context<'_>,
+    amount: i32,
+    choice: HeadsOrTail,
+    game_length: i32,
+) -> Result<(), Error> {
+    let db = &ctx.data().db;
     ctx.data().config.write().unwrap().bot_odds_game_counter += 1;
 
+    let game_id = ctx.id().to_string();
+    let (seed, commitment) = fairness::commit_new_seed(db, &game_id, "coingamble").await?;
+    let mut coingame = CoinGame::new(
+        game_id,
+        seed,
+        ctx.author().id.get(),
+        choice.clone(),
+        amount,
+        ctx.data().config.read().unwrap().side_chance,
+        ctx.data().config.read().unwrap().bot_odds,
+    );
+
     let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
     let time_to_play = game_length;
     let pot = amount;
@@ -87,25 +120,21 @@ pub async fn coingamble(
     let reply = {
         CreateReply::default()
             .content(format!(
-                "> ### {} HEADS OR TAILS?\n> **Bet {} {} **on the correct answer!\n> **Game Ends: **<t:{}:R>",
+                "> ### {} HEADS OR TAILS?\n> **Bet {} {} **on the correct answer!\n> **Game Ends: **<t:{}:R>\n> -# Commitment `{}`",
                 JBUCK_EMOJI,
                 amount,
                 JBUCK_EMOJI,
-                now + time_to_play as u64
+                now + time_to_play as u64,
+                commitment
             ))
             .components(components.clone())
     };
 
     let a = ctx.send(reply).await?;
-    let id = a.message().await?.id;
-
-    let mut coingame = CoinGame::new(
-        ctx.author().id.get(),
-        choice.clone(),
-        amount,
-        ctx.data().config.read().unwrap().side_chance,
-        ctx.data().config.read().unwrap().bot_odds,
-    );
+    let message = a.message().await?;
+    let id = message.id;
+    db.link_transactions(&ctx.id().to_string(), &message.link())
+        .await?;
 
     while let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
         .channel_id(ctx.channel_id())
@@ -330,6 +359,24 @@ pub async fn coingamble(
         }
     };
 
+    let msg = if coin_flip_result.lucky.is_empty() {
+        msg
+    } else {
+        let lucky = coin_flip_result
+            .lucky
+            .iter()
+            .map(|u| format!("<@{}>", u))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "{}\n> :four_leaf_clover: Lucky charms used by {}",
+            msg, lucky
+        )
+    };
+    let msg = format!(
+        "{}\n> -# Seed `{}`, check it with `/verify {}`",
+        msg, coingame.seed, coingame.id
+    );
     let message = {
         CreateReply::default()
             .content(msg)
@@ -2,7 +2,8 @@ use poise::CreateReply;
 
 use crate::{
     database::{self, ConfigDatabase},
-    Context, Error,
+    pricing::PriceModelKind,
+    scheduler, Context, Error,
 };
 
 #[derive(Debug, poise::ChoiceParameter, Clone)]
@@ -12,21 +13,26 @@ pub enum ConfigOption {
     BotOddsGameLimit,
     GameLengthSeconds,
     RobberyLengthSeconds,
-    LotteryTicketPrice,
-    LotteryBasePrize,
-    FutureLotteryTicketPrice,
-    FutureLotteryBasePrize,
     SideChance,
     CommunityEmojiPrice,
     BonesPriceMinFluctuation,
     BonesPriceMaxFluctuation,
     ForceBonesPriceUpdate,
-    LotteryWinner,
     ForceEgg,
     GhostChannelId,
     GhostChannelLength,
     GhostChannelOdds,
     VoiceChannelCelebrationAmount,
+    RoleRefundPercent,
+    BonesPriceModel,
+    BonesPriceTarget,
+    BonesPricePullPercent,
+    BonesDemandImpactPercent,
+    BonesPriceNoise,
+    BonesDemandMaxChange,
+    BonesDecaySchedule,
+    BonesDecayPercent,
+    BonesDecayMinAgeDays,
 }
 
 ///
@@ -92,49 +98,6 @@ pub async fn set(ctx: Context<'_>, option: ConfigOption, value: String) -> Resul
                 .unwrap();
             ctx.data().config.write().unwrap().bot_odds = odds;
         }
-        ConfigOption::LotteryTicketPrice => {
-            let price = parse_value::<i32>(&value)?;
-            ctx.data()
-                .db
-                .set_config_value(database::ConfigKey::LotteryTicketPrice, value.as_str())
-                .await
-                .unwrap();
-            ctx.data().config.write().unwrap().lottery_ticket_price = price;
-        }
-        ConfigOption::LotteryBasePrize => {
-            let prize = parse_value::<i32>(&value)?;
-            ctx.data()
-                .db
-                .set_config_value(database::ConfigKey::LotteryBasePrize, value.as_str())
-                .await
-                .unwrap();
-            ctx.data().config.write().unwrap().lottery_base_prize = prize;
-        }
-        ConfigOption::FutureLotteryTicketPrice => {
-            let price = parse_value::<i32>(&value)?;
-            ctx.data()
-                .db
-                .set_config_value(
-                    database::ConfigKey::FutureLotteryTicketPrice,
-                    value.as_str(),
-                )
-                .await
-                .unwrap();
-            ctx.data()
-                .config
-                .write()
-                .unwrap()
-                .future_lottery_ticket_price = price;
-        }
-        ConfigOption::FutureLotteryBasePrize => {
-            let prize = parse_value::<i32>(&value)?;
-            ctx.data()
-                .db
-                .set_config_value(database::ConfigKey::FutureLotteryBasePrize, value.as_str())
-                .await
-                .unwrap();
-            ctx.data().config.write().unwrap().future_lottery_base_prize = prize;
-        }
         ConfigOption::SideChance => {
             let chance = parse_value::<u32>(&value)?;
             if !(0..=100).contains(&chance) {
@@ -192,17 +155,6 @@ pub async fn set(ctx: Context<'_>, option: ConfigOption, value: String) -> Resul
                 .unwrap();
             ctx.data().config.write().unwrap().bot_odds_game_limit = limit;
         }
-        ConfigOption::LotteryWinner => {
-            let winner = value
-                .parse::<u64>()
-                .map_err(|_| Error::from("Invalid value".to_string()))?;
-            ctx.data()
-                .db
-                .set_config_value(database::ConfigKey::LotteryWinner, value.as_str())
-                .await
-                .unwrap();
-            ctx.data().config.write().unwrap().lottery_winner = Some(winner);
-        }
         ConfigOption::ForceEgg => {
             tracing::info!("force egg");
             let force = parse_value::<bool>(&value)?;
@@ -265,6 +217,126 @@ pub async fn set(ctx: Context<'_>, option: ConfigOption, value: String) -> Resul
                 .unwrap()
                 .voice_channel_celebration_amount = amount;
         }
+        ConfigOption::RoleRefundPercent => {
+            let percent = parse_value::<i32>(&value)?;
+            if !(0..=100).contains(&percent) {
+                return Err(Error::from("Percent must be in range 0..=100".to_string()));
+            }
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::RoleRefundPercent, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().role_refund_percent = percent;
+        }
+        ConfigOption::BonesPriceModel => {
+            let model = value.parse::<PriceModelKind>()?;
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesPriceModel, model.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_price_model = model;
+        }
+        ConfigOption::BonesPriceTarget => {
+            let target = parse_value::<i32>(&value)?;
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesPriceTarget, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_price_target = target;
+        }
+        ConfigOption::BonesPricePullPercent => {
+            let percent = parse_value::<i32>(&value)?;
+            if !(0..=100).contains(&percent) {
+                return Err(Error::from("Percent must be in range 0..=100".to_string()));
+            }
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesPricePullPercent, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_price_pull_percent = percent;
+        }
+        ConfigOption::BonesDemandImpactPercent => {
+            let percent = parse_value::<i32>(&value)?;
+            ctx.data()
+                .db
+                .set_config_value(
+                    database::ConfigKey::BonesDemandImpactPercent,
+                    value.as_str(),
+                )
+                .await
+                .unwrap();
+            ctx.data()
+                .config
+                .write()
+                .unwrap()
+                .bones_demand_impact_percent = percent;
+        }
+        ConfigOption::BonesPriceNoise => {
+            let noise = parse_value::<i32>(&value)?;
+            if noise < 0 {
+                return Err(Error::from("Noise can't be negative".to_string()));
+            }
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesPriceNoise, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_price_noise = noise;
+        }
+        ConfigOption::BonesDemandMaxChange => {
+            let change = parse_value::<i32>(&value)?;
+            if change < 0 {
+                return Err(Error::from("Max change can't be negative".to_string()));
+            }
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesDemandMaxChange, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_demand_max_change = change;
+        }
+        ConfigOption::BonesDecaySchedule => {
+            if scheduler::next_draw(&value, chrono::Utc::now()).is_none() {
+                return Err(Error::from(
+                    "Schedule must be a cron expression with seconds, e.g. `0 0 0 * * Sat`"
+                        .to_string(),
+                ));
+            }
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesDecaySchedule, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_decay_schedule = value;
+        }
+        ConfigOption::BonesDecayPercent => {
+            let percent = parse_value::<i32>(&value)?;
+            if !(1..=100).contains(&percent) {
+                return Err(Error::from("Percent must be in range 1..=100".to_string()));
+            }
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesDecayPercent, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_decay_percent = percent;
+        }
+        ConfigOption::BonesDecayMinAgeDays => {
+            let days = parse_value::<i32>(&value)?;
+            if days < 0 {
+                return Err(Error::from("Days can't be negative".to_string()));
+            }
+            ctx.data()
+                .db
+                .set_config_value(database::ConfigKey::BonesDecayMinAgeDays, value.as_str())
+                .await
+                .unwrap();
+            ctx.data().config.write().unwrap().bones_decay_min_age_days = days;
+        }
     }
     let reply = CreateReply::default().content("Success").ephemeral(true);
     ctx.send(reply).await?;
@@ -1,4 +1,7 @@
-use crate::{database::BalanceDatabase, database::RoleDatabase, Context, Error};
+use crate::{
+    database::BalanceDatabase, database::EffectDatabase, database::Reason, database::RoleDatabase,
+    Context, Error,
+};
 use poise::CreateReply;
 use rand::Rng;
 
@@ -128,10 +131,17 @@ pub async fn daily(ctx: Context<'_>) -> Result<(), Error> {
         v
     };
 
-    ctx.data()
+    let total = amount + interest + n + crown_interest;
+    let doubled = if ctx
+        .data()
         .db
-        .award_balances(vec![user_id], amount + interest + n + crown_interest)
-        .await?;
+        .award_daily(user_id, total, Reason::Daily.into())
+        .await?
+    {
+        total
+    } else {
+        0
+    };
     ctx.data().db.did_daily(user_id).await?;
     ctx.data()
         .active_checks
@@ -140,7 +150,7 @@ pub async fn daily(ctx: Context<'_>) -> Result<(), Error> {
         .remove(&(ctx.author().id.get()));
     let reply = {
         let msg = format!(
-            "You got **{}** <:jbuck:1228663982462865450>!{}{}{}",
+            "You got **{}** <:jbuck:1228663982462865450>!{}{}{}{}",
             amount,
             if interest > 0 {
                 format!("\n**+{}** <:jbuck:1228663982462865450> interest!", interest)
@@ -159,6 +169,14 @@ pub async fn daily(ctx: Context<'_>) -> Result<(), Error> {
                 )
             } else {
                 "".to_string()
+            },
+            if doubled > 0 {
+                format!(
+                    "\n+**{}** <:jbuck:1228663982462865450> double daily token!",
+                    doubled
+                )
+            } else {
+                "".to_string()
             }
         );
         CreateReply::default().content(msg)
@@ -1,4 +1,4 @@
-use crate::database::BalanceDatabase;
+use crate::database::{BalanceDatabase, Reason};
 use crate::{Context, Error};
 use poise::serenity_prelude::User;
 use poise::CreateReply;
@@ -40,7 +40,7 @@ pub async fn fine(
     }
     ctx.data()
         .db
-        .subtract_balances(vec![user_id.parse().unwrap()], amount)
+        .subtract_balances(vec![user_id.parse().unwrap()], amount, Reason::Fine.into())
         .await?;
 
     let msg = match reason {
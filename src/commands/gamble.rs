@@ -2,7 +2,8 @@ pub(crate) use crate::commands::coingamble::new_pot_counter_button;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
-    commands::coingamble::new_player_count_button, database::BalanceDatabase, Context, Error,
+    commands::coingamble::new_player_count_button, database::BalanceDatabase,
+    database::EscrowDatabase, database::Reason, Context, Error,
 };
 use poise::{serenity_prelude as serenity, CreateReply};
 ///
@@ -41,8 +42,12 @@ pub async fn gamble(
         ctx.send(reply).await?;
         return Err("You can't afford to do that".into());
     }
-    db.subtract_balances(vec![game_starter.parse().unwrap()], amount)
-        .await?;
+    db.escrow_stake(
+        game_starter.parse().unwrap(),
+        amount,
+        Reason::Gamble.with_id(ctx.id()),
+    )
+    .await?;
 
     let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
     let time_to_play = game_length;
@@ -64,7 +69,10 @@ pub async fn gamble(
     };
 
     let a = ctx.send(reply).await?;
-    let id = a.message().await?.id;
+    let message = a.message().await?;
+    let id = message.id;
+    db.link_transactions(&ctx.id().to_string(), &message.link())
+        .await?;
     {
         let mut games = ctx.data().games.lock().unwrap();
         games.insert(
@@ -118,8 +126,12 @@ pub async fn gamble(
             .await?;
             continue;
         }
-        db.subtract_balances(vec![player.parse().unwrap()], amount)
-            .await?;
+        db.escrow_stake(
+            player.parse().unwrap(),
+            amount,
+            Reason::Gamble.with_id(ctx.id()),
+        )
+        .await?;
 
         let button2;
         let button3;
@@ -156,7 +168,12 @@ pub async fn gamble(
 
     // let winner_id = winner.parse().unwrap();
 
-    db.award_balances(vec![winner], prize).await?;
+    db.settle_escrow(
+        &ctx.id().to_string(),
+        &[(winner, prize)],
+        Reason::Gamble.with_id(ctx.id()),
+    )
+    .await?;
     // let winner_id = winner.parse().unwrap();
     a.edit(
         ctx,
@@ -1,12 +1,10 @@
 use std::fmt::Display;
 
-use crate::database::BalanceDatabase;
+use crate::database::{BalanceDatabase, Currency, Reason, RoleDatabase};
 use crate::{Context, Error};
 use poise::serenity_prelude::User;
 use poise::CreateReply;
 
-use super::rockpaperscissors::award_role_holder;
-
 #[derive(poise::ChoiceParameter, Clone, Debug)]
 enum WhatToGive {
     Bucks,
@@ -87,31 +85,38 @@ pub async fn give(
         return Err("You can't afford to do that".into());
     }
 
-    let tax = match currency {
-        WhatToGive::Bucks => amount as f32 * 0.02,
-        WhatToGive::Bones => 0.0,
-    };
-    // round tax up to the nearest integer
-    let tax = tax.ceil() as i32;
+    // make sure the recipient has a balance row to receive into
+    db.get_balance(recipient_id).await?;
 
-    match currency {
-        WhatToGive::Bucks => {
-            db.subtract_balances(vec![sender], amount).await?;
-            db.award_balances(vec![recipient_id], amount - tax).await?;
-        }
-        WhatToGive::Bones => {
-            db.remove_bones(sender, amount).await?;
-            db.add_bones(recipient_id, amount).await?;
-        }
+    // only bucks are taxed, the tax is burned when nobody wears the crown
+    let (tax, crown) = match currency {
+        WhatToGive::Bucks => (
+            // round tax up to the nearest integer
+            (amount as f32 * 0.02).ceil() as i32,
+            db.get_unique_role_holder(ctx.data().crown_role_id).await?,
+        ),
+        WhatToGive::Bones => (0, None),
     };
+    let what = match currency {
+        WhatToGive::Bucks => Currency::Bucks,
+        WhatToGive::Bones => Currency::Bones,
+    };
+    db.transfer_taxed(
+        sender,
+        &[(recipient_id, amount - tax)],
+        tax,
+        crown.as_ref().map(|c| c.user_id),
+        what,
+        Reason::Give.with_id(ctx.id()),
+    )
+    .await?;
 
-    let tax_msg = if let Some(user) = award_role_holder(ctx, tax).await? {
-        format!(
+    let tax_msg = match crown {
+        Some(user) if tax > 0 => format!(
             "-{} <:jbuck:1228663982462865450> to <@{}> (Crown's Tax)",
-            tax, user
-        )
-    } else {
-        "".to_string()
+            tax, user.user_id
+        ),
+        _ => "".to_string(),
     };
     let reply = {
         CreateReply::default()
@@ -1,4 +1,4 @@
-use crate::database::BalanceDatabase;
+use crate::database::{BalanceDatabase, Reason};
 use crate::{Context, Error};
 use poise::serenity_prelude as serenity;
 use rand::seq::SliceRandom;
@@ -82,6 +82,10 @@ pub async fn giveaway(
 
     let mut a = ctx.channel_id().send_message(ctx, reply).await?;
     let id = a.id;
+    ctx.data()
+        .db
+        .link_transactions(&id.to_string(), &a.link())
+        .await?;
     while let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
         .channel_id(ctx.channel_id())
         .custom_ids(
@@ -117,7 +121,11 @@ pub async fn giveaway(
         if mci.data.custom_id == format!("{}0", option) {
             ctx.data()
                 .db
-                .award_balances(vec![mci.user.id.into()], amount)
+                .award_balances(
+                    vec![mci.user.id.into()],
+                    amount,
+                    Reason::Giveaway.with_id(id),
+                )
                 .await
                 .unwrap();
             mci.create_response(
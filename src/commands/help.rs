@@ -2,17 +2,22 @@ use crate::{Context, Error};
 use poise::serenity_prelude as serenity;
 use poise::CreateReply;
 
-const WHITE_LISTED: [&str; 17] = [
+const WHITE_LISTED: [&str; 28] = [
     "help",
     "balance",
+    "history",
     "leaderboard",
     "crownleaderboard",
+    "roleleaderboard",
+    "role",
     "give",
     "coingamble",
     "daily",
     "bury",
     "buyrobbery",
     "rpsgamble",
+    "blackjack",
+    "poker",
     "buy",
     "sell",
     "bones",
@@ -20,6 +25,12 @@ const WHITE_LISTED: [&str; 17] = [
     "lottery",
     "report",
     "robbery",
+    "verify",
+    "auction",
+    "bid",
+    "item",
+    "inventory",
+    "market",
 ];
 
 pub async fn complete_help<'a>(
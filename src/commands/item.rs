@@ -0,0 +1,259 @@
+use poise::{serenity_prelude as serenity, CreateReply};
+
+use crate::{
+    database::{Effect, Item, ItemDatabase, Reason},
+    discord::JBUCK_EMOJI,
+    Context, Error,
+};
+
+pub async fn complete_items<'a>(
+    ctx: Context<'a>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    let items = ctx.data().db.get_items().await.unwrap_or_default();
+    items
+        .into_iter()
+        .map(|i| i.name)
+        .filter(move |name| name.to_lowercase().starts_with(&partial.to_lowercase()))
+}
+
+// replies for the user when there's no such item
+async fn find_item(ctx: Context<'_>, name: &str) -> Result<Option<Item>, Error> {
+    let item = ctx.data().db.get_item(name).await?;
+    if item.is_none() {
+        let reply = CreateReply::default()
+            .content(format!("There's no item called {}", name))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+    }
+    Ok(item)
+}
+
+/// One line per item for `/shop`
+pub fn shop_line(item: &Item) -> String {
+    let mut line = format!("> **{}** - {} {}", item.name, item.price, JBUCK_EMOJI);
+    if let Some(stock) = item.stock {
+        line.push_str(&format!(" ({} left)", stock));
+    }
+    if let Some(max) = item.max_per_user {
+        line.push_str(&format!(" (max {} each)", max));
+    }
+    if let Some(hours) = item.expires_after_hours {
+        line.push_str(&format!(" (lasts {} hours)", hours));
+    }
+    if !item.description.is_empty() {
+        line.push_str(&format!("\n> *{}*", item.description));
+    }
+    line
+}
+
+///
+/// Buy an item from the shop
+///
+/// Enter `/buy item <item> [amount]`
+/// ```
+/// /buy item robbery shield
+/// ```
+#[poise::command(slash_command, rename = "item")]
+pub async fn buyitem(
+    ctx: Context<'_>,
+    #[description = "The item to buy"]
+    #[autocomplete = "complete_items"]
+    item: String,
+    #[min = 1]
+    #[description = "How many to buy"]
+    amount: Option<i32>,
+) -> Result<(), Error> {
+    let amount = amount.unwrap_or(1);
+    let item = match find_item(ctx, &item).await? {
+        Some(item) => item,
+        None => return Ok(()),
+    };
+    let bought = ctx
+        .data()
+        .db
+        .buy_item(
+            ctx.author().id.get(),
+            item.id,
+            amount,
+            Reason::ItemPurchase.with_id(item.id),
+        )
+        .await?;
+
+    let reply = match bought {
+        Ok(()) => CreateReply::default().content(format!(
+            "{} bought {} **{}** for {} {}!",
+            ctx.author(),
+            amount,
+            item.name,
+            item.price * amount,
+            JBUCK_EMOJI
+        )),
+        Err(refused) => CreateReply::default()
+            .content(refused.to_string())
+            .ephemeral(true),
+    };
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// See the items you have
+///
+/// Enter `/inventory`
+/// ```
+/// /inventory
+/// ```
+#[poise::command(slash_command)]
+pub async fn inventory(ctx: Context<'_>) -> Result<(), Error> {
+    let items = ctx.data().db.get_inventory(ctx.author().id.get()).await?;
+    let content = if items.is_empty() {
+        "You don't have any items, see what's in the `/shop`".to_string()
+    } else {
+        let lines = items
+            .iter()
+            .map(|i| match i.expires_at {
+                Some(expires_at) => format!(
+                    "> {}x **{}** (first runs out <t:{}:R>)",
+                    i.quantity,
+                    i.name,
+                    expires_at.timestamp()
+                ),
+                None => format!("> {}x **{}**", i.quantity, i.name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("> ### Inventory\n{}", lines)
+    };
+    let reply = CreateReply::default().content(content).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// items in the shop
+///
+#[poise::command(slash_command, subcommands("give", "create", "retire"))]
+pub async fn item(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+///
+/// give items to another player
+///
+/// Enter `/item give <user> <item> [amount]`
+/// ```
+/// /item give @John lucky charm
+/// ```
+#[poise::command(slash_command)]
+pub async fn give(
+    ctx: Context<'_>,
+    #[description = "Who to give it to"] user: serenity::User,
+    #[description = "The item to give"]
+    #[autocomplete = "complete_items"]
+    item: String,
+    #[min = 1]
+    #[description = "How many to give"]
+    amount: Option<i32>,
+) -> Result<(), Error> {
+    let amount = amount.unwrap_or(1);
+    let item = match find_item(ctx, &item).await? {
+        Some(item) => item,
+        None => return Ok(()),
+    };
+    let given = ctx
+        .data()
+        .db
+        .give_item(ctx.author().id.get(), user.id.get(), item.id, amount)
+        .await?;
+
+    let reply = match given {
+        Ok(()) => CreateReply::default().content(format!(
+            "{} gave {} {} **{}**",
+            ctx.author(),
+            user,
+            amount,
+            item.name
+        )),
+        Err(refused) => CreateReply::default()
+            .content(refused.to_string())
+            .ephemeral(true),
+    };
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// add an item to the shop, or change one
+///
+/// Enter `/item create <name> <price>`
+/// ```
+/// /item create robbery shield 50 "Keeps the robbers away" 10 1 false 24 "Robbery shield"
+/// ```
+#[poise::command(slash_command, required_permissions = "ADMINISTRATOR")]
+#[allow(clippy::too_many_arguments)]
+pub async fn create(
+    ctx: Context<'_>,
+    #[description = "The item's name"] name: String,
+    #[min = 1]
+    #[description = "The price of one"]
+    price: i32,
+    #[description = "What it does"] description: Option<String>,
+    #[min = 0]
+    #[description = "How many can be sold, unlimited if empty"]
+    stock: Option<i32>,
+    #[min = 1]
+    #[description = "How many one player can have"]
+    max_per_user: Option<i32>,
+    #[description = "Can players give it to each other"] tradeable: Option<bool>,
+    #[min = 1]
+    #[description = "Bought items run out after this many hours"]
+    expires_after_hours: Option<i32>,
+    #[description = "What using one does"] effect: Option<Effect>,
+) -> Result<(), Error> {
+    let id = ctx
+        .data()
+        .db
+        .create_item(
+            name.trim(),
+            description.as_deref().unwrap_or(""),
+            price,
+            stock,
+            max_per_user,
+            tradeable.unwrap_or(false),
+            expires_after_hours,
+            effect,
+        )
+        .await?;
+    let reply = CreateReply::default()
+        .content(format!("**{}** (#{}) is in the shop", name.trim(), id))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// take an item out of the shop, players keep what they have
+///
+/// Enter `/item retire <item>`
+/// ```
+/// /item retire robbery shield
+/// ```
+#[poise::command(slash_command, required_permissions = "ADMINISTRATOR")]
+pub async fn retire(
+    ctx: Context<'_>,
+    #[description = "The item to take out of the shop"]
+    #[autocomplete = "complete_items"]
+    item: String,
+) -> Result<(), Error> {
+    let item = match find_item(ctx, &item).await? {
+        Some(item) => item,
+        None => return Ok(()),
+    };
+    ctx.data().db.retire_item(item.id).await?;
+    let reply = CreateReply::default()
+        .content(format!("**{}** is no longer for sale", item.name))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use poise::CreateReply;
+use tokio::sync::oneshot;
+
+use crate::{
+    database::JobDatabase,
+    scheduler::{Job, JobCommand},
+    Context, Error,
+};
+
+///
+/// manage scheduled jobs
+///
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    hide_in_help,
+    default_member_permissions = "ADMINISTRATOR",
+    subcommands("list", "run", "pause", "resume")
+)]
+pub async fn jobs(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+///
+/// list scheduled jobs with their last and next run
+///
+/// Enter `/jobs list`
+/// ```
+/// /jobs list
+/// ```
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    hide_in_help,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let stored = ctx.data().db.get_jobs().await?;
+
+    let mut response = "> ### Scheduled jobs\n".to_string();
+    for job in Job::ALL {
+        let status = match stored.iter().find(|s| s.name == job.name()) {
+            Some(status) => status,
+            None => {
+                response.push_str(&format!("> **{}**: never run\n", job.name()));
+                continue;
+            }
+        };
+        response.push_str(&format!(
+            "> **{}**{}\n> Last run: <t:{}:R> | Next run: {}\n",
+            job.name(),
+            if status.paused { " (paused)" } else { "" },
+            status.last_run.timestamp(),
+            match status.next_run {
+                Some(next) if !status.paused => format!("<t:{}:R>", next.timestamp()),
+                _ => "-".to_string(),
+            },
+        ));
+        if let Some(e) = &status.last_error {
+            response.push_str(&format!("> Last error: `{}`\n", e));
+        }
+    }
+
+    let reply = CreateReply::default().content(response).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// run a job right now
+///
+/// Enter `/jobs run <job>`
+/// ```
+/// /jobs run Lottery
+/// ```
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    hide_in_help,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn run(ctx: Context<'_>, job: Job) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let (tx, rx) = oneshot::channel();
+    ctx.data().jobs.send(JobCommand::Run(job, tx))?;
+
+    let content = match rx.await {
+        Ok(Ok(_)) => format!("Ran {}", job.name()),
+        Ok(Err(e)) => format!("{} failed: `{}`", job.name(), e),
+        Err(_) => "Johnny is not running".to_string(),
+    };
+    let reply = CreateReply::default().content(content).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// stop a job from running on its schedule
+///
+/// Enter `/jobs pause <job>`
+/// ```
+/// /jobs pause BonesDecay
+/// ```
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    hide_in_help,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn pause(ctx: Context<'_>, job: Job) -> Result<(), Error> {
+    ctx.data().jobs.send(JobCommand::Pause(job))?;
+    let reply = CreateReply::default()
+        .content(format!("Paused {}", job.name()))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// let a paused job run on its schedule again
+///
+/// Enter `/jobs resume <job>`
+/// ```
+/// /jobs resume BonesDecay
+/// ```
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    hide_in_help,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn resume(ctx: Context<'_>, job: Job) -> Result<(), Error> {
+    ctx.data().jobs.send(JobCommand::Resume(job))?;
+    let reply = CreateReply::default()
+        .content(format!("Resumed {}", job.name()))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
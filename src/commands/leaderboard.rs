@@ -91,9 +91,8 @@ pub async fn crownleaderboard(ctx: Context<'_>) -> Result<(), Error> {
             if let Some(crown) = &crown_holder {
                 if *k == crown.user_id {
                     let now = chrono::Utc::now();
-                    let bought = crown.purchased;
-                    let time_since_purchase = now - bought;
-                    let a = v + time_since_purchase.num_minutes() as f32 / 60.0;
+                    let time_since_credit = now - crown.credited_until;
+                    let a = v + time_since_credit.num_minutes() as f32 / 60.0;
                     return (k, a);
                 }
             }
@@ -147,9 +146,8 @@ pub async fn crownleaderboard(ctx: Context<'_>) -> Result<(), Error> {
     if let Some(crown) = &crown_holder {
         if top_text.is_empty() {
             let now = chrono::Utc::now();
-            let bought = crown.purchased;
-            let time_since_purchase = now - bought;
-            let a = time_since_purchase.num_minutes() as f32 / 60.0;
+            let time_since_credit = now - crown.credited_until;
+            let a = time_since_credit.num_minutes() as f32 / 60.0;
             top_text = format!(
                 "> :clock1: **{:.2} Hours** - **{}**",
                 a,
@@ -172,3 +170,65 @@ pub async fn crownleaderboard(ctx: Context<'_>) -> Result<(), Error> {
     ctx.send(reply).await?;
     Ok(())
 }
+
+///
+/// View who held a unique role the longest
+///
+/// Enter `/roleleaderboard <role>` to view
+/// ```
+/// /roleleaderboard @Johnny'sChosen
+/// ```
+#[poise::command(slash_command)]
+pub async fn roleleaderboard(
+    ctx: Context<'_>,
+    #[description = "A role only one person can have"] role: poise::serenity_prelude::Role,
+) -> Result<(), Error> {
+    if !ctx.data().unique_roles.lock().unwrap().contains(&role.id) {
+        let reply = CreateReply::default()
+            .content(format!("{} isn't a unique role", role))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+    let _ = ctx.defer_or_broadcast().await; // leaderboard can take some time
+
+    let times = ctx.data().db.get_role_leaderboard(role.id.get()).await?;
+    if times.is_empty() {
+        ctx.say(format!("Nobody has had {} yet!", role.name))
+            .await?;
+        return Ok(());
+    }
+    let holder = ctx.data().db.get_unique_role_holder(role.id.get()).await?;
+
+    let mut lines = vec![];
+    for (i, (user_id, hours)) in times.iter().enumerate() {
+        let name = get_discord_name(ctx, *user_id).await;
+        let minutes = (hours.fract() * 60.0) as i32;
+        let holding = holder.as_ref().is_some_and(|h| h.user_id == *user_id);
+        lines.push(format!(
+            "> {}. **{:0>2}:{:0>2}** - {}{}",
+            i + 1,
+            hours.trunc() as i32,
+            minutes,
+            if i == 0 {
+                format!("**{}**", name)
+            } else {
+                name
+            },
+            if holding { " (current holder)" } else { "" }
+        ));
+    }
+
+    let reply = {
+        CreateReply::default()
+            .content(format!(
+                "> ### {} Time Leaderboard \n> \n{}\n",
+                role.name,
+                lines.join("\n")
+            ))
+            .allowed_mentions(CreateAllowedMentions::new().empty_users())
+    };
+
+    ctx.send(reply).await?;
+    Ok(())
+}
@@ -1,7 +1,46 @@
 use crate::commands::robbingevent::get_discord_name;
-use crate::{database::BalanceDatabase, database::LotteryDatabase, Context, Error};
+use crate::{
+    database::BalanceDatabase, database::Lottery, database::LotteryDatabase, database::LotteryDraw,
+    database::Reason, database::SeedDatabase, discord::JBUCK_EMOJI, fairness, game, scheduler,
+    Context, Error,
+};
 use poise::CreateReply;
 
+pub async fn complete_lotteries<'a>(
+    ctx: Context<'a>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    let lotteries = ctx.data().db.get_open_lotteries().await.unwrap_or_default();
+    lotteries
+        .into_iter()
+        .map(|l| l.name)
+        .filter(move |name| name.to_lowercase().starts_with(&partial.to_lowercase()))
+}
+
+// the lottery drawn next when no name is given
+async fn find_lottery(ctx: Context<'_>, name: Option<String>) -> Result<Lottery, Error> {
+    let lotteries = ctx.data().db.get_open_lotteries().await?;
+    let lottery = match &name {
+        Some(name) => lotteries
+            .into_iter()
+            .find(|l| l.name.eq_ignore_ascii_case(name)),
+        None => lotteries.into_iter().next(),
+    };
+    match lottery {
+        Some(l) => Ok(l),
+        None => {
+            let reply = CreateReply::default()
+                .content(match name {
+                    Some(name) => format!("There is no lottery called {}", name),
+                    None => "There is no lottery running".to_string(),
+                })
+                .ephemeral(true);
+            ctx.send(reply).await?;
+            Err("lottery not found".into())
+        }
+    }
+}
+
 ///
 /// Commands relating to the lottery
 ///
@@ -9,7 +48,18 @@ use poise::CreateReply;
 /// ```
 /// /lottery info
 /// ```
-#[poise::command(slash_command, subcommands("info"))]
+#[poise::command(
+    slash_command,
+    subcommands(
+        "info",
+        "history",
+        "stats",
+        "subscribe",
+        "unsubscribe",
+        "create",
+        "edit"
+    )
+)]
 #[tracing::instrument(level = "info")]
 pub async fn lottery(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
@@ -18,32 +68,26 @@ pub async fn lottery(ctx: Context<'_>) -> Result<(), Error> {
 ///
 /// Get information about the current lottery
 ///
-/// Enter `/lottery info`
+/// Enter `/lottery info <lottery>`
 /// ```
-/// /lottery info
+/// /lottery info mega
 /// ```
 #[poise::command(slash_command)]
 #[tracing::instrument(level = "info")]
-pub async fn info(ctx: Context<'_>) -> Result<(), Error> {
+pub async fn info(
+    ctx: Context<'_>,
+    #[description = "The lottery, defaults to the next one drawn"]
+    #[autocomplete = "complete_lotteries"]
+    lottery: Option<String>,
+) -> Result<(), Error> {
+    let lottery = find_lottery(ctx, lottery).await?;
     ctx.defer().await?;
-    let data = ctx.data().db.get_bought_tickets().await.unwrap();
-    let base_prize = { ctx.data().config.read().unwrap().lottery_base_prize };
-    let price = { ctx.data().config.read().unwrap().lottery_ticket_price };
+    let data = ctx.data().db.get_bought_tickets(lottery.id).await?;
 
     let tickets_sold = data.iter().map(|(_, v)| v).sum::<i32>();
-    let prize = (tickets_sold * (price - 1)) + base_prize;
-
-    // ends at the next 18:00 UTC
-    let mut end = chrono::Utc::now()
-        .naive_utc()
-        .date()
-        .and_hms_opt(18, 0, 0)
-        .unwrap();
-
-    let now = chrono::Utc::now().naive_utc();
-    if end.lt(&now) {
-        end += chrono::Duration::days(1);
-    }
+    let prize = lottery.prize(tickets_sold);
+    let end = lottery.draw_time;
+    let seed = ctx.data().db.get_seed(&lottery.game_id()).await?;
 
     let mut player_names = std::collections::HashMap::new();
 
@@ -51,10 +95,19 @@ pub async fn info(ctx: Context<'_>) -> Result<(), Error> {
         player_names.insert(user, get_discord_name(ctx, *user).await);
     }
 
-    let info = format!(
-        "> **Prize pool:** {} <:jbuck:1228663982462865450>\n> **Tickets sold:** {} :tickets:\n > **End:** <t:{}:R>",
-        prize, tickets_sold, end.and_utc().timestamp(),
+    let mut info = format!(
+        "> **Lottery:** {}\n> **Prize pool:** {} <:jbuck:1228663982462865450>\n> **Tickets sold:** {} :tickets:\n > **End:** <t:{}:R>",
+        lottery.name, prize, tickets_sold, end.timestamp(),
     );
+    if lottery.prize_tiers != "100" {
+        info.push_str(&format!("\n> **Prize tiers:** {} %", lottery.prize_tiers));
+    }
+    if lottery.min_tickets > 0 {
+        info.push_str(&format!(
+            "\n> **Rollover:** the pot rolls over below {} :tickets:",
+            lottery.min_tickets
+        ));
+    }
 
     let mut a = data
         .iter()
@@ -68,8 +121,6 @@ pub async fn info(ctx: Context<'_>) -> Result<(), Error> {
         })
         .collect::<Vec<String>>();
 
-    let price = { ctx.data().config.read().unwrap().lottery_ticket_price };
-
     if a.len() < data.len() {
         a.push(String::from(
             "> *Sorry, we don't have the technology to show more than 10 people*",
@@ -83,20 +134,187 @@ pub async fn info(ctx: Context<'_>) -> Result<(), Error> {
 
     a.push(format!(
         "> Use ***/buy lottery*** to purchase a ticket for {} <:jbuck:1228663982462865450>",
-        price
+        lottery.ticket_price
     ));
+    // seeds are only committed by the scheduler, before that there is nothing to show
+    if let Some(seed) = seed {
+        a.push(format!(
+            "> -# Commitment `{}`, check the draw with `/verify {}`",
+            seed.commitment,
+            lottery.game_id()
+        ));
+    } else {
+        a.push(String::from("> -# The draw seed hasn't been committed yet"));
+    }
 
     let reply = CreateReply::default().content(a.join("\n").to_string());
     ctx.send(reply).await?;
     Ok(())
 }
 
+///
+/// See past lottery winners and the biggest pots
+///
+/// Enter `/lottery history`
+/// ```
+/// /lottery history
+/// ```
+#[poise::command(slash_command)]
+#[tracing::instrument(level = "info")]
+pub async fn history(ctx: Context<'_>) -> Result<(), Error> {
+    let recent = ctx.data().db.get_recent_draws(10).await?;
+    let biggest = ctx.data().db.get_biggest_draws(3).await?;
+
+    let format_draw = |d: &LotteryDraw| {
+        format!(
+            "> <t:{}:d> **{}** - <@{}> won **{}** {} with {}/{} :tickets: ({} players, pot {}{})",
+            d.drawn_at.timestamp(),
+            d.name,
+            d.winner,
+            d.prize,
+            JBUCK_EMOJI,
+            d.winner_tickets,
+            d.tickets_sold,
+            d.players,
+            d.pot,
+            match d.winners {
+                0 | 1 => String::new(),
+                n => format!(", {} winners", n),
+            }
+        )
+    };
+
+    let mut lines = vec![String::from("> ### Recent draws")];
+    if recent.is_empty() {
+        lines.push(String::from("> *No lottery has been drawn yet*"));
+    }
+    lines.extend(recent.iter().map(format_draw));
+    if !biggest.is_empty() {
+        lines.push(String::from("> ### Biggest pots"));
+        lines.extend(biggest.iter().map(format_draw));
+    }
+
+    let reply = CreateReply::default()
+        .content(lines.join("\n"))
+        .allowed_mentions(poise::serenity_prelude::CreateAllowedMentions::new().empty_users());
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// See how much someone has spent on and won from the lottery
+///
+/// Enter `/lottery stats <user>`
+/// ```
+/// /lottery stats @John
+/// ```
+#[poise::command(slash_command)]
+#[tracing::instrument(level = "info")]
+pub async fn stats(
+    ctx: Context<'_>,
+    #[description = "Defaults to you"] user: Option<poise::serenity_prelude::User>,
+) -> Result<(), Error> {
+    let user = user.as_ref().unwrap_or(ctx.author());
+    let stats = ctx.data().db.get_lottery_stats(user.id.get()).await?;
+
+    let text = format!(
+        "> ### {}'s lottery stats\n> **Draws entered:** {}\n> **Tickets bought:** {} :tickets:\n> **Spent:** {} {}\n> **Won:** {} {} ({} wins)\n> **Net:** {} {}",
+        get_discord_name(ctx, user.id.get()).await,
+        stats.draws_entered,
+        stats.tickets,
+        stats.spent,
+        JBUCK_EMOJI,
+        stats.won,
+        JBUCK_EMOJI,
+        stats.wins,
+        stats.won - stats.spent,
+        JBUCK_EMOJI
+    );
+    ctx.send(CreateReply::default().content(text)).await?;
+    Ok(())
+}
+
+///
+/// Buy tickets automatically every time a new lottery round starts
+///
+/// Enter `/lottery subscribe <tickets> <days> <lottery>`
+/// ```
+/// /lottery subscribe 2 7 daily
+/// ```
+#[poise::command(slash_command)]
+#[tracing::instrument(level = "info")]
+pub async fn subscribe(
+    ctx: Context<'_>,
+    #[description = "Tickets to buy each round"]
+    #[min = 1]
+    #[max = 100]
+    tickets: i32,
+    #[description = "How many days to keep buying"]
+    #[min = 1]
+    #[max = 30]
+    days: i64,
+    #[description = "The lottery, defaults to the next one drawn"]
+    #[autocomplete = "complete_lotteries"]
+    lottery: Option<String>,
+) -> Result<(), Error> {
+    let lottery = find_lottery(ctx, lottery).await?;
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(days);
+    ctx.data()
+        .db
+        .subscribe_lottery(ctx.author().id.get(), &lottery.name, tickets, expires_at)
+        .await?;
+
+    let reply = CreateReply::default()
+        .content(format!(
+            "You will get {} :tickets: in every **{}** lottery until <t:{}:f>, that's {} {} a round. I'll DM you if you can't afford it.\nUse ***/lottery unsubscribe*** to stop.",
+            tickets,
+            lottery.name,
+            expires_at.timestamp(),
+            tickets * lottery.ticket_price,
+            JBUCK_EMOJI
+        ))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// Stop buying lottery tickets automatically
+///
+/// Enter `/lottery unsubscribe <lottery>`, leave the lottery out to cancel all of them
+/// ```
+/// /lottery unsubscribe daily
+/// ```
+#[poise::command(slash_command)]
+#[tracing::instrument(level = "info")]
+pub async fn unsubscribe(
+    ctx: Context<'_>,
+    #[description = "The lottery, defaults to all of them"]
+    #[autocomplete = "complete_lotteries"]
+    lottery: Option<String>,
+) -> Result<(), Error> {
+    let cancelled = ctx
+        .data()
+        .db
+        .unsubscribe_lottery(ctx.author().id.get(), lottery.as_deref())
+        .await?;
+
+    let content = match (cancelled, lottery) {
+        (0, _) => "You don't have a subscription to cancel".to_string(),
+        (_, Some(name)) => format!("Cancelled your **{}** lottery subscription", name),
+        (n, None) => format!("Cancelled {} lottery subscription(s)", n),
+    };
+    ctx.send(CreateReply::default().content(content).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
 ///
 /// Buy a lottery ticket
 ///
-/// Enter `/buy lottery`
+/// Enter `/buy lottery <amount> <lottery>`
 /// ```
-/// /buy lottery
+/// /buy lottery 2 mega
 /// ```
 #[poise::command(slash_command, rename = "lottery")]
 pub async fn buylotteryticket(
@@ -105,12 +323,24 @@ pub async fn buylotteryticket(
     #[min = 1]
     #[max = 100]
     amount: Option<i32>,
+    #[description = "The lottery, defaults to the next one drawn"]
+    #[autocomplete = "complete_lotteries"]
+    lottery: Option<String>,
 ) -> Result<(), Error> {
     let amount = amount.unwrap_or(1);
-    let user_balance = ctx.data().db.get_balance(ctx.author().id.get()).await?;
-    let base_prize = { ctx.data().config.read().unwrap().lottery_base_prize };
-    let price = { ctx.data().config.read().unwrap().lottery_ticket_price };
-    if price * amount > user_balance {
+    let lottery = find_lottery(ctx, lottery).await?;
+    let owned_tickets = ctx
+        .data()
+        .db
+        .buy_lottery_tickets(
+            lottery.id,
+            ctx.author().id.get(),
+            amount,
+            Reason::LotteryTicket.with_id(lottery.id),
+        )
+        .await?;
+    let Some(owned_tickets) = owned_tickets else {
+        let user_balance = ctx.data().db.get_balance(ctx.author().id.get()).await?;
         let reply = {
             CreateReply::default()
                 .content(format!(
@@ -121,35 +351,23 @@ pub async fn buylotteryticket(
         };
         ctx.send(reply).await?;
         return Err("can't afford to do that".into());
-    }
-
-    ctx.data()
-        .db
-        .subtract_balances(vec![ctx.author().id.get()], price * amount)
-        .await?;
-
-    let owned_tickets = ctx
-        .data()
-        .db
-        .bought_lottery_ticket(ctx.author().id.get(), amount)
-        .await?;
+    };
 
-    let prize = ctx
+    let tickets_sold = ctx
         .data()
         .db
-        .get_bought_tickets()
-        .await
-        .unwrap()
+        .get_bought_tickets(lottery.id)
+        .await?
         .iter()
-        .map(|(_, x)| x * (price - 1))
-        .sum::<i32>()
-        + base_prize;
+        .map(|(_, x)| x)
+        .sum::<i32>();
+    let prize = lottery.prize(tickets_sold);
 
     let reply = {
         CreateReply::default()
                 .content(format!(
-                    "> **<@{}> purchased a lottery ticket!**\n> They have a total of {} :tickets:\n> Prize pool increased to {} <:jbuck:1228663982462865450>",
-                    ctx.author().id.get(), owned_tickets, prize
+                    "> **<@{}> purchased a {} lottery ticket!**\n> They have a total of {} :tickets:\n> Prize pool increased to {} <:jbuck:1228663982462865450>",
+                    ctx.author().id.get(), lottery.name, owned_tickets, prize
                 ))
     };
 
@@ -157,3 +375,134 @@ pub async fn buylotteryticket(
 
     Ok(())
 }
+
+///
+/// start a new lottery that runs next to the others
+///
+/// Enter `/lottery create <name> <ticket price> <base prize> <schedule>`
+/// ```
+/// /lottery create mega 50 1000 "0 0 18 * * Sun" prize_tiers:70,20,10 min_tickets:20
+/// ```
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    hide_in_help,
+    required_permissions = "ADMINISTRATOR"
+)]
+pub async fn create(
+    ctx: Context<'_>,
+    #[description = "The name players pick the lottery by"] name: String,
+    #[description = "Price of one ticket"]
+    #[min = 1]
+    ticket_price: i32,
+    #[description = "Prize pool before any tickets are sold"]
+    #[min = 0]
+    base_prize: i32,
+    #[description = "Draw schedule as a cron expression in UTC, with seconds"] schedule: String,
+    #[description = "Percentages of the pot per place, e.g. 70,20,10"] prize_tiers: Option<String>,
+    #[description = "The pot rolls over when fewer tickets are sold"]
+    #[min = 0]
+    min_tickets: Option<i32>,
+) -> Result<(), Error> {
+    if let Some(tiers) = &prize_tiers {
+        game::parse_tiers(tiers)?;
+    }
+    let lotteries = ctx.data().db.get_open_lotteries().await?;
+    if lotteries.iter().any(|l| l.name.eq_ignore_ascii_case(&name)) {
+        return Err(format!("There already is a lottery called {}", name).into());
+    }
+    let draw_time = scheduler::next_draw(&schedule, chrono::Utc::now())
+        .ok_or("That schedule has no upcoming draw")?;
+
+    let id = ctx
+        .data()
+        .db
+        .create_lottery(
+            &name,
+            ticket_price,
+            base_prize,
+            &schedule,
+            draw_time,
+            prize_tiers.as_deref().unwrap_or("100"),
+            min_tickets.unwrap_or(0),
+        )
+        .await?;
+    fairness::commit_new_seed(&ctx.data().db, &format!("lottery-{}", id), "lottery").await?;
+
+    let reply = CreateReply::default()
+        .content(format!(
+            "Created the {} lottery, first draw <t:{}:R>",
+            name,
+            draw_time.timestamp()
+        ))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// change the prices of a running lottery
+///
+/// Enter `/lottery edit <lottery>` with the values to change. The next values are used
+/// once the current round has been drawn.
+/// ```
+/// /lottery edit daily next_ticket_price:10
+/// ```
+#[poise::command(
+    slash_command,
+    category = "Admin",
+    hide_in_help,
+    required_permissions = "ADMINISTRATOR"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn edit(
+    ctx: Context<'_>,
+    #[autocomplete = "complete_lotteries"] lottery: String,
+    #[min = 1] ticket_price: Option<i32>,
+    #[min = 0] base_prize: Option<i32>,
+    #[min = 1] next_ticket_price: Option<i32>,
+    #[min = 0] next_base_prize: Option<i32>,
+    #[description = "Percentages of the pot per place, e.g. 70,20,10"] prize_tiers: Option<String>,
+    #[description = "The pot rolls over when fewer tickets are sold"]
+    #[min = 0]
+    min_tickets: Option<i32>,
+) -> Result<(), Error> {
+    if let Some(tiers) = &prize_tiers {
+        game::parse_tiers(tiers)?;
+    }
+    let mut lottery = find_lottery(ctx, Some(lottery)).await?;
+    if ticket_price.is_some_and(|price| price != lottery.ticket_price)
+        && !ctx
+            .data()
+            .db
+            .get_bought_tickets(lottery.id)
+            .await?
+            .is_empty()
+    {
+        return Err(
+            "Tickets have already been sold this round, change next_ticket_price instead".into(),
+        );
+    }
+    lottery.prize_tiers = prize_tiers.unwrap_or(lottery.prize_tiers);
+    lottery.min_tickets = min_tickets.unwrap_or(lottery.min_tickets);
+    lottery.ticket_price = ticket_price.unwrap_or(lottery.ticket_price);
+    lottery.base_prize = base_prize.unwrap_or(lottery.base_prize);
+    lottery.next_ticket_price = next_ticket_price.unwrap_or(lottery.next_ticket_price);
+    lottery.next_base_prize = next_base_prize.unwrap_or(lottery.next_base_prize);
+    ctx.data().db.update_lottery(&lottery).await?;
+
+    let reply = CreateReply::default()
+        .content(format!(
+            "**{}** lottery\nTicket price: {} (next {})\nBase prize: {} (next {})\nPrize tiers: {} %\nMinimum tickets: {}",
+            lottery.name,
+            lottery.ticket_price,
+            lottery.next_ticket_price,
+            lottery.base_prize,
+            lottery.next_base_prize,
+            lottery.prize_tiers,
+            lottery.min_tickets
+        ))
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use poise::CreateReply;
+
+use crate::{
+    database::{MarketDatabase, OrderPlaced, OrderSide},
+    discord::JBUCK_EMOJI,
+    Context, Error,
+};
+
+/// How many prices of each side `/market book` shows
+const BOOK_DEPTH: i64 = 10;
+
+///
+/// trade bones with other players
+///
+#[poise::command(slash_command, subcommands("sell", "buy", "book", "cancel"))]
+pub async fn market(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+///
+/// offer bones for sale at a price
+///
+/// Enter `/market sell <amount> <price>`, the bones are held until the order fills or you cancel it
+/// ```
+/// /market sell 5 30
+/// ```
+#[poise::command(slash_command)]
+pub async fn sell(
+    ctx: Context<'_>,
+    #[min = 1]
+    #[description = "How many bones to sell"]
+    amount: i32,
+    #[min = 1]
+    #[description = "The lowest price per bone"]
+    price: i32,
+) -> Result<(), Error> {
+    place_order(ctx, OrderSide::Sell, amount, price).await
+}
+
+///
+/// bid for bones at a price
+///
+/// Enter `/market buy <amount> <price>`, the bucks are held until the order fills or you cancel it
+/// ```
+/// /market buy 3 28
+/// ```
+#[poise::command(slash_command)]
+pub async fn buy(
+    ctx: Context<'_>,
+    #[min = 1]
+    #[description = "How many bones to buy"]
+    amount: i32,
+    #[min = 1]
+    #[description = "The highest price per bone"]
+    price: i32,
+) -> Result<(), Error> {
+    place_order(ctx, OrderSide::Buy, amount, price).await
+}
+
+async fn place_order(
+    ctx: Context<'_>,
+    side: OrderSide,
+    amount: i32,
+    price: i32,
+) -> Result<(), Error> {
+    let placed = ctx
+        .data()
+        .db
+        .place_order(ctx.author().id.get(), side, amount, price)
+        .await?;
+    let reply = match placed {
+        Ok(placed) => CreateReply::default().content(order_text(ctx, side, price, &placed)),
+        Err(refused) => CreateReply::default()
+            .content(refused.to_string())
+            .ephemeral(true),
+    };
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+fn order_text(ctx: Context<'_>, side: OrderSide, price: i32, placed: &OrderPlaced) -> String {
+    let verb = match side {
+        OrderSide::Buy => ("bought", "from"),
+        OrderSide::Sell => ("sold", "to"),
+    };
+    let mut text = String::new();
+    for fill in placed.fills.iter() {
+        text.push_str(&format!(
+            "> {} {} {} :bone: {} <@{}> at {} {}\n",
+            ctx.author(),
+            verb.0,
+            fill.quantity,
+            verb.1,
+            fill.user_id,
+            fill.price,
+            JBUCK_EMOJI
+        ));
+    }
+    if placed.remaining > 0 {
+        text.push_str(&format!(
+            "> Order #{} to {} {} :bone: at {} {} is on the book",
+            placed.id,
+            side.as_str(),
+            placed.remaining,
+            price,
+            JBUCK_EMOJI
+        ));
+    }
+    text.trim_end().to_string()
+}
+
+///
+/// see the open orders for bones
+///
+/// Enter `/market book`
+/// ```
+/// /market book
+/// ```
+#[poise::command(slash_command)]
+pub async fn book(ctx: Context<'_>) -> Result<(), Error> {
+    let db = &ctx.data().db;
+    let level = |(price, quantity): &(i32, i32)| {
+        format!("> {} :bone: at {} {}", quantity, price, JBUCK_EMOJI)
+    };
+    let mut asks = db.get_order_book(OrderSide::Sell, BOOK_DEPTH).await?;
+    // lowest ask right above the highest bid
+    asks.reverse();
+    let asks = asks.iter().map(level).collect::<Vec<_>>();
+    let bids = db
+        .get_order_book(OrderSide::Buy, BOOK_DEPTH)
+        .await?
+        .iter()
+        .map(level)
+        .collect::<Vec<_>>();
+
+    let mut content = "> ### :bone: Bones Market\n> **Selling**\n".to_string();
+    if asks.is_empty() {
+        content.push_str("> Nobody is selling\n");
+    } else {
+        content.push_str(&format!("{}\n", asks.join("\n")));
+    }
+    content.push_str("> **Buying**\n");
+    if bids.is_empty() {
+        content.push_str("> Nobody is buying\n");
+    } else {
+        content.push_str(&format!("{}\n", bids.join("\n")));
+    }
+    if let Some(price) = db.get_last_trade_price().await? {
+        content.push_str(&format!("> Last trade: {} {}\n", price, JBUCK_EMOJI));
+    }
+
+    let orders = db.get_user_orders(ctx.author().id.get()).await?;
+    if !orders.is_empty() {
+        content.push_str("> **Your orders**\n");
+        for order in orders {
+            content.push_str(&format!(
+                "> #{} {} {} :bone: at {} {}\n",
+                order.id, order.side, order.quantity, order.price, JBUCK_EMOJI
+            ));
+        }
+        content.push_str("> -# Cancel one with `/market cancel <order>`");
+    }
+    let reply = CreateReply::default()
+        .content(content.trim_end())
+        .ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// cancel one of your orders and get back what it held
+///
+/// Enter `/market cancel <order>`, `/market book` shows your order numbers
+/// ```
+/// /market cancel 12
+/// ```
+#[poise::command(slash_command)]
+pub async fn cancel(
+    ctx: Context<'_>,
+    #[description = "The order number"] order: i64,
+) -> Result<(), Error> {
+    let cancelled = ctx
+        .data()
+        .db
+        .cancel_order(order, ctx.author().id.get())
+        .await?;
+    let content = match cancelled {
+        Some(order) if order.side == OrderSide::Buy.as_str() => format!(
+            "Cancelled order #{}, you got back {} {}",
+            order.id,
+            order.price * order.quantity,
+            JBUCK_EMOJI
+        ),
+        Some(order) => format!(
+            "Cancelled order #{}, you got back {} :bone:",
+            order.id, order.quantity
+        ),
+        None => "You don't have an open order with that number".to_string(),
+    };
+    let reply = CreateReply::default().content(content).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
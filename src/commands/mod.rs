@@ -1,9 +1,11 @@
 pub mod addbucks;
+pub mod auction;
 pub mod award;
 pub mod balance;
 pub mod blackjack;
 pub mod burn;
 pub mod buy;
+pub mod cardtable;
 pub mod checkbucks;
 pub mod coingamble;
 pub mod config;
@@ -13,16 +15,22 @@ pub mod gamble;
 pub mod give;
 pub mod giveaway;
 pub mod help;
+pub mod item;
+pub mod jobs;
 pub mod leaderboard;
 pub mod lottery;
+pub mod market;
 pub mod paidchannels;
+pub mod poker;
 pub mod quit;
 pub mod register;
 pub mod removebucks;
 pub mod report;
 pub mod robbingevent;
 pub mod rockpaperscissors;
+pub mod role;
 pub mod sacrifice;
 pub mod say;
 pub mod stats;
 pub mod transfer;
+pub mod verify;
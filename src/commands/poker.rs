@@ -0,0 +1,697 @@
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::commands::cardtable::{ephemeral_response, robbery_refusal, time_left};
+use crate::commands::rockpaperscissors::crown_holder;
+use crate::database::{BalanceDatabase, EscrowDatabase, Reason};
+use crate::discord::JBUCK_EMOJI;
+use crate::game::{rank_hand, Card, Poker, PokerAction, PokerError, Shoe, Street, POKER_SEATS};
+use crate::{Context, Error};
+use poise::{serenity_prelude as serenity, CreateReply};
+
+const ACTIONS: [(&str, &str, PokerAction); 5] = [
+    ("poker_fold", "Fold", PokerAction::Fold),
+    ("poker_check", "Check", PokerAction::Check),
+    ("poker_call", "Call", PokerAction::Call),
+    ("poker_raise", "Raise", PokerAction::Raise),
+    ("poker_allin", "All in", PokerAction::AllIn),
+];
+
+///
+/// Play Texas Hold'em
+///
+/// Enter `/poker open <buy-in>` to start a table in a new thread.
+/// ```
+/// /poker open 100
+/// ```
+#[poise::command(slash_command, subcommands("open"), subcommand_required)]
+pub async fn poker(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+///
+/// Open a poker table
+///
+/// Opens a table in a new thread where up to 8 players sit down with the buy-in as chips.
+/// Blinds are a fiftieth of the buy-in, the crown rakes 5% of every pot that sees a flop.
+/// Check your hole cards with the My cards button, everyone cashes out when the table closes.
+/// ```
+/// /poker open 100
+/// ```
+#[poise::command(slash_command, guild_only)]
+#[tracing::instrument(level = "info")]
+pub async fn open(
+    ctx: Context<'_>,
+    #[description = "Chips everyone sits down with"]
+    #[min = 20]
+    buy_in: i32,
+) -> Result<(), Error> {
+    let author = ctx.author().id.get();
+    let refusal = match robbery_refusal(ctx, author) {
+        Some(refusal) => Some(refusal),
+        None if ctx.data().db.get_balance(author).await? < buy_in => {
+            Some("You don't have enough to buy in.".to_string())
+        }
+        None => None,
+    };
+    if let Some(refusal) = refusal {
+        let reply = {
+            CreateReply::default()
+                .content(refusal)
+                .ephemeral(true)
+                .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users())
+        };
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    let thread = ctx
+        .channel_id()
+        .create_thread(
+            ctx,
+            serenity::CreateThread::new(format!("Poker, {} buy-in", buy_in))
+                .kind(serenity::ChannelType::PublicThread),
+        )
+        .await?;
+    let reply = CreateReply::default().content(format!(
+        "> ### :spades: {} opened a poker table in <#{}>, buy in for {} {}",
+        ctx.author(),
+        thread.id,
+        buy_in,
+        JBUCK_EMOJI
+    ));
+    ctx.send(reply).await?;
+
+    let table = Mutex::new(Poker::new(buy_in));
+    sit(ctx, &table, thread.id, author, buy_in).await?;
+    let result = run_table(ctx, &table, thread.id, buy_in).await;
+
+    // everyone still at the table cashes out, even if the game broke. What they put in a
+    // hand that never finished comes back with their chips.
+    let seats = { table.lock().unwrap().seats.clone() };
+    let mut content = "> ### :spades: The table is closed\n".to_string();
+    let mut failed = None;
+    for seat in seats.iter() {
+        let chips = seat.chips + seat.committed;
+        if let Err(e) = cash_out(ctx, thread.id, seat.player, chips).await {
+            tracing::error!(
+                "Failed to cash out {} from poker {}: {}",
+                seat.player,
+                thread.id,
+                e
+            );
+            let _ = writeln!(content, "> <@{}> couldn't be cashed out", seat.player);
+            failed = Some(e);
+            continue;
+        }
+        let _ = writeln!(
+            content,
+            "> <@{}> cashed out {} {}",
+            seat.player, chips, JBUCK_EMOJI
+        );
+    }
+    thread
+        .id
+        .send_message(
+            ctx,
+            serenity::CreateMessage::new()
+                .content(content)
+                .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users()),
+        )
+        .await?;
+    thread
+        .id
+        .edit_thread(ctx, serenity::EditThread::new().archived(true).locked(true))
+        .await?;
+    match failed {
+        Some(e) => result.and(Err(e)),
+        None => result,
+    }
+}
+
+/// Deals hands for as long as two players with chips are sitting at the table
+async fn run_table(
+    ctx: Context<'_>,
+    table: &Mutex<Poker>,
+    thread: serenity::ChannelId,
+    buy_in: i32,
+) -> Result<(), Error> {
+    let game_length = { ctx.data().config.read().unwrap().game_length_seconds };
+    let db = &ctx.data().db;
+    let mut deadline = from_now(game_length)?;
+    let content = { lobby_message(&table.lock().unwrap(), buy_in, deadline) };
+    let mut message = thread
+        .send_message(
+            ctx,
+            serenity::CreateMessage::new()
+                .content(content)
+                .components(vec![seat_buttons()])
+                .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users()),
+        )
+        .await?;
+    // players who asked to get up during a hand
+    let mut leaving: Vec<u64> = vec![];
+    let mut last_action = String::new();
+    let mut custom_ids = ["poker_sit", "poker_leave", "poker_cards"]
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>();
+    custom_ids.extend(ACTIONS.iter().map(|(id, _, _)| id.to_string()));
+
+    loop {
+        let Some(mci) = serenity::ComponentInteractionCollector::new(ctx)
+            .channel_id(thread)
+            .custom_ids(custom_ids.clone())
+            .timeout(time_left(deadline)?)
+            .await
+        else {
+            let (in_hand, hand_over) = {
+                let game = table.lock().unwrap();
+                (game.street.is_some(), game.is_hand_over())
+            };
+            // short stacks can be all in from the blinds with nothing left to decide
+            if in_hand && !hand_over {
+                let (player, action) = { table.lock().unwrap().act_on_timeout()? };
+                last_action = format!("<@{}> ran out of time and {}", player, action);
+            } else if !in_hand {
+                let g = { table.lock().unwrap().clone() };
+                if g.ready_players() < 2 {
+                    message
+                        .edit(
+                            ctx,
+                            serenity::EditMessage::new()
+                                .content(lobby_message(&g, buy_in, deadline))
+                                .components(vec![]),
+                        )
+                        .await?;
+                    return Ok(());
+                }
+                message
+                    .edit(ctx, serenity::EditMessage::new().components(vec![]))
+                    .await?;
+                deadline = from_now(game_length)?;
+                message = deal(ctx, table, thread, deadline).await?;
+                last_action.clear();
+                continue;
+            }
+            deadline = from_now(game_length)?;
+            if let Some(next) = after_action(
+                ctx,
+                table,
+                thread,
+                &mut message,
+                &mut leaving,
+                &last_action,
+                buy_in,
+                deadline,
+            )
+            .await?
+            {
+                message = next;
+            }
+            continue;
+        };
+        let player = mci.user.id.get();
+
+        match mci.data.custom_id.as_str() {
+            "poker_sit" => {
+                if let Some(refusal) = robbery_refusal(ctx, player) {
+                    ephemeral_response(ctx, &mci, refusal).await?;
+                    continue;
+                }
+                let refusal = {
+                    let game = table.lock().unwrap();
+                    if game.seats.iter().any(|s| s.player == player) {
+                        Some(PokerError::AlreadySeated.to_string())
+                    } else if game.seats.len() >= POKER_SEATS {
+                        Some(PokerError::TableFull.to_string())
+                    } else {
+                        None
+                    }
+                };
+                let refusal = match refusal {
+                    None if db.get_balance(player).await? < buy_in => {
+                        Some("You don't have enough to buy in.".to_string())
+                    }
+                    refusal => refusal,
+                };
+                if let Some(refusal) = refusal {
+                    ephemeral_response(ctx, &mci, refusal).await?;
+                    continue;
+                }
+                sit(ctx, table, thread, player, buy_in).await?;
+                let in_hand = { table.lock().unwrap().street.is_some() };
+                let content = match in_hand {
+                    true => format!(
+                        "You sat down with {} chips, you're dealt in next hand.",
+                        buy_in
+                    ),
+                    false => format!("You sat down with {} chips.", buy_in),
+                };
+                ephemeral_response(ctx, &mci, content).await?;
+            }
+            "poker_leave" => {
+                let content = match get_up(ctx, table, thread, player).await {
+                    Ok(Ok(chips)) => format!("You cashed out {} {}", chips, JBUCK_EMOJI),
+                    Ok(Err(PokerError::HandInProgress)) => {
+                        if !leaving.contains(&player) {
+                            leaving.push(player);
+                        }
+                        "You'll cash out after this hand.".to_string()
+                    }
+                    Ok(Err(e)) => e.to_string(),
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to cash out {} from poker {}: {}",
+                            player,
+                            thread,
+                            e
+                        );
+                        "I couldn't cash you out, you keep your seat for now.".to_string()
+                    }
+                };
+                ephemeral_response(ctx, &mci, content).await?;
+            }
+            "poker_cards" => {
+                let content = {
+                    let game = table.lock().unwrap();
+                    match game.seats.iter().find(|s| s.player == player) {
+                        Some(seat) if !seat.hole.is_empty() && game.street.is_some() => {
+                            let all = [seat.hole.as_slice(), game.board.as_slice()].concat();
+                            format!(
+                                "Your cards: {}\nYou have {}",
+                                cards(&seat.hole),
+                                rank_hand(&all).category
+                            )
+                        }
+                        Some(_) => "You're not in this hand.".to_string(),
+                        None => PokerError::NotSeated.to_string(),
+                    }
+                };
+                ephemeral_response(ctx, &mci, content).await?;
+                continue;
+            }
+            id => {
+                let action = ACTIONS
+                    .iter()
+                    .find(|(action_id, _, _)| *action_id == id)
+                    .map(|(_, _, action)| *action)
+                    .unwrap();
+                let acted = { table.lock().unwrap().act(player, action) };
+                if let Err(e) = acted {
+                    ephemeral_response(ctx, &mci, e.to_string()).await?;
+                    continue;
+                }
+                mci.create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+                    .await?;
+                last_action = format!("<@{}> {}", player, action);
+                deadline = from_now(game_length)?;
+                if let Some(next) = after_action(
+                    ctx,
+                    table,
+                    thread,
+                    &mut message,
+                    &mut leaving,
+                    &last_action,
+                    buy_in,
+                    deadline,
+                )
+                .await?
+                {
+                    message = next;
+                }
+                continue;
+            }
+        }
+
+        // sitting down or getting up between hands changes the lobby
+        let g = { table.lock().unwrap().clone() };
+        let content = match g.street {
+            Some(_) => table_message(&g, &last_action, deadline),
+            None => lobby_message(&g, buy_in, deadline),
+        };
+        message
+            .edit(ctx, serenity::EditMessage::new().content(content))
+            .await?;
+    }
+}
+
+/// Updates the table after someone acted. Once the hand is over the pots are paid out,
+/// the leavers cash out and the next hand is dealt, or the lobby opens if it can't be.
+#[allow(clippy::too_many_arguments)]
+async fn after_action(
+    ctx: Context<'_>,
+    table: &Mutex<Poker>,
+    thread: serenity::ChannelId,
+    message: &mut serenity::Message,
+    leaving: &mut Vec<u64>,
+    last_action: &str,
+    buy_in: i32,
+    deadline: u64,
+) -> Result<Option<serenity::Message>, Error> {
+    let g = { table.lock().unwrap().clone() };
+    if !g.is_hand_over() {
+        message
+            .edit(
+                ctx,
+                serenity::EditMessage::new().content(table_message(&g, last_action, deadline)),
+            )
+            .await?;
+        return Ok(None);
+    }
+
+    let result = { table.lock().unwrap().settle()? };
+    message
+        .edit(
+            ctx,
+            serenity::EditMessage::new()
+                .content(table_message(&g, last_action, deadline))
+                .components(vec![]),
+        )
+        .await?;
+
+    let mut content = "> ### :spades: Hand over\n".to_string();
+    for (player, rank) in result.shown.iter() {
+        let hole = g
+            .seats
+            .iter()
+            .find(|s| s.player == *player)
+            .map(|s| cards(&s.hole))
+            .unwrap_or_default();
+        let _ = writeln!(
+            content,
+            "> <@{}> shows {} for {}",
+            player, hole, rank.category
+        );
+    }
+    for (player, won) in result.winnings.iter() {
+        let _ = writeln!(content, "> <@{}> wins {} chips", player, won);
+    }
+    // the rake already came out of the pots, only the crown's cut is left to pay
+    let crowned = crown_holder(&ctx.data().db, ctx.data().crown_role_id, result.rake).await?;
+    ctx.data()
+        .db
+        .settle_escrows(
+            &[],
+            &[],
+            crowned.map(|crown| (crown, result.rake)),
+            Reason::Poker.with_id(thread),
+        )
+        .await?;
+    if let Some(crowned) = crowned {
+        let _ = writeln!(
+            content,
+            "> {} {} was paid to <@{}>! (Crown's Tax)",
+            result.rake, JBUCK_EMOJI, crowned
+        );
+    }
+
+    // busted players get up with the ones who asked to
+    let mut leavers = std::mem::take(leaving);
+    let busted = {
+        let game = table.lock().unwrap();
+        game.seats
+            .iter()
+            .filter(|s| s.chips == 0)
+            .map(|s| s.player)
+            .collect::<Vec<_>>()
+    };
+    for player in busted {
+        if !leavers.contains(&player) {
+            leavers.push(player);
+        }
+    }
+    for player in leavers {
+        match get_up(ctx, table, thread, player).await {
+            Ok(Ok(chips)) => {
+                let _ = writeln!(
+                    content,
+                    "> <@{}> cashed out {} {}",
+                    player, chips, JBUCK_EMOJI
+                );
+            }
+            Ok(Err(_)) => {}
+            Err(e) => {
+                tracing::error!("Failed to cash out {} from poker {}: {}", player, thread, e);
+                let _ = writeln!(
+                    content,
+                    "> <@{}> couldn't be cashed out and keeps their seat",
+                    player
+                );
+            }
+        }
+    }
+    thread
+        .send_message(
+            ctx,
+            serenity::CreateMessage::new()
+                .content(content)
+                .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users()),
+        )
+        .await?;
+
+    let g = { table.lock().unwrap().clone() };
+    let next = match g.ready_players() {
+        0 | 1 => {
+            thread
+                .send_message(
+                    ctx,
+                    serenity::CreateMessage::new()
+                        .content(lobby_message(&g, buy_in, deadline))
+                        .components(vec![seat_buttons()])
+                        .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users()),
+                )
+                .await?
+        }
+        _ => deal(ctx, table, thread, deadline).await?,
+    };
+    Ok(Some(next))
+}
+
+/// Starts a hand and posts its table, pinging whoever acts first
+async fn deal(
+    ctx: Context<'_>,
+    table: &Mutex<Poker>,
+    thread: serenity::ChannelId,
+    deadline: u64,
+) -> Result<serenity::Message, Error> {
+    let g = {
+        let shoe = Shoe::new(1, &mut *ctx.data().rng.lock().unwrap());
+        let mut game = table.lock().unwrap();
+        game.start_hand(shoe)?;
+        game.clone()
+    };
+    let mut components = vec![action_buttons()];
+    components.push(seat_buttons());
+    let message = thread
+        .send_message(
+            ctx,
+            serenity::CreateMessage::new()
+                .content(table_message(&g, "", deadline))
+                .components(components)
+                .allowed_mentions(
+                    serenity::CreateAllowedMentions::new()
+                        .users(g.to_act().map(serenity::UserId::new)),
+                ),
+        )
+        .await?;
+    Ok(message)
+}
+
+/// Takes the buy-in out of the player's balance and seats them with it as chips
+async fn sit(
+    ctx: Context<'_>,
+    table: &Mutex<Poker>,
+    thread: serenity::ChannelId,
+    player: u64,
+    buy_in: i32,
+) -> Result<(), Error> {
+    ctx.data()
+        .db
+        .escrow_stake(
+            player,
+            buy_in,
+            Reason::Poker.with_id(seat_id(thread, player)),
+        )
+        .await?;
+    let sat = { table.lock().unwrap().sit(player, buy_in) };
+    if let Err(e) = sat {
+        ctx.data()
+            .db
+            .refund_escrow(&seat_id(thread, player))
+            .await?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Gets the player up from the table and pays their chips back. They're put back in their
+/// seat when the payout fails, so the chips are still cashed out when the table closes.
+async fn get_up(
+    ctx: Context<'_>,
+    table: &Mutex<Poker>,
+    thread: serenity::ChannelId,
+    player: u64,
+) -> Result<Result<i32, PokerError>, Error> {
+    let (left, seated) = {
+        let mut game = table.lock().unwrap();
+        let seated = game.clone();
+        (game.leave(player), seated)
+    };
+    let chips = match left {
+        Ok(chips) => chips,
+        Err(e) => return Ok(Err(e)),
+    };
+    if let Err(e) = cash_out(ctx, thread, player, chips).await {
+        *table.lock().unwrap() = seated;
+        return Err(e);
+    }
+    Ok(Ok(chips))
+}
+
+/// Pays the player's chips back into their balance
+async fn cash_out(
+    ctx: Context<'_>,
+    thread: serenity::ChannelId,
+    player: u64,
+    chips: i32,
+) -> Result<(), Error> {
+    ctx.data()
+        .db
+        .settle_escrow(
+            &seat_id(thread, player),
+            &[(player, chips)],
+            Reason::Poker.with_id(seat_id(thread, player)),
+        )
+        .await
+}
+
+/// The escrow a player's buy-in is held under while they're at the table
+fn seat_id(thread: serenity::ChannelId, player: u64) -> String {
+    format!("poker-{}-{}", thread, player)
+}
+
+fn from_now(seconds: i32) -> Result<u64, Error> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + seconds as u64)
+}
+
+fn cards(cards: &[Card]) -> String {
+    cards
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn seat_lines(game: &Poker) -> String {
+    let mut content = String::new();
+    for seat in game.seats.iter() {
+        let mut status = vec![];
+        if game.button() == Some(seat.player) {
+            status.push("dealer".to_string());
+        }
+        if seat.bet > 0 {
+            status.push(format!("bet {}", seat.bet));
+        }
+        if game.street.is_some() && !seat.in_hand {
+            status.push("sitting out".to_string());
+        } else if seat.folded {
+            status.push("folded".to_string());
+        } else if game.street.is_some() && seat.chips == 0 {
+            status.push("all in".to_string());
+        }
+        let _ = write!(content, "> <@{}>: {} chips", seat.player, seat.chips);
+        if !status.is_empty() {
+            let _ = write!(content, " ({})", status.join(", "));
+        }
+        content.push('\n');
+    }
+    content
+}
+
+fn lobby_message(game: &Poker, buy_in: i32, deadline: u64) -> String {
+    let mut content = format!(
+        "> ### :spades: Poker, buy in for {} {}\n> Blinds {}/{}, {}/{} seats taken\n",
+        buy_in,
+        JBUCK_EMOJI,
+        game.small_blind,
+        game.big_blind,
+        game.seats.len(),
+        POKER_SEATS
+    );
+    content.push_str(&seat_lines(game));
+    if game.ready_players() < 2 && time_left(deadline).is_ok_and(|left| left.is_zero()) {
+        content.push_str("> **Not enough players, the table is closing**");
+    } else {
+        let _ = write!(content, "> **Cards are dealt <t:{}:R>**", deadline);
+    }
+    content
+}
+
+/// The table mid-hand, hole cards stay hidden until the showdown
+fn table_message(game: &Poker, last_action: &str, deadline: u64) -> String {
+    let street = match game.street {
+        Some(Street::Preflop) => "Preflop",
+        Some(Street::Flop) => "Flop",
+        Some(Street::Turn) => "Turn",
+        Some(Street::River) => "River",
+        Some(Street::Showdown) | None => "Showdown",
+    };
+    let board = match game.board.is_empty() {
+        true => "🂠 🂠 🂠".to_string(),
+        false => cards(&game.board),
+    };
+    let mut content = format!(
+        "> ### :spades: {}: {}\n> Pot: {} chips\n",
+        street,
+        board,
+        game.pot()
+    );
+    content.push_str(&seat_lines(game));
+    if !last_action.is_empty() {
+        let _ = writeln!(content, "> {}", last_action);
+    }
+    if let Some(player) = game.to_act() {
+        let _ = write!(content, "> **<@{}> to act", player);
+        match game.to_call(player) {
+            0 => {}
+            to_call => {
+                let _ = write!(content, ", {} to call", to_call);
+            }
+        }
+        let _ = write!(content, " <t:{}:R>**", deadline);
+    }
+    content
+}
+
+fn seat_buttons() -> serenity::CreateActionRow {
+    serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new("poker_sit")
+            .label("Sit")
+            .style(serenity::ButtonStyle::Success),
+        serenity::CreateButton::new("poker_leave")
+            .label("Leave")
+            .style(serenity::ButtonStyle::Secondary),
+        serenity::CreateButton::new("poker_cards")
+            .label("My cards")
+            .style(serenity::ButtonStyle::Primary)
+            .emoji(serenity::ReactionType::Unicode("🃏".to_string())),
+    ])
+}
+
+fn action_buttons() -> serenity::CreateActionRow {
+    serenity::CreateActionRow::Buttons(
+        ACTIONS
+            .iter()
+            .map(|(id, label, action)| {
+                let style = match action {
+                    PokerAction::Fold => serenity::ButtonStyle::Danger,
+                    PokerAction::AllIn => serenity::ButtonStyle::Success,
+                    _ => serenity::ButtonStyle::Primary,
+                };
+                serenity::CreateButton::new(*id).label(*label).style(style)
+            })
+            .collect(),
+    )
+}
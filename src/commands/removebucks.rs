@@ -1,4 +1,4 @@
-use crate::database::BalanceDatabase;
+use crate::database::{BalanceDatabase, Reason};
 use crate::{Context, Error};
 use poise::serenity_prelude::User;
 use poise::CreateReply;
@@ -46,7 +46,11 @@ pub async fn remove_bucks(
     }
     ctx.data()
         .db
-        .subtract_balances(vec![user_id.parse().unwrap()], amount)
+        .subtract_balances(
+            vec![user_id.parse().unwrap()],
+            amount,
+            Reason::RemoveBucks.into(),
+        )
         .await?;
 
     let reply =
@@ -1,5 +1,6 @@
 use crate::{
-    database::BalanceDatabase, database::RobberyDatabase, database::RoleDatabase, Context, Error,
+    database::BalanceDatabase, database::Currency, database::Effect, database::EffectDatabase,
+    database::Reason, database::RobberyDatabase, database::RoleDatabase, Context, Error,
 };
 use chrono::{Datelike, NaiveDate, NaiveTime};
 use poise::serenity_prelude;
@@ -143,28 +144,48 @@ pub async fn wrapped_robbing_event(
         tracing::info!("locked balances not empty, aborting robbing event");
         return Ok(());
     }
-    let leaderboard = ctx.data().db.get_leaderboard().await?;
-    let chosen_players = leaderboard
-        .choose_multiple(&mut rand::thread_rng(), 4)
-        .cloned()
-        .collect::<Vec<_>>();
-
-    let mut named_players = HashMap::new();
-    let mut abort = false;
+    let mut leaderboard = ctx.data().db.get_leaderboard().await?;
+    let mut unshielded = 0;
+    for player in leaderboard.iter() {
+        if !ctx
+            .data()
+            .db
+            .has_effect(player.0, Effect::RobberyShield)
+            .await?
+        {
+            unshielded += 1;
+        }
+    }
+    if unshielded < 4 {
+        let reply = {
+            poise::CreateReply::default()
+                .content("Too many players are shielded, there's nobody left to rob.")
+                .ephemeral(true)
+        };
+        ctx.send(reply).await?;
+        return Ok(());
+    }
 
-    {
-        let mut locked = ctx.data().locked_balances.lock().unwrap();
-        for player in chosen_players.iter() {
-            if player.1 == 0 {
-                // clear locked balances
-                ctx.data().locked_balances.lock().unwrap().clear();
-                abort = true;
-            }
-            locked.insert(player.0);
+    leaderboard.shuffle(&mut rand::thread_rng());
+    let mut chosen_players = vec![];
+    let mut shielded = vec![];
+    for player in leaderboard {
+        if chosen_players.len() == 4 {
+            break;
+        }
+        if ctx
+            .data()
+            .db
+            .has_effect(player.0, Effect::RobberyShield)
+            .await?
+        {
+            shielded.push(player.0);
+        } else {
+            chosen_players.push(player);
         }
     }
 
-    if abort {
+    if chosen_players.iter().any(|player| player.1 == 0) {
         let reply = {
             poise::CreateReply::default()
                 .content("One of the chosen players has no money, so we're skipping this round.")
@@ -173,6 +194,22 @@ pub async fn wrapped_robbing_event(
         ctx.send(reply).await?;
         return Ok(());
     }
+
+    // the robbery goes ahead, so the shields that kept their owners out of it are used up
+    for player in shielded.iter() {
+        ctx.data()
+            .db
+            .consume_effect(*player, Effect::RobberyShield)
+            .await?;
+    }
+
+    let mut named_players = HashMap::new();
+    {
+        let mut locked = ctx.data().locked_balances.lock().unwrap();
+        for player in chosen_players.iter() {
+            locked.insert(player.0);
+        }
+    }
     let players = { ctx.data().locked_balances.lock().unwrap().clone() };
     for player in players {
         let name = get_discord_name(ctx, player).await;
@@ -208,14 +245,30 @@ pub async fn wrapped_robbing_event(
         Some(u) => format!("{} has started a wealth redistribution!", u),
         None => "Time for some wealth redistribution!".to_string(),
     };
+    let shields = if shielded.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            "\n> :shield: {} hid behind a robbery shield",
+            shielded
+                .iter()
+                .map(|player| format!("<@{}>", player))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
     let reply = {
         CreateMessage::default()
             .content(format!(
-                    "> ### <:jbuck:1228663982462865450> {}\n> Which one of these players could spare a couple of bucks?\n > **Voting Ends: **<t:{}:R>", msg, now+time_to_play as u64))
+                    "> ### <:jbuck:1228663982462865450> {}\n> Which one of these players could spare a couple of bucks?{}\n > **Voting Ends: **<t:{}:R>", msg, shields, now+time_to_play as u64))
             .components(components.clone())
     };
 
     let mut id = ctx.channel_id().send_message(ctx, reply).await?;
+    ctx.data()
+        .db
+        .link_transactions(&id.id.to_string(), &id.link())
+        .await?;
     let mut votes: HashMap<String, Vec<String>> = HashMap::new();
     let mut already_voted: HashSet<String> = HashSet::new();
 
@@ -308,7 +361,7 @@ pub async fn wrapped_robbing_event(
 
     let reply = {
         EditMessage::default()
-            .content(format!("> ### <:jbuck:1228663982462865450> {}\n> Which one of these players could spare a couple of bucks?\n > **Voting Has Ended!**", msg))
+            .content(format!("> ### <:jbuck:1228663982462865450> {}\n> Which one of these players could spare a couple of bucks?{}\n > **Voting Has Ended!**", msg, shields))
             .components(components.clone())
     };
 
@@ -367,9 +420,9 @@ pub async fn wrapped_robbing_event(
     let percentage_to_steal = ctx.data().rng.lock().unwrap().gen_range(5..=25);
 
     let balance = ctx.data().db.get_balance(player.parse().unwrap()).await?;
-    let stolen = balance * percentage_to_steal / 100;
-
-    let each = stolen / robbers.len() as i32;
+    let each = balance * percentage_to_steal / 100 / robbers.len() as i32;
+    // only take what can be split evenly so no bucks vanish
+    let stolen = each * robbers.len() as i32;
 
     let victim_name = named_players.get(&player.parse().unwrap()).unwrap().clone();
 
@@ -388,13 +441,15 @@ pub async fn wrapped_robbing_event(
         return Ok(());
     }
 
+    let to: Vec<(u64, i32)> = robbers.iter().map(|z| (z.parse().unwrap(), each)).collect();
     ctx.data()
         .db
-        .award_balances(robbers.iter().map(|z| z.parse().unwrap()).collect(), each)
-        .await?;
-    ctx.data()
-        .db
-        .subtract_balances(vec![player.parse().unwrap()], stolen)
+        .transfer(
+            player.parse().unwrap(),
+            &to,
+            Currency::Bucks,
+            Reason::Robbery.with_id(id.id),
+        )
         .await?;
 
     let mut text = format!("> ### <:jbuck:1228663982462865450> {}\n> I hope you are proud {}.\n> **You {}get {} <:jbuck:1228663982462865450>!**",
@@ -418,7 +473,7 @@ pub async fn wrapped_robbing_event(
     )
     .await;
 
-    let names_map: HashMap<String, String> = HashMap::from_iter(names.into_iter());
+    let names_map: HashMap<String, String> = HashMap::from_iter(names);
 
     let formatted_other_votes = votes
         .iter()
@@ -1,6 +1,9 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{database::BalanceDatabase, database::RoleDatabase, Context, Error};
+use crate::{
+    database::BalanceDatabase, database::Database, database::EscrowDatabase, database::Reason,
+    database::RoleDatabase, Context, Error,
+};
 use poise::CreateReply;
 
 use ::poise::serenity_prelude::{self as serenity};
@@ -71,16 +74,42 @@ pub async fn rpsgamble(
         return Err("Not enough money".into());
     }
 
+    let game_id = ctx.id().to_string();
     if amount > 0 {
         ctx.data()
             .db
-            .subtract_balances(vec![ctx.author().id.get()], amount)
+            .escrow_stake(
+                ctx.author().id.get(),
+                amount,
+                Reason::RpsGamble.with_id(ctx.id()),
+            )
             .await?;
 
         let mut locked = ctx.data().locked_balances.lock().unwrap();
         locked.insert(ctx.author().id.get());
     }
 
+    let result = play(ctx, amount, &user, choice, now, time_to_play).await;
+    if result.is_err() {
+        // whatever broke the game, the stakes it still holds go back
+        ctx.data().db.refund_escrow(&game_id).await?;
+    }
+    {
+        let mut locked = ctx.data().locked_balances.lock().unwrap();
+        locked.remove(&ctx.author().id.get());
+    }
+    result
+}
+
+async fn play(
+    ctx: Context<'_>,
+    amount: i32,
+    user: &serenity::User,
+    choice: RPSChoice,
+    now: u64,
+    time_to_play: i32,
+) -> Result<(), Error> {
+    let game_id = ctx.id().to_string();
     ctx.send(CreateReply::default().content("success").ephemeral(true))
         .await?;
 
@@ -109,6 +138,10 @@ pub async fn rpsgamble(
     };
 
     let mut message = ctx.channel_id().send_message(ctx, reply).await?;
+    ctx.data()
+        .db
+        .link_transactions(&ctx.id().to_string(), &message.link())
+        .await?;
 
     if user.id.get() == ctx.data().bot_id {
         let reply = {
@@ -119,6 +152,11 @@ pub async fn rpsgamble(
                 ))
                 .reference_message(&message)
         };
+        // the house always wins
+        ctx.data()
+            .db
+            .settle_escrow(&game_id, &[], Reason::RpsGamble.with_id(ctx.id()))
+            .await?;
         ctx.channel_id().send_message(ctx, reply).await?;
         return Ok(());
     }
@@ -160,15 +198,7 @@ pub async fn rpsgamble(
         }
         let balance = { ctx.data().db.get_balance(user.id.get()).await? };
         if amount > balance {
-            {
-                ctx.data()
-                    .db
-                    .award_balances(vec![ctx.author().id.get()], amount)
-                    .await?;
-
-                let mut locked = ctx.data().locked_balances.lock().unwrap();
-                locked.remove(&ctx.author().id.get());
-            }
+            ctx.data().db.refund_escrow(&game_id).await?;
             let content = message.content.clone();
             message
                 .edit(
@@ -199,6 +229,12 @@ pub async fn rpsgamble(
             "scissors" => Some(RPSChoice::Scissors),
             _ => unreachable!(),
         };
+        if amount > 0 {
+            ctx.data()
+                .db
+                .escrow_stake(user.id.get(), amount, Reason::RpsGamble.with_id(ctx.id()))
+                .await?;
+        }
         // acknowledge the interaction
         mci.create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
             .await?;
@@ -209,14 +245,7 @@ pub async fn rpsgamble(
         Some(RPSChoice::Paper) => 1,
         Some(RPSChoice::Scissors) => 2,
         None => {
-            ctx.data()
-                .db
-                .award_balances(vec![ctx.author().id.get()], amount)
-                .await?;
-            {
-                let mut locked = ctx.data().locked_balances.lock().unwrap();
-                locked.remove(&ctx.author().id.get());
-            }
+            ctx.data().db.refund_escrow(&game_id).await?;
             let content = message.content.clone();
 
             message
@@ -249,10 +278,7 @@ pub async fn rpsgamble(
     let prize = (amount * 2) - ((amount * 2) as f32 * 0.02).ceil() as i32;
     let msg = match result {
         0 => {
-            ctx.data()
-                .db
-                .award_balances(vec![ctx.author().id.get()], amount)
-                .await?;
+            ctx.data().db.refund_escrow(&game_id).await?;
             format!(
                 "{} and {} both chose {}\nit is a tie!{}",
                 ctx.author(),
@@ -266,16 +292,19 @@ pub async fn rpsgamble(
             )
         }
         1 => {
+            // the winnings and the crown's tax settle together
+            let crowned = crown_holder(&ctx.data().db, ctx.data().crown_role_id, tax).await?;
             ctx.data()
                 .db
-                .award_balances(vec![ctx.author().id.get()], prize)
-                .await?;
-            ctx.data()
-                .db
-                .subtract_balances(vec![user.id.get()], amount)
+                .settle_escrows(
+                    &[&game_id],
+                    &[(ctx.author().id.get(), prize)],
+                    crowned.map(|crown| (crown, tax)),
+                    Reason::RpsGamble.with_id(ctx.id()),
+                )
                 .await?;
 
-            let tax_msg = if let Some(crowned) = award_role_holder(ctx, tax).await? {
+            let tax_msg = if let Some(crowned) = crowned {
                 format!(
                     "{} <:jbuck:1228663982462865450>  was paid to <@{}>! (Crown's Tax)",
                     tax, crowned
@@ -305,11 +334,18 @@ pub async fn rpsgamble(
             )
         }
         2 => {
+            // the winnings and the crown's tax settle together
+            let crowned = crown_holder(&ctx.data().db, ctx.data().crown_role_id, tax).await?;
             ctx.data()
                 .db
-                .award_balances(vec![user.id.get()], prize)
+                .settle_escrows(
+                    &[&game_id],
+                    &[(user.id.get(), prize)],
+                    crowned.map(|crown| (crown, tax)),
+                    Reason::RpsGamble.with_id(ctx.id()),
+                )
                 .await?;
-            let tax_msg = if let Some(crowned) = award_role_holder(ctx, tax).await? {
+            let tax_msg = if let Some(crowned) = crowned {
                 format!(
                     "{} <:jbuck:1228663982462865450>  was paid to <@{}>! (Crown's Tax)",
                     tax, crowned
@@ -361,10 +397,6 @@ pub async fn rpsgamble(
             )
     };
     ctx.channel_id().send_message(ctx, reply).await?;
-    {
-        let mut locked = ctx.data().locked_balances.lock().unwrap();
-        locked.remove(&ctx.author().id.get());
-    }
     Ok(())
 }
 
@@ -384,22 +416,18 @@ fn new_scissors_button() -> serenity::CreateButton {
         .style(poise::serenity_prelude::ButtonStyle::Primary)
 }
 
-pub async fn award_role_holder(ctx: Context<'_>, amount: i32) -> Result<Option<u64>, Error> {
+/// Who the crown's cut of `amount` goes to, None when there's nothing to pay or nobody
+/// wears the crown
+pub async fn crown_holder(
+    db: &Database,
+    crown_role_id: u64,
+    amount: i32,
+) -> Result<Option<u64>, Error> {
     if amount == 0 {
         return Ok(None);
     }
-    if let Some(user) = ctx
-        .data()
-        .db
-        .get_unique_role_holder(ctx.data().crown_role_id)
+    Ok(db
+        .get_unique_role_holder(crown_role_id)
         .await?
-    {
-        ctx.data()
-            .db
-            .award_balances(vec![user.user_id], amount)
-            .await?;
-        Ok(Some(user.user_id))
-    } else {
-        Ok(None)
-    }
+        .map(|holder| holder.user_id))
 }
@@ -0,0 +1,225 @@
+use chrono::{DateTime, Utc};
+use poise::{
+    serenity_prelude::{self as serenity, CreateAllowedMentions},
+    CreateReply,
+};
+
+use crate::{
+    commands::auction,
+    database::{self, Reason, RoleDatabase},
+    discord::JBUCK_EMOJI,
+    Context, Error,
+};
+
+/// Button in the rental reminder DM, `renew-role-<guild>-<role>`
+pub const RENEW_BUTTON: &str = "renew-role-";
+
+///
+/// rent roles and see who held the unique ones
+///
+#[poise::command(slash_command, subcommands("rent", "rentals", "history"))]
+pub async fn role(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+///
+/// see everyone who held a unique role
+///
+/// Enter `/role history <role>`
+/// ```
+/// /role history @Johnny'sChosen
+/// ```
+#[poise::command(slash_command)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "A role only one person can have"] role: poise::serenity_prelude::Role,
+) -> Result<(), Error> {
+    if !ctx.data().unique_roles.lock().unwrap().contains(&role.id) {
+        let reply = CreateReply::default()
+            .content(format!("{} isn't a unique role", role))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    let tenures = ctx.data().db.get_role_history(role.id.get(), 15).await?;
+    if tenures.is_empty() {
+        let reply = CreateReply::default()
+            .content(format!("Nobody has had {} yet!", role))
+            .ephemeral(true);
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    let mut response = format!("> ### {} owners\n", role.name);
+    for tenure in tenures {
+        let paid = match tenure.price {
+            Some(price) => format!(" for {} {}", price, JBUCK_EMOJI),
+            None => "".to_string(),
+        };
+        let until = match (tenure.lost, tenure.lost_how) {
+            (Some(lost), Some(how)) => format!("until <t:{}:R> ({})", lost.timestamp(), how),
+            (Some(lost), None) => format!("until <t:{}:R>", lost.timestamp()),
+            _ => "and still holds it".to_string(),
+        };
+        response.push_str(&format!(
+            "> <@{}> got it <t:{}:R>{} {}\n",
+            tenure.user_id,
+            tenure.acquired.timestamp(),
+            paid,
+            until
+        ));
+    }
+
+    let reply = CreateReply::default()
+        .content(response)
+        .allowed_mentions(CreateAllowedMentions::new().empty_users());
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// Charges the user for renting the role and gives it to them, adding to the rental if
+/// they already have one. The inner error is why they can't, to show the user.
+pub async fn rent_role(
+    http: &serenity::Http,
+    db: &database::Database,
+    guild_id: serenity::GuildId,
+    user_id: serenity::UserId,
+    role_id: serenity::RoleId,
+) -> Result<Result<DateTime<Utc>, String>, Error> {
+    let roles = db.get_purchasable_roles().await?;
+    let role = match roles.iter().find(|r| r.role_id == role_id.get()) {
+        Some(role) => role,
+        None => return Ok(Err("That role isn't for sale!".to_string())),
+    };
+    let (price, days) = match role.rent() {
+        Some(rent) => rent,
+        None => return Ok(Err("That role can't be rented!".to_string())),
+    };
+    if auction::is_auctioned(db, role_id.get()).await? {
+        return Ok(Err("That role is up for auction!".to_string()));
+    }
+
+    let member = guild_id.member(http, user_id).await?;
+    if let Some(required) = role.required_role_id {
+        if !member.roles.contains(&serenity::RoleId::new(required)) {
+            return Ok(Err(format!(
+                "You need the role <@&{}> to rent this role!",
+                required
+            )));
+        }
+    }
+
+    let rental = db.get_rental(role_id.get(), user_id.get()).await?;
+    if rental.is_none() && member.roles.contains(&role_id) {
+        return Ok(Err("You already have that role!".to_string()));
+    }
+    // renewing early adds the days on top of what's left
+    let now = Utc::now();
+    let previous = rental.map(|rental| rental.expires_at);
+    let from = match previous {
+        Some(expires_at) if expires_at > now => expires_at,
+        _ => now,
+    };
+    let expires_at = from + chrono::Duration::days(days as i64);
+    let charged = db
+        .set_rental(
+            role_id.get(),
+            user_id.get(),
+            guild_id.get(),
+            expires_at,
+            price,
+            Reason::RoleRental.with_id(role_id),
+        )
+        .await?;
+    if !charged {
+        return Ok(Err(format!(
+            "You can't afford to rent that role! You need {} {}!",
+            price, JBUCK_EMOJI
+        )));
+    }
+    if let Err(e) = http
+        .add_member_role(guild_id, user_id, role_id, Some("Renting a role"))
+        .await
+    {
+        db.undo_rental(
+            role_id.get(),
+            user_id.get(),
+            previous,
+            price,
+            Reason::RoleRental.with_id(role_id),
+        )
+        .await?;
+        return Err(e.into());
+    }
+    Ok(Ok(expires_at))
+}
+
+///
+/// rent a role for a while
+///
+/// Enter `/role rent <role>`, renting a role you're already renting adds more time
+/// ```
+/// /role rent @VIP
+/// ```
+#[poise::command(slash_command)]
+pub async fn rent(
+    ctx: Context<'_>,
+    #[description = "The role to rent"] role: poise::serenity_prelude::Role,
+) -> Result<(), Error> {
+    let result = rent_role(
+        &ctx.serenity_context().http,
+        &ctx.data().db,
+        ctx.guild_id()
+            .ok_or("Roles can only be rented in a server")?,
+        ctx.author().id,
+        role.id,
+    )
+    .await?;
+
+    let reply = match result {
+        Ok(expires_at) => CreateReply::default().content(format!(
+            "{} rented {} until <t:{}:f>!",
+            ctx.author(),
+            role,
+            expires_at.timestamp()
+        )),
+        Err(e) => CreateReply::default().content(e).ephemeral(true),
+    };
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+///
+/// see the roles you're renting
+///
+/// Enter `/role rentals`
+/// ```
+/// /role rentals
+/// ```
+#[poise::command(slash_command)]
+pub async fn rentals(ctx: Context<'_>) -> Result<(), Error> {
+    let rentals = ctx
+        .data()
+        .db
+        .get_user_rentals(ctx.author().id.get())
+        .await?;
+    let content = if rentals.is_empty() {
+        "You aren't renting any roles".to_string()
+    } else {
+        rentals
+            .iter()
+            .map(|r| {
+                format!(
+                    "> <@&{}> until <t:{}:f>",
+                    r.role_id,
+                    r.expires_at.timestamp()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let reply = CreateReply::default().content(content).ephemeral(true);
+    ctx.send(reply).await?;
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::database::BalanceDatabase;
 use crate::{Context, Error};
 
+#[allow(dead_code)]
 #[derive(Debug, poise::ChoiceParameter, Clone)]
 pub enum SacrificeReasons {
     Robbery,
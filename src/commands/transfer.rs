@@ -1,4 +1,4 @@
-use crate::database::BalanceDatabase;
+use crate::database::{BalanceDatabase, Currency, Reason};
 use crate::{Context, Error};
 use poise::serenity_prelude::User;
 use poise::CreateReply;
@@ -41,7 +41,6 @@ pub async fn transfer(
         ctx.send(reply).await?;
         return Err("You can't do that".into());
     }
-    let user_id = source.id.to_string();
     let user_balance = ctx.data().db.get_balance(source.id.get()).await?;
     if user_balance < amount {
         let reply = {
@@ -55,14 +54,15 @@ pub async fn transfer(
         ctx.send(reply).await?;
         return Err("can't afford to do that".into());
     }
-    let recipient_id = recipient.id.to_string();
+    ctx.data().db.get_balance(recipient.id.get()).await?;
     ctx.data()
         .db
-        .subtract_balances(vec![user_id.parse().unwrap()], amount)
-        .await?;
-    ctx.data()
-        .db
-        .award_balances(vec![recipient_id.parse().unwrap()], amount)
+        .transfer(
+            source.id.get(),
+            &[(recipient.id.get(), amount)],
+            Currency::Bucks,
+            Reason::Transfer.with_id(ctx.id()),
+        )
         .await?;
 
     let reply = {
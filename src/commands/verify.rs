@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+use poise::{serenity_prelude as serenity, CreateReply};
+
+use crate::{
+    database::{LotteryDatabase, SeedDatabase},
+    fairness,
+    game::{CoinGame, Lottery},
+    Context, Error,
+};
+
+fn param<T: FromStr>(params: &str, key: &str) -> Option<T> {
+    params
+        .split(';')
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| *k == key)
+        .and_then(|(_, v)| v.parse().ok())
+}
+
+///
+/// Replay a coin game or lottery draw from its revealed seed
+///
+/// Enter `/verify <game id>`, the id is shown under every result
+/// ```
+/// /verify lottery-12
+/// ```
+#[poise::command(slash_command)]
+#[tracing::instrument(level = "info")]
+pub async fn verify(
+    ctx: Context<'_>,
+    #[description = "The game id from the result message"] game_id: String,
+) -> Result<(), Error> {
+    let db = &ctx.data().db;
+    let seed = match db.get_seed(&game_id).await? {
+        Some(s) => s,
+        None => {
+            let reply = CreateReply::default()
+                .content(format!("There is no game with id `{}`", game_id))
+                .ephemeral(true);
+            ctx.send(reply).await?;
+            return Ok(());
+        }
+    };
+
+    let mut lines = vec![
+        format!("> ### Game `{}` ({})", seed.game_id, seed.game),
+        format!("> **Commitment:** `{}`", seed.commitment),
+    ];
+
+    let outcome = match (&seed.outcome, seed.revealed_at) {
+        (Some(outcome), Some(revealed_at)) => {
+            lines.push(format!(
+                "> **Seed:** `{}` (revealed <t:{}:R>)",
+                seed.seed,
+                revealed_at.timestamp()
+            ));
+            outcome.clone()
+        }
+        _ => {
+            lines.push("> Not drawn yet, the seed is revealed with the result".to_string());
+            let reply = CreateReply::default().content(lines.join("\n"));
+            ctx.send(reply).await?;
+            return Ok(());
+        }
+    };
+
+    if outcome == "rollover" {
+        lines.push("> Too few tickets were sold, the pot rolled over without a draw".to_string());
+        let reply = CreateReply::default().content(lines.join("\n"));
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    let committed = fairness::commitment(&seed.seed) == seed.commitment;
+    lines.push(format!(
+        "> sha256(seed) {} the commitment {}",
+        if committed {
+            "matches"
+        } else {
+            "does not match"
+        },
+        if committed {
+            ":white_check_mark:"
+        } else {
+            ":x:"
+        }
+    ));
+    lines.push(format!("> -# {}", fairness::DERIVATION));
+
+    let params = seed.params.clone().unwrap_or_default();
+    let recomputed = match seed.game.as_str() {
+        "coingamble" => {
+            let side_chance = param::<u32>(&params, "side_chance").ok_or("missing side chance")?;
+            let heads_odds = param::<f32>(&params, "heads_odds").ok_or("missing heads odds")?;
+            lines.push(format!(
+                "> **Inputs:** side chance {}%, heads odds {:.2}\n> Side if roll 0 < {:.2}, otherwise heads if roll 1 < {:.2}\n> Roll 0: {:.6} | Roll 1: {:.6}",
+                side_chance,
+                heads_odds,
+                side_chance as f64 / 100.0,
+                heads_odds,
+                fairness::roll(&seed.seed, &seed.game_id, 0),
+                fairness::roll(&seed.seed, &seed.game_id, 1),
+            ));
+            CoinGame::flip(&seed.seed, &seed.game_id, side_chance, heads_odds).to_uppercase()
+        }
+        "lottery" => {
+            let lottery_id = seed.game_id.trim_start_matches("lottery-").parse::<i64>()?;
+            let tiers = param::<usize>(&params, "tiers").unwrap_or(1);
+            let tickets = db.get_bought_tickets(lottery_id).await?;
+            let total = tickets.iter().map(|(_, t)| t).sum::<i32>();
+            lines.push(format!(
+                "> **Inputs:** {} :tickets: from {} players, numbered in order of user id, {} prize tiers\n> Place n wins ticket floor(roll n-1 × tickets left), drawn without replacement",
+                total,
+                tickets.len(),
+                tiers,
+            ));
+            Lottery::new(tickets)
+                .get_winners(&seed.seed, &seed.game_id, tiers)
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+        _ => return Err(format!("can't replay {} games", seed.game).into()),
+    };
+
+    let show = |outcome: &str| match seed.game.as_str() {
+        "lottery" => outcome
+            .split(',')
+            .map(|w| format!("<@{}>", w))
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => outcome.to_string(),
+    };
+    lines.push(format!(
+        "> **Recomputed:** {} | **Announced:** {} {}",
+        show(&recomputed),
+        show(&outcome),
+        if recomputed == outcome {
+            ":white_check_mark:"
+        } else {
+            ":x:"
+        }
+    ));
+
+    let reply = CreateReply::default()
+        .content(lines.join("\n"))
+        .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users());
+    ctx.send(reply).await?;
+    Ok(())
+}
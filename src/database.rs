@@ -5,7 +5,7 @@ use std::fmt;
 #[cfg(not(test))]
 use tokio::fs;
 
-use crate::Error;
+use crate::{pricing::PriceModelKind, scheduler, Error};
 
 #[derive(Debug, sqlx::FromRow)]
 struct Balance {
@@ -39,6 +39,8 @@ pub struct PurchaseableRoleConfig {
     pub only_one: bool,
     pub increment: Option<i32>,
     pub required_role_id: Option<i64>,
+    pub rent_price: Option<i32>,
+    pub rent_days: Option<i32>,
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -48,19 +50,419 @@ pub struct PurchaseableRole {
     pub only_one: bool,
     pub increment: Option<i32>,
     pub required_role_id: Option<u64>,
+    pub rent_price: Option<i32>,
+    pub rent_days: Option<i32>,
+}
+
+impl PurchaseableRole {
+    /// The price and number of days of a rental, if the role can be rented
+    pub fn rent(&self) -> Option<(i32, i32)> {
+        match (self.rent_price, self.rent_days) {
+            (Some(price), Some(days)) if price > 0 && days > 0 && !self.only_one => {
+                Some((price, days))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct RoleRental {
+    pub role_id: i64,
+    pub user_id: i64,
+    pub guild_id: i64,
+    pub expires_at: DateTime<Utc>,
+    pub reminded: bool,
 }
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct RoleHolder {
     pub user_id: u64,
-    pub purchased: sqlx::types::chrono::DateTime<Utc>,
+    /// crown hours have been credited up to here, the purchase time if nothing was yet
+    pub credited_until: sqlx::types::chrono::DateTime<Utc>,
+}
+
+/// How somebody stopped holding a unique role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TenureEnd {
+    Bought,
+    Sold,
+    Auction,
+}
+
+impl TenureEnd {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TenureEnd::Bought => "bought",
+            TenureEnd::Sold => "sold",
+            TenureEnd::Auction => "auctioned",
+        }
+    }
+}
+
+/// One stretch of somebody holding a unique role, `lost` is None while they still do
+#[derive(Debug, sqlx::FromRow)]
+pub struct RoleTenure {
+    pub user_id: i64,
+    pub acquired: sqlx::types::chrono::DateTime<Utc>,
+    pub lost: Option<sqlx::types::chrono::DateTime<Utc>>,
+    pub price: Option<i32>,
+    pub lost_how: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Currency {
+    Bucks,
+    Bones,
+}
+
+impl Currency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Currency::Bucks => "bucks",
+            Currency::Bones => "bones",
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            Currency::Bucks => "balance",
+            Currency::Bones => "bones",
+        }
+    }
+}
+
+/// Why a balance changed, stored with every row of the `transactions` ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    CoinGamble,
+    Gamble,
+    RpsGamble,
+    Blackjack,
+    Poker,
+    Daily,
+    Give,
+    CrownTax,
+    Transfer,
+    Robbery,
+    LotteryTicket,
+    LotteryPrize,
+    PaidChannel,
+    Fine,
+    Award,
+    AddBucks,
+    RemoveBucks,
+    Giveaway,
+    Bury,
+    BonesPurchase,
+    BonesSale,
+    BonesDecay,
+    MarketOrder,
+    MarketTrade,
+    RolePurchase,
+    RoleRental,
+    RoleSale,
+    AuctionBid,
+    BonesPrediction,
+    ItemPurchase,
+    EmojiPurchase,
+    EmojiRefund,
+    EscrowRefund,
+}
+
+impl Reason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::CoinGamble => "coingamble",
+            Reason::Gamble => "gamble",
+            Reason::RpsGamble => "rpsgamble",
+            Reason::Blackjack => "blackjack",
+            Reason::Poker => "poker",
+            Reason::Daily => "daily",
+            Reason::Give => "give",
+            Reason::CrownTax => "crown_tax",
+            Reason::Transfer => "transfer",
+            Reason::Robbery => "robbery",
+            Reason::LotteryTicket => "lottery_ticket",
+            Reason::LotteryPrize => "lottery_prize",
+            Reason::PaidChannel => "paid_channel",
+            Reason::Fine => "fine",
+            Reason::Award => "award",
+            Reason::AddBucks => "add_bucks",
+            Reason::RemoveBucks => "remove_bucks",
+            Reason::Giveaway => "giveaway",
+            Reason::Bury => "bury",
+            Reason::BonesPurchase => "bones_purchase",
+            Reason::BonesSale => "bones_sale",
+            Reason::BonesDecay => "bones_decay",
+            Reason::MarketOrder => "market_order",
+            Reason::MarketTrade => "market_trade",
+            Reason::RolePurchase => "role_purchase",
+            Reason::RoleRental => "role_rental",
+            Reason::RoleSale => "role_sale",
+            Reason::AuctionBid => "auction_bid",
+            Reason::BonesPrediction => "bones_prediction",
+            Reason::ItemPurchase => "item_purchase",
+            Reason::EmojiPurchase => "emoji_purchase",
+            Reason::EmojiRefund => "emoji_refund",
+            Reason::EscrowRefund => "escrow_refund",
+        }
+    }
+
+    /// Tag the change with the game or event it belongs to
+    pub fn with_id(self, correlation_id: impl ToString) -> Memo {
+        Memo {
+            reason: self,
+            correlation_id: Some(correlation_id.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Memo {
+    pub reason: Reason,
+    pub correlation_id: Option<String>,
+}
+
+impl Memo {
+    /// The same event booked under another reason, like the crown's cut of a game
+    pub fn as_reason(&self, reason: Reason) -> Memo {
+        Memo {
+            reason,
+            correlation_id: self.correlation_id.clone(),
+        }
+    }
+}
+
+impl From<Reason> for Memo {
+    fn from(reason: Reason) -> Self {
+        Self {
+            reason,
+            correlation_id: None,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Transaction {
+    pub amount: i32,
+    pub currency: String,
+    pub reason: String,
+    pub balance: i32,
+    pub created_at: sqlx::types::chrono::DateTime<Utc>,
+    pub link: Option<String>,
+}
+
+// applies the change and writes the ledger row on the caller's connection so both
+// land in the same sql transaction. returns None if the user has no balance row.
+/// Charges the current ticket price and adds the tickets, `None` when the user can't afford
+/// them and the caller should roll back
+async fn buy_tickets(
+    conn: &mut sqlx::SqliteConnection,
+    lottery_id: i64,
+    user_id: u64,
+    amount: i32,
+    memo: &Memo,
+) -> Result<Option<i32>, Error> {
+    let (price,) = sqlx::query_as::<_, (i32,)>(
+        "SELECT ticket_price FROM lotteries WHERE id = $1 AND status = 'open'",
+    )
+    .bind(lottery_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or("That lottery has already been drawn")?;
+    match record_transaction(conn, user_id, -price * amount, Currency::Bucks, memo).await? {
+        Some(remaining) if remaining >= 0 => {}
+        _ => return Ok(None),
+    }
+    let data = sqlx::query_as::<_, Tickets>("INSERT INTO lottery_tickets (lottery_id, user_id, tickets) VALUES ($1, $2, $3) ON CONFLICT(lottery_id, user_id) DO UPDATE SET tickets = tickets + $3 RETURNING tickets")
+        .bind(lottery_id)
+        .bind(user_id as i64)
+        .bind(amount)
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(Some(data.tickets))
+}
+
+async fn record_transaction(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: u64,
+    amount: i32,
+    currency: Currency,
+    memo: &Memo,
+) -> Result<Option<i32>, Error> {
+    let balance = sqlx::query_as::<_, Balance>(
+        format!(
+            "UPDATE balances SET {0} = {0} + $1 WHERE id = $2 RETURNING {0} as balance",
+            currency.column()
+        )
+        .as_str(),
+    )
+    .bind(amount)
+    .bind(user_id as i64)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let balance = match balance {
+        Some(b) => b.balance,
+        None => return Ok(None),
+    };
+
+    sqlx::query("INSERT INTO transactions (user_id, amount, currency, reason, correlation_id, balance) VALUES ($1, $2, $3, $4, $5, $6)")
+        .bind(user_id as i64)
+        .bind(amount)
+        .bind(currency.as_str())
+        .bind(memo.reason.as_str())
+        .bind(memo.correlation_id.as_deref())
+        .bind(balance)
+        .execute(&mut *conn)
+        .await?;
+    if currency == Currency::Bones {
+        update_bones_lots(conn, user_id, amount).await?;
+    }
+    Ok(Some(balance))
+}
+
+// new bones start a lot, spent or lost bones come out of the oldest lots first
+async fn update_bones_lots(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: u64,
+    amount: i32,
+) -> Result<(), Error> {
+    if amount > 0 {
+        sqlx::query("INSERT INTO bones_lots (user_id, quantity, acquired_at) VALUES ($1, $2, $3)")
+            .bind(user_id as i64)
+            .bind(amount)
+            .bind(Utc::now())
+            .execute(&mut *conn)
+            .await?;
+        return Ok(());
+    }
+    let lots = sqlx::query_as::<_, (i64, i32)>(
+        "SELECT id, quantity FROM bones_lots WHERE user_id = $1 ORDER BY julianday(acquired_at) ASC, id ASC",
+    )
+    .bind(user_id as i64)
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut left = -amount;
+    for (id, quantity) in lots {
+        if left <= 0 {
+            break;
+        }
+        let taken = quantity.min(left);
+        sqlx::query("UPDATE bones_lots SET quantity = quantity - $2 WHERE id = $1")
+            .bind(id)
+            .bind(taken)
+            .execute(&mut *conn)
+            .await?;
+        left -= taken;
+    }
+    sqlx::query("DELETE FROM bones_lots WHERE user_id = $1 AND quantity <= 0")
+        .bind(user_id as i64)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// How many of `bones` a decay of `percent` takes, rounded up so a few bones still decay
+fn decayed_bones(bones: i32, percent: i32) -> i32 {
+    ((bones as i64 * percent as i64 + 99) / 100) as i32
+}
+
+// what a decay would take from each balance and open sell order. Without a cutoff all
+// bones decay, otherwise only lots and orders holding bones from before it.
+async fn decaying_bones(
+    conn: &mut sqlx::SqliteConnection,
+    percent: i32,
+    acquired_before: Option<DateTime<Utc>>,
+) -> Result<(Vec<(i64, i32)>, Vec<(i64, i64, i32, i32)>), Error> {
+    let balances = match acquired_before {
+        None => {
+            sqlx::query_as::<_, (i64, i32)>("SELECT id, bones FROM balances WHERE bones > 0")
+                .fetch_all(&mut *conn)
+                .await?
+        }
+        Some(before) => {
+            sqlx::query_as::<_, (i64, i32)>(
+                "SELECT b.id, MIN(b.bones, SUM(l.quantity)) FROM balances b JOIN bones_lots l ON l.user_id = b.id WHERE b.bones > 0 AND julianday(l.acquired_at) <= julianday($1) GROUP BY b.id",
+            )
+            .bind(before)
+            .fetch_all(&mut *conn)
+            .await?
+        }
+    };
+    let orders = sqlx::query_as::<_, (i64, i64, i32)>(
+        "SELECT id, user_id, quantity FROM market_orders WHERE status = 'open' AND side = 'sell' AND ($1 IS NULL OR julianday(acquired_at) <= julianday($1))",
+    )
+    .bind(acquired_before)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok((
+        balances
+            .into_iter()
+            .map(|(user_id, bones)| (user_id, decayed_bones(bones, percent)))
+            .filter(|(_, lost)| *lost > 0)
+            .collect(),
+        orders
+            .into_iter()
+            .map(|(id, user_id, quantity)| {
+                (id, user_id, quantity, decayed_bones(quantity, percent))
+            })
+            .filter(|(_, _, _, lost)| *lost > 0)
+            .collect(),
+    ))
+}
+
+// adds up what each player loses over their balance and sell orders
+fn bones_lost_per_user(
+    balances: &[(i64, i32)],
+    orders: &[(i64, i64, i32, i32)],
+) -> Vec<(u64, i32)> {
+    let mut lost: Vec<(u64, i32)> = vec![];
+    let all = balances
+        .iter()
+        .map(|(user_id, bones)| (*user_id, *bones))
+        .chain(
+            orders
+                .iter()
+                .map(|(_, user_id, _, bones)| (*user_id, *bones)),
+        );
+    for (user_id, bones) in all {
+        match lost.iter_mut().find(|(u, _)| *u == user_id as u64) {
+            Some(entry) => entry.1 += bones,
+            None => lost.push((user_id as u64, bones)),
+        }
+    }
+    lost
 }
 
 #[allow(async_fn_in_trait)]
 pub trait BalanceDatabase {
     async fn get_balance(&self, user_id: u64) -> Result<i32, Error>;
-    async fn award_balances(&self, user_ids: Vec<u64>, award: i32) -> Result<(), Error>;
-    async fn subtract_balances(&self, user_ids: Vec<u64>, amount: i32) -> Result<(), Error>;
+    async fn award_balances(&self, user_ids: Vec<u64>, award: i32, memo: Memo)
+        -> Result<(), Error>;
+    async fn subtract_balances(
+        &self,
+        user_ids: Vec<u64>,
+        amount: i32,
+        memo: Memo,
+    ) -> Result<(), Error>;
+    async fn transfer(
+        &self,
+        from: u64,
+        to: &[(u64, i32)],
+        currency: Currency,
+        memo: Memo,
+    ) -> Result<(), Error>;
+    async fn transfer_taxed(
+        &self,
+        from: u64,
+        to: &[(u64, i32)],
+        tax: i32,
+        crown: Option<u64>,
+        currency: Currency,
+        memo: Memo,
+    ) -> Result<(), Error>;
     async fn get_leaderboard(&self) -> Result<Vec<(u64, i32)>, Error>;
     async fn get_last_daily(&self, user_id: u64) -> Result<Option<DateTime<Utc>>, Error>;
     async fn did_daily(&self, user_id: u64) -> Result<(), Error>;
@@ -74,9 +476,29 @@ pub trait BalanceDatabase {
     async fn update_crown_timer(&self, user_id: u64, hours: f32) -> Result<(), Error>;
     async fn get_bones(&self, user_id: u64) -> Result<i32, Error>;
     async fn get_bones_leaderboard(&self) -> Result<Vec<(u64, i32, i32)>, Error>;
-    async fn decay_bones(&self) -> Result<Vec<u64>, Error>;
-    async fn add_bones(&self, user_id: u64, amount: i32) -> Result<i32, Error>;
-    async fn remove_bones(&self, user_id: u64, amount: i32) -> Result<i32, Error>;
+    /// Takes `percent` of everyone's bones, only from bones acquired before the cutoff if
+    /// there is one. Returns how many each player lost.
+    async fn decay_bones(
+        &self,
+        percent: i32,
+        acquired_before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<(u64, i32)>, Error>;
+    /// What `decay_bones` would take from each player right now
+    async fn get_decaying_bones(
+        &self,
+        percent: i32,
+        acquired_before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<(u64, i32)>, Error>;
+    async fn add_bones(&self, user_id: u64, amount: i32, memo: Memo) -> Result<i32, Error>;
+    async fn remove_bones(&self, user_id: u64, amount: i32, memo: Memo) -> Result<i32, Error>;
+    async fn link_transactions(&self, correlation_id: &str, link: &str) -> Result<(), Error>;
+    async fn get_transactions(
+        &self,
+        user_id: u64,
+        currency: Option<Currency>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Transaction>, Error>;
 }
 
 pub trait RobberyDatabase {
@@ -84,17 +506,155 @@ pub trait RobberyDatabase {
     async fn bought_robbery(&self, user_id: u64) -> Result<(), Error>;
 }
 
+pub trait BonesPriceDatabase {
+    async fn record_bones_price(&self, price: i32, at: DateTime<Utc>) -> Result<(), Error>;
+    /// Prices set since `since`, oldest first, starting with the one that was in effect then
+    async fn get_bones_prices(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, i32)>, Error>;
+    /// Bones bought from and sold to Johnny since `since`
+    async fn get_bones_flow(&self, since: DateTime<Utc>) -> Result<(i32, i32), Error>;
+}
+
 pub trait ChannelDatabase {
     async fn get_paid_channels(&self) -> Result<Vec<(u64, i32)>, Error>;
     async fn set_channel_price(&self, channel_id: u64, price: i32) -> Result<(), Error>;
     async fn remove_paid_channel(&self, channel_id: u64) -> Result<(), Error>;
 }
 
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Lottery {
+    pub id: i64,
+    pub name: String,
+    pub ticket_price: i32,
+    pub base_prize: i32,
+    pub next_ticket_price: i32,
+    pub next_base_prize: i32,
+    pub schedule: String,
+    pub draw_time: DateTime<Utc>,
+    /// Percentages of the pot per place, e.g. `70,20,10`
+    pub prize_tiers: String,
+    /// Below this many tickets the round isn't drawn and the pot rolls over
+    pub min_tickets: i32,
+}
+
+impl Lottery {
+    /// The pot a winner would take home right now, each ticket adds its price minus one
+    pub fn prize(&self, tickets_sold: i32) -> i32 {
+        tickets_sold * (self.ticket_price - 1) + self.base_prize
+    }
+
+    /// The id its seed is stored under, what players pass to `/verify`
+    pub fn game_id(&self) -> String {
+        format!("lottery-{}", self.id)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct LotteryDraw {
+    pub name: String,
+    pub drawn_at: DateTime<Utc>,
+    pub pot: i32,
+    pub winner: i64,
+    /// What first place took home
+    pub prize: i32,
+    pub winners: i32,
+    pub winner_tickets: i32,
+    pub tickets_sold: i32,
+    pub players: i32,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct LotteryStats {
+    pub draws_entered: i32,
+    pub tickets: i32,
+    pub spent: i32,
+    pub wins: i32,
+    pub won: i32,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct LotterySubscription {
+    pub user_id: i64,
+    pub lottery_name: String,
+    pub tickets: i32,
+    pub expires_at: DateTime<Utc>,
+    /// The round tickets were last bought for, so each round is only charged once
+    pub last_lottery_id: Option<i64>,
+}
+
 pub trait LotteryDatabase {
-    async fn clear_tickets(&self) -> Result<(), Error>;
-    async fn bought_lottery_ticket(&self, user_id: u64, amount: i32) -> Result<i32, Error>;
-    async fn get_bought_tickets(&self) -> Result<Vec<(u64, i32)>, Error>;
+    #[allow(clippy::too_many_arguments)]
+    async fn create_lottery(
+        &self,
+        name: &str,
+        ticket_price: i32,
+        base_prize: i32,
+        schedule: &str,
+        draw_time: DateTime<Utc>,
+        prize_tiers: &str,
+        min_tickets: i32,
+    ) -> Result<i64, Error>;
+    async fn get_open_lotteries(&self) -> Result<Vec<Lottery>, Error>;
+    async fn update_lottery(&self, lottery: &Lottery) -> Result<(), Error>;
+    async fn add_to_lottery_prize(&self, lottery_id: i64, amount: i32) -> Result<(), Error>;
+    /// Pays out the prizes per tier, reveals the round's seed and opens the next round
+    /// with the same name and schedule, the rollover is added to its base prize. Without
+    /// prizes the round is marked as rolled over instead of drawn. Returns `None` when
+    /// the round was already closed.
+    #[allow(clippy::too_many_arguments)]
+    async fn close_lottery(
+        &self,
+        lottery: &Lottery,
+        prizes: &[(u64, i32)],
+        pot: i32,
+        rollover: i32,
+        next_draw: DateTime<Utc>,
+        params: Option<&str>,
+        outcome: &str,
+    ) -> Result<Option<i64>, Error>;
+    async fn get_recent_draws(&self, limit: i32) -> Result<Vec<LotteryDraw>, Error>;
+    async fn get_biggest_draws(&self, limit: i32) -> Result<Vec<LotteryDraw>, Error>;
+    async fn get_lottery_stats(&self, user_id: u64) -> Result<LotteryStats, Error>;
+    /// Charges the current ticket price and hands out the tickets in one go, returns the
+    /// tickets now held or `None` when the user can't afford them
+    async fn buy_lottery_tickets(
+        &self,
+        lottery_id: i64,
+        user_id: u64,
+        amount: i32,
+        memo: Memo,
+    ) -> Result<Option<i32>, Error>;
+    async fn get_bought_tickets(&self, lottery_id: i64) -> Result<Vec<(u64, i32)>, Error>;
+    /// Tickets held across every open lottery
     async fn get_user_tickets(&self, user_id: u64) -> Result<i32, Error>;
+    async fn subscribe_lottery(
+        &self,
+        user_id: u64,
+        lottery_name: &str,
+        tickets: i32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error>;
+    /// Cancels the subscription to one lottery, or all of them without a name
+    async fn unsubscribe_lottery(
+        &self,
+        user_id: u64,
+        lottery_name: Option<&str>,
+    ) -> Result<u64, Error>;
+    async fn get_subscriptions(&self) -> Result<Vec<LotterySubscription>, Error>;
+    async fn get_user_subscriptions(&self, user_id: u64)
+        -> Result<Vec<LotterySubscription>, Error>;
+    /// Buys the subscribed tickets for a round and marks the round charged in one go, the
+    /// round is still marked when the user can't afford it so it isn't retried
+    async fn charge_subscription(
+        &self,
+        user_id: u64,
+        lottery_name: &str,
+        lottery_id: i64,
+        tickets: i32,
+        memo: Memo,
+    ) -> Result<Option<i32>, Error>;
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -111,6 +671,35 @@ pub trait ShopDatabase {
 pub trait RoleDatabase {
     async fn price_decayed(&self, role_id: u64) -> Result<(), Error>;
     async fn get_purchasable_roles(&self) -> Result<Vec<PurchaseableRole>, Error>;
+    /// A price of 0 stops the role from being rented
+    async fn set_role_rent(&self, role_id: u64, price: i32, days: i32) -> Result<(), Error>;
+    async fn get_rental(&self, role_id: u64, user_id: u64) -> Result<Option<RoleRental>, Error>;
+    async fn get_rentals(&self) -> Result<Vec<RoleRental>, Error>;
+    async fn get_user_rentals(&self, user_id: u64) -> Result<Vec<RoleRental>, Error>;
+    /// Charges the rent and starts or extends a rental, the reminder is sent again before
+    /// the new expiry. Returns false when the user can't afford it.
+    #[allow(clippy::too_many_arguments)]
+    async fn set_rental(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        guild_id: u64,
+        expires_at: DateTime<Utc>,
+        price: i32,
+        memo: Memo,
+    ) -> Result<bool, Error>;
+    /// Refunds a rental the role couldn't be given for, going back to the previous
+    /// expiry or ending the rental if there wasn't one
+    async fn undo_rental(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        previous: Option<DateTime<Utc>>,
+        refund: i32,
+        memo: Memo,
+    ) -> Result<(), Error>;
+    async fn set_rental_reminded(&self, role_id: u64, user_id: u64) -> Result<(), Error>;
+    async fn end_rental(&self, role_id: u64, user_id: u64) -> Result<(), Error>;
     async fn increment_role_price(&self, role_id: String) -> Result<(), Error>;
     async fn set_role_price(
         &self,
@@ -121,7 +710,48 @@ pub trait RoleDatabase {
         only_one: Option<bool>,
     ) -> Result<(), Error>;
     async fn get_unique_role_holder(&self, role_id: u64) -> Result<Option<RoleHolder>, Error>;
-    async fn set_unique_role_holder(&self, role_id: u64, user_id: u64) -> Result<(), Error>;
+    /// Hands the role to the user, ending the previous holder's tenure the way it was lost
+    async fn set_unique_role_holder(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        price: i32,
+        how: TenureEnd,
+    ) -> Result<(), Error>;
+    /// Records a purchase of a role anybody can have
+    async fn record_role_purchase(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        price: i32,
+    ) -> Result<(), Error>;
+    /// The user's current tenure of the role, if we know how they got it
+    async fn get_role_tenure(
+        &self,
+        role_id: u64,
+        user_id: u64,
+    ) -> Result<Option<RoleTenure>, Error>;
+    /// Ends the user's tenure as sold, clears them as the unique holder, takes the price
+    /// back down by its increment and pays the refund. Returns false when the user
+    /// wasn't holding the role.
+    async fn sell_role(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        refund: i32,
+        memo: Memo,
+    ) -> Result<bool, Error>;
+    /// The holders of a role, newest first
+    async fn get_role_history(&self, role_id: u64, limit: i64) -> Result<Vec<RoleTenure>, Error>;
+    /// Total hours each user held the role, counting the current holder up to now
+    async fn get_role_leaderboard(&self, role_id: u64) -> Result<Vec<(u64, f32)>, Error>;
+    /// Credits the holder of the role with the hours since they were last credited, up
+    /// to `until`. Returns who got how many hours, None if nobody holds the role.
+    async fn accrue_crown_time(
+        &self,
+        role_id: u64,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(u64, f32)>, Error>;
     async fn get_price_decay_config(&self) -> Result<Vec<RolePriceDecayConfig>, Error>;
     async fn set_price_decay_config(
         &self,
@@ -144,122 +774,502 @@ pub trait ConfigDatabase {
     async fn del_config_value(&self, key: ConfigKey) -> Result<(), Error>;
 }
 
-#[derive(Debug, sqlx::FromRow)]
-struct ConfigRow {
-    pub key: String,
-    pub value: String,
+/// Stakes of games that are still running. A stake leaves the player's balance when
+/// it is escrowed and the row is only removed once the game settles or is refunded,
+/// so a restart mid-game can give it back.
+pub trait EscrowDatabase {
+    async fn escrow_stake(&self, user_id: u64, amount: i32, memo: Memo) -> Result<(), Error>;
+    async fn settle_escrow(
+        &self,
+        game_id: &str,
+        payouts: &[(u64, i32)],
+        memo: Memo,
+    ) -> Result<(), Error>;
+    async fn settle_escrows(
+        &self,
+        game_ids: &[&str],
+        payouts: &[(u64, i32)],
+        crown: Option<(u64, i32)>,
+        memo: Memo,
+    ) -> Result<(), Error>;
+    async fn refund_escrow(&self, game_id: &str) -> Result<Vec<(u64, i32)>, Error>;
+    async fn get_unsettled_games(&self) -> Result<Vec<(String, String)>, Error>;
+    async fn get_stakes(&self, game_id: &str) -> Result<Vec<(u64, i32)>, Error>;
 }
 
-impl ConfigRow {
-    fn as_config_key(&self) -> ConfigKey {
-        match self.key.as_str() {
-            "daily_upper_limit" => ConfigKey::DailyUpperLimit,
-            "bot_odds_updated" => ConfigKey::BotOddsUpdated,
-            "bot_odds" => ConfigKey::BotOdds,
-            "game_length_seconds" => ConfigKey::GameLengthSeconds,
-            "lottery_base_prize" => ConfigKey::LotteryBasePrize,
-            "lottery_ticket_price" => ConfigKey::LotteryTicketPrice,
-            "future_lottery_base_prize" => ConfigKey::FutureLotteryBasePrize,
-            "future_lottery_ticket_price" => ConfigKey::FutureLotteryTicketPrice,
-            "side_chance" => ConfigKey::SideChance,
-            "community_emoji_price" => ConfigKey::CommunityEmojiPrice,
-            "bones_price_updated" => ConfigKey::BonesPriceUpdated,
-            "bones_price" => ConfigKey::BonesPrice,
-            "bones_price_min" => ConfigKey::BonesPriceMin,
-            "bones_price_max" => ConfigKey::BonesPriceMax,
-            "bones_price_last_was_increase" => ConfigKey::BonesPriceLastWasIncrease,
-            "bones_price_force_update" => ConfigKey::ForceBonesPriceUpdate,
-            "bot_odds_game_limit" => ConfigKey::BotOddsGameLimit,
-            "lottery_winner" => ConfigKey::LotteryWinner,
-            "force_egg" => ConfigKey::ForceEgg,
-            "robbery_length_seconds" => ConfigKey::RobberyLengthSeconds,
-            "ghost_channel_odds" => ConfigKey::GhostChannelOdds,
-            "ghost_channel_length" => ConfigKey::GhostChannelLength,
-            "ghost_channel_id" => ConfigKey::GhostChannelId,
-            "unghost_time" => ConfigKey::UnghostTime,
-            "voice_channel_celebration_amount" => ConfigKey::VoiceChannelCelebrationAmount,
-            _ => panic!("Invalid config"),
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Auction {
+    pub id: i64,
+    pub role_id: i64,
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub message_id: Option<i64>,
+    pub reserve: i32,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub status: String,
+    pub high_bidder: Option<i64>,
+    pub high_bid: Option<i32>,
+}
+
+impl Auction {
+    /// The reserve for the first bid, after that at least 5% more than the high bid
+    pub fn min_bid(&self) -> i32 {
+        match self.high_bid {
+            Some(bid) => bid + (bid / 20).max(1),
+            None => self.reserve,
         }
     }
+
+    /// The escrow the high bid is held under
+    pub fn game_id(&self) -> String {
+        format!("auction-{}", self.id)
+    }
 }
 
-pub enum ConfigKey {
-    DailyUpperLimit,
-    BotOddsUpdated,
-    BotOdds,
-    GameLengthSeconds,
-    LotteryBasePrize,
-    LotteryTicketPrice,
-    FutureLotteryBasePrize,
-    FutureLotteryTicketPrice,
-    SideChance,
-    CommunityEmojiPrice,
-    BonesPrice,
-    BonesPriceUpdated,
-    BonesPriceMin,
-    BonesPriceMax,
-    BonesPriceLastWasIncrease,
-    ForceBonesPriceUpdate,
-    BotOddsGameLimit,
-    LotteryWinner,
-    ForceEgg,
-    RobberyLengthSeconds,
-    GhostChannelOdds,
-    GhostChannelLength,
-    GhostChannelId,
-    UnghostTime,
-    VoiceChannelCelebrationAmount,
+/// Why a bid wasn't taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidRefused {
+    Closed,
+    TooLow(i32),
+    CantAfford,
 }
 
-impl ConfigKey {
-    fn as_str(&self) -> &str {
+impl fmt::Display for BidRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ConfigKey::DailyUpperLimit => "daily_upper_limit",
-            ConfigKey::BotOddsUpdated => "bot_odds_updated",
-            ConfigKey::BotOdds => "bot_odds",
-            ConfigKey::BotOddsGameLimit => "bot_odds_game_limit",
-            ConfigKey::GameLengthSeconds => "game_length_seconds",
-            ConfigKey::LotteryBasePrize => "lottery_base_prize",
-            ConfigKey::LotteryTicketPrice => "lottery_ticket_price",
-            ConfigKey::FutureLotteryBasePrize => "future_lottery_base_prize",
-            ConfigKey::FutureLotteryTicketPrice => "future_lottery_ticket_price",
-            ConfigKey::BonesPrice => "bones_price",
-            ConfigKey::SideChance => "side_chance",
-            ConfigKey::CommunityEmojiPrice => "community_emoji_price",
-            ConfigKey::BonesPriceUpdated => "bones_price_updated",
-            ConfigKey::BonesPriceMin => "bones_price_min",
-            ConfigKey::BonesPriceMax => "bones_price_max",
-            ConfigKey::BonesPriceLastWasIncrease => "bones_price_last_was_increase",
-            ConfigKey::ForceBonesPriceUpdate => "bones_price_force_update",
-            ConfigKey::LotteryWinner => "lottery_winner",
-            ConfigKey::ForceEgg => "force_egg",
-            ConfigKey::RobberyLengthSeconds => "robbery_length_seconds",
-            ConfigKey::GhostChannelOdds => "ghost_channel_odds",
-            ConfigKey::GhostChannelLength => "ghost_channel_length",
-            ConfigKey::GhostChannelId => "ghost_channel_id",
-            ConfigKey::UnghostTime => "unghost_time",
-            ConfigKey::VoiceChannelCelebrationAmount => "voice_channel_celebration_amount",
+            BidRefused::Closed => write!(f, "That auction isn't taking bids"),
+            BidRefused::TooLow(min) => write!(f, "You have to bid at least {min}"),
+            BidRefused::CantAfford => write!(f, "You can't afford that bid"),
         }
     }
 }
 
-impl ConfigDatabase for Database {
-    async fn get_config(&self) -> Result<Config, Error> {
-        let data = sqlx::query_as::<_, ConfigRow>("SELECT * FROM config")
-            .fetch_all(&self.connection)
-            .await?;
+#[derive(Debug)]
+pub struct BidPlaced {
+    pub auction: Auction,
+    /// The previous high bidder, who got their bid back
+    pub outbid: Option<(u64, i32)>,
+    /// The bid came in late and pushed back the end
+    pub extended: bool,
+}
 
-        let mut config = Config {
-            daily_upper_limit: None,
-            bot_odds_updated: None,
-            bot_odds: None,
-            bot_odds_game_counter: None,
+pub trait AuctionDatabase {
+    async fn create_auction(
+        &self,
+        role_id: u64,
+        guild_id: u64,
+        channel_id: u64,
+        reserve: i32,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<i64, Error>;
+    async fn get_auction(&self, id: i64) -> Result<Option<Auction>, Error>;
+    /// Pending and open auctions, ending soonest first
+    async fn get_active_auctions(&self) -> Result<Vec<Auction>, Error>;
+    /// Opens the auction for bids once its message is posted
+    async fn open_auction(&self, id: i64, message_id: u64) -> Result<(), Error>;
+    /// Escrows the bid and refunds the bidder it beats. A bid within `snipe_window` of
+    /// the end pushes the end back so there's `snipe_window` left.
+    async fn place_bid(
+        &self,
+        id: i64,
+        user_id: u64,
+        amount: i32,
+        now: DateTime<Utc>,
+        snipe_window: chrono::Duration,
+    ) -> Result<Result<BidPlaced, BidRefused>, Error>;
+    /// Ends the auction, the high bid if any is spent
+    async fn close_auction(&self, id: i64) -> Result<(), Error>;
+    /// Ends the auction without a sale and refunds the high bid
+    async fn cancel_auction(&self, id: i64) -> Result<Vec<(u64, i32)>, Error>;
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Item {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub price: i32,
+    pub stock: Option<i32>,
+    pub max_per_user: Option<i32>,
+    pub tradeable: bool,
+    pub expires_after_hours: Option<i32>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct InventoryItem {
+    pub name: String,
+    pub quantity: i32,
+    /// The soonest any of them run out
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Why an item couldn't be bought or given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemRefused {
+    NotForSale,
+    SoldOut,
+    TooMany(i32),
+    CantAfford,
+    NotTradeable,
+    NotEnough,
+}
+
+impl fmt::Display for ItemRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemRefused::NotForSale => write!(f, "That item isn't for sale"),
+            ItemRefused::SoldOut => write!(f, "There aren't that many left"),
+            ItemRefused::TooMany(max) => write!(f, "Nobody can have more than {max} of those"),
+            ItemRefused::CantAfford => write!(f, "You can't afford that"),
+            ItemRefused::NotTradeable => write!(f, "That item can't be traded"),
+            ItemRefused::NotEnough => write!(f, "You don't have that many"),
+        }
+    }
+}
+
+pub trait ItemDatabase {
+    #[allow(clippy::too_many_arguments)]
+    async fn create_item(
+        &self,
+        name: &str,
+        description: &str,
+        price: i32,
+        stock: Option<i32>,
+        max_per_user: Option<i32>,
+        tradeable: bool,
+        expires_after_hours: Option<i32>,
+        effect: Option<Effect>,
+    ) -> Result<i64, Error>;
+    /// Items in the shop
+    async fn get_items(&self) -> Result<Vec<Item>, Error>;
+    async fn get_item(&self, name: &str) -> Result<Option<Item>, Error>;
+    /// Takes the item out of the shop, anybody who has some keeps them
+    async fn retire_item(&self, id: i64) -> Result<(), Error>;
+    async fn buy_item(
+        &self,
+        user_id: u64,
+        item_id: i64,
+        amount: i32,
+        memo: Memo,
+    ) -> Result<Result<(), ItemRefused>, Error>;
+    /// Moves items to another user, the ones running out soonest first
+    async fn give_item(
+        &self,
+        from: u64,
+        to: u64,
+        item_id: i64,
+        amount: i32,
+    ) -> Result<Result<(), ItemRefused>, Error>;
+    /// The items a user has that haven't run out
+    async fn get_inventory(&self, user_id: u64) -> Result<Vec<InventoryItem>, Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl OrderSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        }
+    }
+}
+
+/// A limit order for bones, `quantity` is what's left to fill
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MarketOrder {
+    pub id: i64,
+    pub user_id: i64,
+    pub side: String,
+    pub price: i32,
+    pub quantity: i32,
+}
+
+impl MarketOrder {
+    pub fn game_id(&self) -> String {
+        format!("market-{}", self.id)
+    }
+}
+
+/// Part of a new order filled against an order already on the book
+#[derive(Debug, PartialEq, Eq)]
+pub struct Fill {
+    /// Whoever posted the order on the book
+    pub user_id: u64,
+    pub quantity: i32,
+    pub price: i32,
+}
+
+#[derive(Debug)]
+pub struct OrderPlaced {
+    pub id: i64,
+    pub fills: Vec<Fill>,
+    /// Bones still waiting on the book
+    pub remaining: i32,
+}
+
+/// Why an order couldn't be placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderRefused {
+    CantAfford,
+    NotEnoughBones,
+}
+
+impl fmt::Display for OrderRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderRefused::CantAfford => write!(f, "You can't afford that order"),
+            OrderRefused::NotEnoughBones => write!(f, "You don't have that many :bone:"),
+        }
+    }
+}
+
+pub trait MarketDatabase {
+    /// Escrows the order and fills what it can against crossing orders from other users,
+    /// best price first. Trades happen at the price of the order that was on the book.
+    async fn place_order(
+        &self,
+        user_id: u64,
+        side: OrderSide,
+        quantity: i32,
+        price: i32,
+    ) -> Result<Result<OrderPlaced, OrderRefused>, Error>;
+    /// Takes one of the user's open orders off the book and returns its escrow
+    async fn cancel_order(&self, id: i64, user_id: u64) -> Result<Option<MarketOrder>, Error>;
+    /// Open bones per price, best prices first
+    async fn get_order_book(&self, side: OrderSide, depth: i64) -> Result<Vec<(i32, i32)>, Error>;
+    async fn get_user_orders(&self, user_id: u64) -> Result<Vec<MarketOrder>, Error>;
+    async fn get_last_trade_price(&self) -> Result<Option<i32>, Error>;
+}
+
+/// What an item does, the commands it plugs into check for it and use one up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Effect {
+    /// Keeps the owner out of one robbery
+    #[name = "Robbery shield"]
+    RobberyShield,
+    /// Tips one coin gamble towards the owner's side
+    #[name = "Lucky charm"]
+    LuckyCharm,
+    /// Doubles the next daily
+    #[name = "Double daily"]
+    DoubleDaily,
+    /// Keeps the owner from being egged once
+    #[name = "Egg insurance"]
+    EggInsurance,
+}
+
+impl Effect {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Effect::RobberyShield => "robbery_shield",
+            Effect::LuckyCharm => "lucky_charm",
+            Effect::DoubleDaily => "double_daily",
+            Effect::EggInsurance => "egg_insurance",
+        }
+    }
+}
+
+pub trait EffectDatabase {
+    /// Whether the user has an item with the effect that hasn't run out
+    async fn has_effect(&self, user_id: u64, effect: Effect) -> Result<bool, Error>;
+    /// Uses up one item with the effect, the one running out soonest.
+    /// False if the user didn't have any.
+    async fn consume_effect(&self, user_id: u64, effect: Effect) -> Result<bool, Error>;
+    /// Pays out a daily, twice over when the user has a double daily to use up in the same
+    /// transaction. True if it was doubled.
+    async fn award_daily(&self, user_id: u64, amount: i32, memo: Memo) -> Result<bool, Error>;
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct JobStatus {
+    pub name: String,
+    pub last_run: DateTime<Utc>,
+    pub next_run: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub paused: bool,
+}
+
+pub trait JobDatabase {
+    async fn get_jobs(&self) -> Result<Vec<JobStatus>, Error>;
+    async fn record_job_run(
+        &self,
+        name: &str,
+        last_run: DateTime<Utc>,
+        next_run: Option<DateTime<Utc>>,
+        error: Option<&str>,
+    ) -> Result<(), Error>;
+    async fn set_job_paused(&self, name: &str, paused: bool) -> Result<(), Error>;
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct GameSeed {
+    pub game_id: String,
+    pub game: String,
+    pub seed: String,
+    pub commitment: String,
+    pub params: Option<String>,
+    pub outcome: Option<String>,
+    pub revealed_at: Option<DateTime<Utc>>,
+}
+
+pub trait SeedDatabase {
+    async fn commit_seed(
+        &self,
+        game_id: &str,
+        game: &str,
+        seed: &str,
+        commitment: &str,
+    ) -> Result<(), Error>;
+    async fn get_seed(&self, game_id: &str) -> Result<Option<GameSeed>, Error>;
+    /// Publishes the seed together with the inputs and outcome of the draw
+    async fn reveal_seed(
+        &self,
+        game_id: &str,
+        params: Option<&str>,
+        outcome: &str,
+    ) -> Result<(), Error>;
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ConfigRow {
+    pub key: String,
+    pub value: String,
+}
+
+impl ConfigRow {
+    fn as_config_key(&self) -> ConfigKey {
+        match self.key.as_str() {
+            "daily_upper_limit" => ConfigKey::DailyUpperLimit,
+            "bot_odds_updated" => ConfigKey::BotOddsUpdated,
+            "bot_odds" => ConfigKey::BotOdds,
+            "game_length_seconds" => ConfigKey::GameLengthSeconds,
+            "side_chance" => ConfigKey::SideChance,
+            "community_emoji_price" => ConfigKey::CommunityEmojiPrice,
+            "bones_price_updated" => ConfigKey::BonesPriceUpdated,
+            "bones_price" => ConfigKey::BonesPrice,
+            "bones_price_min" => ConfigKey::BonesPriceMin,
+            "bones_price_max" => ConfigKey::BonesPriceMax,
+            "bones_price_last_was_increase" => ConfigKey::BonesPriceLastWasIncrease,
+            "bones_price_force_update" => ConfigKey::ForceBonesPriceUpdate,
+            "bot_odds_game_limit" => ConfigKey::BotOddsGameLimit,
+            "force_egg" => ConfigKey::ForceEgg,
+            "robbery_length_seconds" => ConfigKey::RobberyLengthSeconds,
+            "ghost_channel_odds" => ConfigKey::GhostChannelOdds,
+            "ghost_channel_length" => ConfigKey::GhostChannelLength,
+            "ghost_channel_id" => ConfigKey::GhostChannelId,
+            "unghost_time" => ConfigKey::UnghostTime,
+            "voice_channel_celebration_amount" => ConfigKey::VoiceChannelCelebrationAmount,
+            "role_refund_percent" => ConfigKey::RoleRefundPercent,
+            "bones_price_model" => ConfigKey::BonesPriceModel,
+            "bones_price_target" => ConfigKey::BonesPriceTarget,
+            "bones_price_pull_percent" => ConfigKey::BonesPricePullPercent,
+            "bones_demand_impact_percent" => ConfigKey::BonesDemandImpactPercent,
+            "bones_price_noise" => ConfigKey::BonesPriceNoise,
+            "bones_demand_max_change" => ConfigKey::BonesDemandMaxChange,
+            "bones_decay_schedule" => ConfigKey::BonesDecaySchedule,
+            "bones_decay_percent" => ConfigKey::BonesDecayPercent,
+            "bones_decay_min_age_days" => ConfigKey::BonesDecayMinAgeDays,
+            "bones_decayed_at" => ConfigKey::BonesDecayedAt,
+            "bones_decay_warning" => ConfigKey::BonesDecayWarning,
+            _ => panic!("Invalid config"),
+        }
+    }
+}
+
+pub enum ConfigKey {
+    DailyUpperLimit,
+    BotOddsUpdated,
+    BotOdds,
+    GameLengthSeconds,
+    SideChance,
+    CommunityEmojiPrice,
+    BonesPrice,
+    BonesPriceUpdated,
+    BonesPriceMin,
+    BonesPriceMax,
+    BonesPriceLastWasIncrease,
+    ForceBonesPriceUpdate,
+    BotOddsGameLimit,
+    ForceEgg,
+    RobberyLengthSeconds,
+    GhostChannelOdds,
+    GhostChannelLength,
+    GhostChannelId,
+    UnghostTime,
+    VoiceChannelCelebrationAmount,
+    RoleRefundPercent,
+    BonesPriceModel,
+    BonesPriceTarget,
+    BonesPricePullPercent,
+    BonesDemandImpactPercent,
+    BonesPriceNoise,
+    BonesDemandMaxChange,
+    BonesDecaySchedule,
+    BonesDecayPercent,
+    BonesDecayMinAgeDays,
+    BonesDecayedAt,
+    BonesDecayWarning,
+}
+
+impl ConfigKey {
+    fn as_str(&self) -> &str {
+        match self {
+            ConfigKey::DailyUpperLimit => "daily_upper_limit",
+            ConfigKey::BotOddsUpdated => "bot_odds_updated",
+            ConfigKey::BotOdds => "bot_odds",
+            ConfigKey::BotOddsGameLimit => "bot_odds_game_limit",
+            ConfigKey::GameLengthSeconds => "game_length_seconds",
+            ConfigKey::BonesPrice => "bones_price",
+            ConfigKey::SideChance => "side_chance",
+            ConfigKey::CommunityEmojiPrice => "community_emoji_price",
+            ConfigKey::BonesPriceUpdated => "bones_price_updated",
+            ConfigKey::BonesPriceMin => "bones_price_min",
+            ConfigKey::BonesPriceMax => "bones_price_max",
+            ConfigKey::BonesPriceLastWasIncrease => "bones_price_last_was_increase",
+            ConfigKey::ForceBonesPriceUpdate => "bones_price_force_update",
+            ConfigKey::ForceEgg => "force_egg",
+            ConfigKey::RobberyLengthSeconds => "robbery_length_seconds",
+            ConfigKey::GhostChannelOdds => "ghost_channel_odds",
+            ConfigKey::GhostChannelLength => "ghost_channel_length",
+            ConfigKey::GhostChannelId => "ghost_channel_id",
+            ConfigKey::UnghostTime => "unghost_time",
+            ConfigKey::VoiceChannelCelebrationAmount => "voice_channel_celebration_amount",
+            ConfigKey::RoleRefundPercent => "role_refund_percent",
+            ConfigKey::BonesPriceModel => "bones_price_model",
+            ConfigKey::BonesPriceTarget => "bones_price_target",
+            ConfigKey::BonesPricePullPercent => "bones_price_pull_percent",
+            ConfigKey::BonesDemandImpactPercent => "bones_demand_impact_percent",
+            ConfigKey::BonesPriceNoise => "bones_price_noise",
+            ConfigKey::BonesDemandMaxChange => "bones_demand_max_change",
+            ConfigKey::BonesDecaySchedule => "bones_decay_schedule",
+            ConfigKey::BonesDecayPercent => "bones_decay_percent",
+            ConfigKey::BonesDecayMinAgeDays => "bones_decay_min_age_days",
+            ConfigKey::BonesDecayedAt => "bones_decayed_at",
+            ConfigKey::BonesDecayWarning => "bones_decay_warning",
+        }
+    }
+}
+
+impl ConfigDatabase for Database {
+    async fn get_config(&self) -> Result<Config, Error> {
+        let data = sqlx::query_as::<_, ConfigRow>("SELECT * FROM config")
+            .fetch_all(&self.connection)
+            .await?;
+
+        let mut config = Config {
+            daily_upper_limit: None,
+            bot_odds_updated: None,
+            bot_odds: None,
+            bot_odds_game_counter: None,
             bot_odds_game_limit: None,
             game_length_seconds: None,
-            lottery_base_prize: None,
-            lottery_ticket_price: None,
-            future_lottery_base_prize: None,
-            future_lottery_ticket_price: None,
             side_chance: None,
             community_emoji_price: 5,
             bones_price: 25,
@@ -268,7 +1278,6 @@ impl ConfigDatabase for Database {
             bones_price_max: 5,
             bones_price_last_was_increase: None,
             force_bones_price_update: None,
-            lottery_winner: None,
             force_egg: false,
             robbery_length_seconds: None,
             ghost_channel_id: None,
@@ -276,6 +1285,18 @@ impl ConfigDatabase for Database {
             ghost_channel_odds: None,
             unghost_time: None,
             voice_channel_celebration_amount: 1000,
+            role_refund_percent: 50,
+            bones_price_model: PriceModelKind::RandomWalk,
+            bones_price_target: 25,
+            bones_price_pull_percent: 20,
+            bones_demand_impact_percent: 1,
+            bones_price_noise: 1,
+            bones_demand_max_change: 5,
+            bones_decay_schedule: scheduler::BONES_DECAY_SCHEDULE.to_string(),
+            bones_decay_percent: 100,
+            bones_decay_min_age_days: 0,
+            bones_decayed_at: None,
+            bones_decay_warning: None,
         };
 
         for d in data {
@@ -299,18 +1320,6 @@ impl ConfigDatabase for Database {
                 ConfigKey::GameLengthSeconds => {
                     config.game_length_seconds = Some(d.value.parse().unwrap());
                 }
-                ConfigKey::LotteryBasePrize => {
-                    config.lottery_base_prize = Some(d.value.parse().unwrap());
-                }
-                ConfigKey::LotteryTicketPrice => {
-                    config.lottery_ticket_price = Some(d.value.parse().unwrap());
-                }
-                ConfigKey::FutureLotteryBasePrize => {
-                    config.future_lottery_base_prize = Some(d.value.parse().unwrap());
-                }
-                ConfigKey::FutureLotteryTicketPrice => {
-                    config.future_lottery_ticket_price = Some(d.value.parse().unwrap());
-                }
                 ConfigKey::BonesPrice => {
                     config.bones_price = d.value.parse().unwrap();
                 }
@@ -339,18 +1348,7 @@ impl ConfigDatabase for Database {
                 ConfigKey::ForceBonesPriceUpdate => {
                     config.force_bones_price_update = Some(d.value.parse().unwrap())
                 }
-                ConfigKey::BotOddsGameLimit => {
-                    config.bot_odds_game_limit = match d.value.parse() {
-                        Ok(x) => Some(x),
-                        Err(_) => None,
-                    }
-                }
-                ConfigKey::LotteryWinner => {
-                    config.lottery_winner = match d.value.parse() {
-                        Ok(x) => Some(x),
-                        Err(_) => None,
-                    }
-                }
+                ConfigKey::BotOddsGameLimit => config.bot_odds_game_limit = d.value.parse().ok(),
                 ConfigKey::RobberyLengthSeconds => {
                     config.robbery_length_seconds = Some(d.value.parse().unwrap())
                 }
@@ -371,6 +1369,39 @@ impl ConfigDatabase for Database {
                 ConfigKey::VoiceChannelCelebrationAmount => {
                     config.voice_channel_celebration_amount = d.value.parse().unwrap();
                 }
+                ConfigKey::RoleRefundPercent => {
+                    config.role_refund_percent = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesPriceModel => {
+                    config.bones_price_model = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesPriceTarget => {
+                    config.bones_price_target = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesPricePullPercent => {
+                    config.bones_price_pull_percent = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesDemandImpactPercent => {
+                    config.bones_demand_impact_percent = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesPriceNoise => config.bones_price_noise = d.value.parse().unwrap(),
+                ConfigKey::BonesDemandMaxChange => {
+                    config.bones_demand_max_change = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesDecaySchedule => config.bones_decay_schedule = d.value,
+                ConfigKey::BonesDecayPercent => {
+                    config.bones_decay_percent = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesDecayMinAgeDays => {
+                    config.bones_decay_min_age_days = d.value.parse().unwrap();
+                }
+                ConfigKey::BonesDecayedAt => {
+                    config.bones_decayed_at =
+                        chrono::DateTime::from_timestamp(d.value.parse().unwrap(), 0);
+                }
+                ConfigKey::BonesDecayWarning => {
+                    config.bones_decay_warning = d.value.parse().ok();
+                }
             }
         }
         Ok(config)
@@ -419,10 +1450,6 @@ pub struct Config {
     pub bot_odds_game_limit: Option<u8>,
     pub bot_odds_game_counter: Option<u8>,
     pub game_length_seconds: Option<i32>,
-    pub lottery_base_prize: Option<i32>,
-    pub lottery_ticket_price: Option<i32>,
-    pub future_lottery_base_prize: Option<i32>,
-    pub future_lottery_ticket_price: Option<i32>,
     pub side_chance: Option<u32>,
     pub community_emoji_price: i32,
     pub bones_price: i32,
@@ -431,7 +1458,6 @@ pub struct Config {
     pub bones_price_max: i32,
     pub bones_price_last_was_increase: Option<bool>,
     pub force_bones_price_update: Option<bool>,
-    pub lottery_winner: Option<u64>,
     pub force_egg: bool,
     pub robbery_length_seconds: Option<i8>,
     pub ghost_channel_id: Option<u64>,
@@ -439,13 +1465,30 @@ pub struct Config {
     pub ghost_channel_odds: Option<u8>,
     pub unghost_time: Option<chrono::DateTime<Utc>>,
     pub voice_channel_celebration_amount: i32,
+    pub role_refund_percent: i32,
+    pub bones_price_model: PriceModelKind,
+    pub bones_price_target: i32,
+    pub bones_price_pull_percent: i32,
+    pub bones_demand_impact_percent: i32,
+    /// How far mean reversion and supply and demand wander either way on their own
+    pub bones_price_noise: i32,
+    /// The most supply and demand moves the price in one update
+    pub bones_demand_max_change: i32,
+    /// Cron expression with a leading seconds field, in UTC
+    pub bones_decay_schedule: String,
+    pub bones_decay_percent: i32,
+    /// Only bones held at least this long decay, 0 for all of them
+    pub bones_decay_min_age_days: i32,
+    pub bones_decayed_at: Option<chrono::DateTime<Utc>>,
+    /// Hours before the next decay of the last warning sent
+    pub bones_decay_warning: Option<i32>,
 }
 
 impl fmt::Display for Config {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "**Daily upper limit**: {}\n**Heads odds updated**: {}\n**Heads odds**: {:.2}\n**Heads odds game limit**: {}\n**Game length seconds**: {}\n**Lottery base prize**: {}\n**Lottery ticket price**: {}\n**Future lottery base prize**: {}\n**Future lottery ticket price**: {}\n**Side chance**: {}\n**Bones price**: {}\n**Bones price updated**: {}\n**Community emoji price**: {}\n**Bones price min change**: {}\n**Bones price max change**: {}\n**Bones price force update**: {}\n**Next Lottery Winner**: {}\n**Force egg:**{}\n**Ghost channel: **{}\n**Ghost channel odds:** {} %\n **Ghost channel length **: {} (minutes)\n **Obnoxious celebration amount**: {}\n",
+            "**Daily upper limit**: {}\n**Heads odds updated**: {}\n**Heads odds**: {:.2}\n**Heads odds game limit**: {}\n**Game length seconds**: {}\n**Side chance**: {}\n**Bones price**: {}\n**Bones price updated**: {}\n**Community emoji price**: {}\n**Bones price min change**: {}\n**Bones price max change**: {}\n**Bones price force update**: {}\n**Force egg:**{}\n**Ghost channel: **{}\n**Ghost channel odds:** {} %\n **Ghost channel length **: {} (minutes)\n **Obnoxious celebration amount**: {}\n**Role refund percent**: {} %\n**Bones price model**: {}\n**Bones price target**: {}\n**Bones price pull**: {} %\n**Bones demand impact**: {} % per bone\n**Bones price noise**: {}\n**Bones demand max change**: {}\n**Bones decay schedule**: `{}`\n**Bones decay**: {} %\n**Bones decay min age**: {} days\n**Bones decayed at**: {}\n",
             self.daily_upper_limit.unwrap_or(0),
             self.bot_odds_updated
                 .map(|x| x.to_rfc2822())
@@ -453,10 +1496,6 @@ impl fmt::Display for Config {
             self.bot_odds.unwrap_or(0.0),
             self.bot_odds_game_limit.unwrap_or(10),
             self.game_length_seconds.unwrap_or(0),
-            self.lottery_base_prize.unwrap_or(0),
-            self.lottery_ticket_price.unwrap_or(0),
-            self.future_lottery_base_prize.unwrap_or(0),
-            self.future_lottery_ticket_price.unwrap_or(0),
             self.side_chance.unwrap_or(0),
             self.bones_price,
             self.bones_price_updated.to_rfc2822(),
@@ -464,10 +1503,6 @@ impl fmt::Display for Config {
             self.bones_price_min,
             self.bones_price_max,
             self.force_bones_price_update.unwrap_or(false),
-            match self.lottery_winner {
-                Some(x) => format!("<@{x}>"),
-                None => "None".to_string(),
-            },
             self.force_egg,
             match self.ghost_channel_id {
                 Some(x) => format!("<#{}>", x),
@@ -476,6 +1511,19 @@ impl fmt::Display for Config {
         self.ghost_channel_odds.unwrap_or(0),
         self.ghost_channel_length.unwrap_or(0),
         self.voice_channel_celebration_amount,
+        self.role_refund_percent,
+        self.bones_price_model.as_str(),
+        self.bones_price_target,
+        self.bones_price_pull_percent,
+        self.bones_demand_impact_percent,
+        self.bones_price_noise,
+        self.bones_demand_max_change,
+        self.bones_decay_schedule,
+        self.bones_decay_percent,
+        self.bones_decay_min_age_days,
+        self.bones_decayed_at
+            .map(|x| x.to_rfc2822())
+            .unwrap_or_else(|| "None".to_string()),
         )
     }
 }
@@ -585,6 +1633,41 @@ impl RobberyDatabase for Database {
     }
 }
 
+impl BonesPriceDatabase for Database {
+    async fn record_bones_price(&self, price: i32, at: DateTime<Utc>) -> Result<(), Error> {
+        sqlx::query("INSERT INTO bones_prices (price, recorded_at) VALUES ($1, $2)")
+            .bind(price)
+            .bind(at)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_bones_prices(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, i32)>, Error> {
+        Ok(sqlx::query_as::<_, (DateTime<Utc>, i32)>(
+            "SELECT recorded_at, price FROM (SELECT recorded_at, price, id FROM bones_prices WHERE recorded_at < $1 ORDER BY recorded_at DESC, id DESC LIMIT 1) UNION ALL SELECT recorded_at, price FROM bones_prices WHERE recorded_at >= $1 ORDER BY recorded_at",
+        )
+        .bind(since)
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn get_bones_flow(&self, since: DateTime<Utc>) -> Result<(i32, i32), Error> {
+        Ok(sqlx::query_as::<_, (i32, i32)>(
+            "SELECT COALESCE(SUM(CASE WHEN reason = $1 THEN amount ELSE 0 END), 0), COALESCE(SUM(CASE WHEN reason = $2 THEN -amount ELSE 0 END), 0) FROM transactions WHERE currency = $3 AND julianday(created_at) >= julianday($4)",
+        )
+        .bind(Reason::BonesPurchase.as_str())
+        .bind(Reason::BonesSale.as_str())
+        .bind(Currency::Bones.as_str())
+        .bind(since)
+        .fetch_one(&self.connection)
+        .await?)
+    }
+}
+
 impl ChannelDatabase for Database {
     async fn get_paid_channels(&self) -> Result<Vec<(u64, i32)>, Error> {
         let data = sqlx::query_as::<_, (i64, i32)>("SELECT id, price FROM paid_channels")
@@ -617,7 +1700,7 @@ impl ChannelDatabase for Database {
 impl RoleDatabase for Database {
     async fn get_purchasable_roles(&self) -> Result<Vec<PurchaseableRole>, Error> {
         let data = sqlx::query_as::<_, PurchaseableRoleConfig>(
-            "SELECT role_id, price, only_one, required_role_id, increment FROM purchaseable_roles",
+            "SELECT role_id, price, only_one, required_role_id, increment, rent_price, rent_days FROM purchaseable_roles",
         )
         .fetch_all(&self.connection)
         .await?;
@@ -629,51 +1712,175 @@ impl RoleDatabase for Database {
                 only_one: x.only_one,
                 increment: x.increment,
                 required_role_id: x.required_role_id.map(|x| x as u64),
+                rent_price: x.rent_price,
+                rent_days: x.rent_days,
             })
             .collect())
     }
 
-    async fn set_role_price(
-        &self,
-        role_id: u64,
-        price: i32,
-        increment: Option<i32>,
-        required_role: Option<u64>,
-        only_one: Option<bool>,
-    ) -> Result<(), Error> {
-        if price == 0 {
-            sqlx::query("DELETE FROM purchaseable_roles WHERE role_id = $1")
-                .bind(role_id as i64)
-                .execute(&self.connection)
-                .await?;
-            return Ok(());
-        }
-        let required = required_role.map(|required| required as i64);
-        sqlx::query("INSERT INTO purchaseable_roles (role_id, price, increment, required_role_id, only_one) VALUES ($1, $2, $3, $4, $5) ON CONFLICT(role_id) DO UPDATE SET price = $2, increment = $3, required_role_id = $4, only_one = $5")
-            .bind(role_id as i64)
-            .bind(price)
-            .bind(increment)
-            .bind(required)
-            .bind(only_one)
-            .execute(&self.connection)
-            .await?;
-
-        Ok(())
-    }
-
-    async fn increment_role_price(&self, role_id: String) -> Result<(), Error> {
+    async fn set_role_rent(&self, role_id: u64, price: i32, days: i32) -> Result<(), Error> {
+        let (price, days) = match price {
+            0 => (None, None),
+            _ => (Some(price), Some(days)),
+        };
         sqlx::query(
-            "UPDATE purchaseable_roles SET price = price+COALESCE(increment,0) WHERE role_id = $1",
+            "UPDATE purchaseable_roles SET rent_price = $1, rent_days = $2 WHERE role_id = $3",
         )
-        .bind(role_id)
+        .bind(price)
+        .bind(days)
+        .bind(role_id as i64)
         .execute(&self.connection)
         .await?;
         Ok(())
     }
 
-    async fn get_unique_role_holder(&self, role_id: u64) -> Result<Option<RoleHolder>, Error> {
+    async fn get_rental(&self, role_id: u64, user_id: u64) -> Result<Option<RoleRental>, Error> {
+        let data = sqlx::query_as::<_, RoleRental>(
+            "SELECT role_id, user_id, guild_id, expires_at, reminded FROM role_rentals WHERE role_id = $1 AND user_id = $2",
+        )
+        .bind(role_id as i64)
+        .bind(user_id as i64)
+        .fetch_optional(&self.connection)
+        .await?;
+        Ok(data)
+    }
+
+    async fn get_rentals(&self) -> Result<Vec<RoleRental>, Error> {
+        let data = sqlx::query_as::<_, RoleRental>(
+            "SELECT role_id, user_id, guild_id, expires_at, reminded FROM role_rentals ORDER BY expires_at",
+        )
+        .fetch_all(&self.connection)
+        .await?;
+        Ok(data)
+    }
+
+    async fn get_user_rentals(&self, user_id: u64) -> Result<Vec<RoleRental>, Error> {
+        let data = sqlx::query_as::<_, RoleRental>(
+            "SELECT role_id, user_id, guild_id, expires_at, reminded FROM role_rentals WHERE user_id = $1 ORDER BY expires_at",
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.connection)
+        .await?;
+        Ok(data)
+    }
+
+    async fn set_rental(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        guild_id: u64,
+        expires_at: DateTime<Utc>,
+        price: i32,
+        memo: Memo,
+    ) -> Result<bool, Error> {
+        let mut tx = self.connection.begin().await?;
+        match record_transaction(&mut tx, user_id, -price, Currency::Bucks, &memo).await? {
+            Some(remaining) if remaining >= 0 => {}
+            _ => return Ok(false),
+        }
+        sqlx::query("INSERT INTO role_rentals (role_id, user_id, guild_id, expires_at) VALUES ($1, $2, $3, $4) ON CONFLICT(role_id, user_id) DO UPDATE SET expires_at = $4, reminded = FALSE")
+            .bind(role_id as i64)
+            .bind(user_id as i64)
+            .bind(guild_id as i64)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn undo_rental(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        previous: Option<DateTime<Utc>>,
+        refund: i32,
+        memo: Memo,
+    ) -> Result<(), Error> {
+        let mut tx = self.connection.begin().await?;
+        record_transaction(&mut tx, user_id, refund, Currency::Bucks, &memo).await?;
+        match previous {
+            Some(expires_at) => {
+                sqlx::query(
+                    "UPDATE role_rentals SET expires_at = $1 WHERE role_id = $2 AND user_id = $3",
+                )
+                .bind(expires_at)
+                .bind(role_id as i64)
+                .bind(user_id as i64)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM role_rentals WHERE role_id = $1 AND user_id = $2")
+                    .bind(role_id as i64)
+                    .bind(user_id as i64)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn set_rental_reminded(&self, role_id: u64, user_id: u64) -> Result<(), Error> {
+        sqlx::query("UPDATE role_rentals SET reminded = TRUE WHERE role_id = $1 AND user_id = $2")
+            .bind(role_id as i64)
+            .bind(user_id as i64)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn end_rental(&self, role_id: u64, user_id: u64) -> Result<(), Error> {
+        sqlx::query("DELETE FROM role_rentals WHERE role_id = $1 AND user_id = $2")
+            .bind(role_id as i64)
+            .bind(user_id as i64)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_role_price(
+        &self,
+        role_id: u64,
+        price: i32,
+        increment: Option<i32>,
+        required_role: Option<u64>,
+        only_one: Option<bool>,
+    ) -> Result<(), Error> {
+        if price == 0 {
+            sqlx::query("DELETE FROM purchaseable_roles WHERE role_id = $1")
+                .bind(role_id as i64)
+                .execute(&self.connection)
+                .await?;
+            return Ok(());
+        }
+        let required = required_role.map(|required| required as i64);
+        sqlx::query("INSERT INTO purchaseable_roles (role_id, price, increment, required_role_id, only_one) VALUES ($1, $2, $3, $4, $5) ON CONFLICT(role_id) DO UPDATE SET price = $2, increment = $3, required_role_id = $4, only_one = $5")
+            .bind(role_id as i64)
+            .bind(price)
+            .bind(increment)
+            .bind(required)
+            .bind(only_one)
+            .execute(&self.connection)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn increment_role_price(&self, role_id: String) -> Result<(), Error> {
+        sqlx::query(
+            "UPDATE purchaseable_roles SET price = price+COALESCE(increment,0) WHERE role_id = $1",
+        )
+        .bind(role_id)
+        .execute(&self.connection)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_unique_role_holder(&self, role_id: u64) -> Result<Option<RoleHolder>, Error> {
         let a = sqlx::query_as::<_, (i64, DateTime<Utc>)>(
-            "SELECT user_id, purchased FROM role_holders WHERE role_id = $1",
+            "SELECT user_id, COALESCE(credited_until, purchased) FROM role_holders WHERE role_id = $1",
         )
         .bind(role_id as i64)
         .fetch_optional(&self.connection)
@@ -682,20 +1889,164 @@ impl RoleDatabase for Database {
             None => Ok(None),
             Some(rh) => Ok(Some(RoleHolder {
                 user_id: rh.0 as u64,
-                purchased: rh.1,
+                credited_until: rh.1,
             })),
         }
     }
 
-    async fn set_unique_role_holder(&self, role_id: u64, user_id: u64) -> Result<(), Error> {
-        sqlx::query("INSERT INTO role_holders (role_id, user_id, purchased) VALUES ($1, $2, CURRENT_TIMESTAMP) ON CONFLICT(role_id) DO UPDATE SET user_id = $2, purchased = CURRENT_TIMESTAMP")
+    async fn set_unique_role_holder(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        price: i32,
+        how: TenureEnd,
+    ) -> Result<(), Error> {
+        let mut tx = self.connection.begin().await?;
+        sqlx::query("UPDATE role_tenures SET lost = CURRENT_TIMESTAMP, lost_how = $1 WHERE role_id = $2 AND lost IS NULL")
+            .bind(how.as_str())
+            .bind(role_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO role_tenures (role_id, user_id, price) VALUES ($1, $2, $3)")
+            .bind(role_id as i64)
+            .bind(user_id as i64)
+            .bind(price)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO role_holders (role_id, user_id, purchased) VALUES ($1, $2, CURRENT_TIMESTAMP) ON CONFLICT(role_id) DO UPDATE SET user_id = $2, purchased = CURRENT_TIMESTAMP, credited_until = NULL")
+            .bind(role_id as i64)
+            .bind(user_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn record_role_purchase(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        price: i32,
+    ) -> Result<(), Error> {
+        sqlx::query("INSERT INTO role_tenures (role_id, user_id, price) VALUES ($1, $2, $3)")
             .bind(role_id as i64)
             .bind(user_id as i64)
+            .bind(price)
             .execute(&self.connection)
             .await?;
         Ok(())
     }
 
+    async fn get_role_tenure(
+        &self,
+        role_id: u64,
+        user_id: u64,
+    ) -> Result<Option<RoleTenure>, Error> {
+        let data = sqlx::query_as::<_, RoleTenure>(
+            "SELECT user_id, acquired, lost, price, lost_how FROM role_tenures WHERE role_id = $1 AND user_id = $2 AND lost IS NULL ORDER BY id DESC LIMIT 1",
+        )
+        .bind(role_id as i64)
+        .bind(user_id as i64)
+        .fetch_optional(&self.connection)
+        .await?;
+        Ok(data)
+    }
+
+    async fn sell_role(
+        &self,
+        role_id: u64,
+        user_id: u64,
+        refund: i32,
+        memo: Memo,
+    ) -> Result<bool, Error> {
+        let mut tx = self.connection.begin().await?;
+        let sold = sqlx::query("UPDATE role_tenures SET lost = CURRENT_TIMESTAMP, lost_how = $1 WHERE role_id = $2 AND user_id = $3 AND lost IS NULL")
+            .bind(TenureEnd::Sold.as_str())
+            .bind(role_id as i64)
+            .bind(user_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        if sold.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query("DELETE FROM role_holders WHERE role_id = $1 AND user_id = $2")
+            .bind(role_id as i64)
+            .bind(user_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE purchaseable_roles SET price = MAX(price - COALESCE(increment, 0), 1) WHERE role_id = $1",
+        )
+        .bind(role_id as i64)
+        .execute(&mut *tx)
+        .await?;
+        if refund > 0 {
+            record_transaction(&mut tx, user_id, refund, Currency::Bucks, &memo).await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn get_role_history(&self, role_id: u64, limit: i64) -> Result<Vec<RoleTenure>, Error> {
+        let data = sqlx::query_as::<_, RoleTenure>(
+            "SELECT user_id, acquired, lost, price, lost_how FROM role_tenures WHERE role_id = $1 ORDER BY acquired DESC, id DESC LIMIT $2",
+        )
+        .bind(role_id as i64)
+        .bind(limit)
+        .fetch_all(&self.connection)
+        .await?;
+        Ok(data)
+    }
+
+    async fn get_role_leaderboard(&self, role_id: u64) -> Result<Vec<(u64, f32)>, Error> {
+        let data = sqlx::query_as::<_, (i64, f64)>(
+            "SELECT user_id, SUM(julianday(COALESCE(lost, CURRENT_TIMESTAMP)) - julianday(acquired)) * 24 AS hours FROM role_tenures WHERE role_id = $1 GROUP BY user_id ORDER BY hours DESC LIMIT 10",
+        )
+        .bind(role_id as i64)
+        .fetch_all(&self.connection)
+        .await?;
+        Ok(data
+            .into_iter()
+            .map(|(id, hours)| (id as u64, hours as f32))
+            .collect())
+    }
+
+    async fn accrue_crown_time(
+        &self,
+        role_id: u64,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(u64, f32)>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let holder = sqlx::query_as::<_, (i64, Option<DateTime<Utc>>)>(
+            "SELECT user_id, COALESCE(credited_until, purchased) FROM role_holders WHERE role_id = $1",
+        )
+        .bind(role_id as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let (user_id, since) = match holder {
+            Some(holder) => holder,
+            None => return Ok(None),
+        };
+
+        let hours = match since {
+            Some(since) if since < until => (until - since).num_seconds() as f32 / 3600.0,
+            _ => 0.0,
+        };
+        sqlx::query("INSERT INTO crown_holder_times (id, hours_held) VALUES ($1, $2) ON CONFLICT(id) DO UPDATE SET hours_held = hours_held + $2")
+            .bind(user_id)
+            .bind(hours)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE role_holders SET credited_until = $1 WHERE role_id = $2")
+            .bind(until)
+            .bind(role_id as i64)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some((user_id as u64, hours)))
+    }
+
     async fn get_price_decay_config(&self) -> Result<Vec<RolePriceDecayConfig>, Error> {
         let data = sqlx::query_as::<_, RolePriceDecay>(
             "SELECT role_id, amount, interval, last_decay, minimum FROM role_price_decay WHERE amount > 0",
@@ -722,7 +2073,7 @@ impl RoleDatabase for Database {
         minimum: i32,
     ) -> Result<PurchaseableRole, Error> {
         let data = sqlx::query_as::<_, PurchaseableRoleConfig>(
-            "UPDATE purchaseable_roles SET price = MAX(price - $2, $3) WHERE role_id = $1 RETURNING role_id, price, only_one, required_role_id, increment",
+            "UPDATE purchaseable_roles SET price = MAX(price - $2, $3) WHERE role_id = $1 RETURNING role_id, price, only_one, required_role_id, increment, rent_price, rent_days",
         )
         .bind(role_id as i64)
         .bind(amount)
@@ -735,6 +2086,8 @@ impl RoleDatabase for Database {
             only_one: data.only_one,
             increment: data.increment,
             required_role_id: data.required_role_id.map(|x| x as u64),
+            rent_price: data.rent_price,
+            rent_days: data.rent_days,
         })
     }
 
@@ -791,49 +2144,91 @@ impl BalanceDatabase for Database {
     }
 
     #[tracing::instrument(level = "info")]
-    async fn award_balances(&self, user_ids: Vec<u64>, award: i32) -> Result<(), Error> {
+    async fn award_balances(
+        &self,
+        user_ids: Vec<u64>,
+        award: i32,
+        memo: Memo,
+    ) -> Result<(), Error> {
         if user_ids.is_empty() {
             return Ok(());
         }
-        let a = user_ids
-            .iter()
-            .map(|x| format!("'{}'", x))
-            .collect::<Vec<String>>()
-            .join(", ");
-
-        sqlx::query(
-            format!(
-                "UPDATE balances SET balance = balance + $1 WHERE id IN ({})",
-                a
-            )
-            .as_str(),
-        )
-        .bind(award)
-        .execute(&self.connection)
-        .await?;
+        let mut tx = self.connection.begin().await?;
+        for user_id in user_ids {
+            record_transaction(&mut tx, user_id, award, Currency::Bucks, &memo).await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
     #[tracing::instrument(level = "info")]
-    async fn subtract_balances(&self, user_ids: Vec<u64>, amount: i32) -> Result<(), Error> {
+    async fn subtract_balances(
+        &self,
+        user_ids: Vec<u64>,
+        amount: i32,
+        memo: Memo,
+    ) -> Result<(), Error> {
         if user_ids.is_empty() {
             return Ok(());
         }
-        let a = user_ids
-            .iter()
-            .map(|x| format!("'{}'", x))
-            .collect::<Vec<String>>()
-            .join(", ");
-        sqlx::query(
-            format!(
-                "UPDATE balances SET balance = balance - $1 WHERE id IN ({})",
-                a
+        let mut tx = self.connection.begin().await?;
+        for user_id in user_ids {
+            record_transaction(&mut tx, user_id, -amount, Currency::Bucks, &memo).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Moves `sum(to)` out of `from` in a single sql transaction. Nothing is written if
+    /// the sender would go negative or any party has no balance row.
+    #[tracing::instrument(level = "info")]
+    async fn transfer(
+        &self,
+        from: u64,
+        to: &[(u64, i32)],
+        currency: Currency,
+        memo: Memo,
+    ) -> Result<(), Error> {
+        self.transfer_taxed(from, to, 0, None, currency, memo).await
+    }
+
+    /// Like `transfer` but the sender also pays `tax`, which goes to the crown as a
+    /// crown tax row or is burned when nobody wears it.
+    #[tracing::instrument(level = "info")]
+    async fn transfer_taxed(
+        &self,
+        from: u64,
+        to: &[(u64, i32)],
+        tax: i32,
+        crown: Option<u64>,
+        currency: Currency,
+        memo: Memo,
+    ) -> Result<(), Error> {
+        let total: i32 = to.iter().map(|(_, amount)| amount).sum::<i32>() + tax;
+        let mut tx = self.connection.begin().await?;
+        let remaining = record_transaction(&mut tx, from, -total, currency, &memo)
+            .await?
+            .ok_or("sender has no balance")?;
+        if remaining < 0 {
+            return Err("insufficient funds".into());
+        }
+        for (user_id, amount) in to {
+            record_transaction(&mut tx, *user_id, *amount, currency, &memo)
+                .await?
+                .ok_or("recipient has no balance")?;
+        }
+        if let Some(crown) = crown.filter(|_| tax > 0) {
+            record_transaction(
+                &mut tx,
+                crown,
+                tax,
+                currency,
+                &memo.as_reason(Reason::CrownTax),
             )
-            .as_str(),
-        )
-        .bind(amount)
-        .execute(&self.connection)
-        .await?;
+            .await?
+            .ok_or("crown has no balance")?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
@@ -905,11 +2300,21 @@ impl BalanceDatabase for Database {
 
     #[tracing::instrument(level = "info")]
     async fn bury_balance(&self, user_id: u64, amount: i32) -> Result<(), Error> {
-        sqlx::query("INSERT INTO buried_balances (id, amount) VALUES ($1, $2) ON CONFLICT(id) DO UPDATE SET amount = amount + $1")
+        let mut tx = self.connection.begin().await?;
+        record_transaction(
+            &mut tx,
+            user_id,
+            -amount,
+            Currency::Bucks,
+            &Reason::Bury.into(),
+        )
+        .await?;
+        sqlx::query("INSERT INTO buried_balances (id, amount) VALUES ($1, $2) ON CONFLICT(id) DO UPDATE SET amount = amount + $2")
             .bind(user_id.to_string())
             .bind(amount)
-            .execute(&self.connection)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await?;
 
         Ok(())
     }
@@ -979,42 +2384,63 @@ impl BalanceDatabase for Database {
         )
     }
 
-    async fn add_bones(&self, user_id: u64, amount: i32) -> Result<i32, Error> {
-        Ok(
-            sqlx::query("UPDATE balances SET bones = bones + $1 WHERE id = $2")
-                .bind(amount)
-                .bind(user_id as i64)
-                .execute(&self.connection)
-                .await?
-                .rows_affected()
-                .try_into()
-                .unwrap(),
-        )
+    async fn add_bones(&self, user_id: u64, amount: i32, memo: Memo) -> Result<i32, Error> {
+        let mut tx = self.connection.begin().await?;
+        let affected = record_transaction(&mut tx, user_id, amount, Currency::Bones, &memo)
+            .await?
+            .map_or(0, |_| 1);
+        tx.commit().await?;
+        Ok(affected)
     }
 
-    async fn remove_bones(&self, user_id: u64, amount: i32) -> Result<i32, Error> {
-        Ok(
-            sqlx::query("UPDATE balances SET bones = bones - $1 WHERE id = $2")
-                .bind(amount)
-                .bind(user_id as i64)
-                .execute(&self.connection)
-                .await?
-                .rows_affected()
-                .try_into()
-                .unwrap(),
-        )
+    async fn remove_bones(&self, user_id: u64, amount: i32, memo: Memo) -> Result<i32, Error> {
+        let mut tx = self.connection.begin().await?;
+        let affected = record_transaction(&mut tx, user_id, -amount, Currency::Bones, &memo)
+            .await?
+            .map_or(0, |_| 1);
+        tx.commit().await?;
+        Ok(affected)
     }
 
-    async fn decay_bones(&self) -> Result<Vec<u64>, Error> {
-        let affected: Vec<(i64, i64)> =
-            sqlx::query_as::<_, (i64, i64)>("SELECT id, id from balances WHERE bones > 0")
-                .fetch_all(&self.connection)
-                .await?;
+    async fn decay_bones(
+        &self,
+        percent: i32,
+        acquired_before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<(u64, i32)>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let (balances, orders) = decaying_bones(&mut tx, percent, acquired_before).await?;
 
-        sqlx::query("UPDATE balances SET bones = 0 WHERE bones > 0")
-            .execute(&self.connection)
-            .await?;
-        Ok(affected.iter().map(|x| x.0 as u64).collect())
+        let memo = Reason::BonesDecay.into();
+        for (user_id, bones) in balances.iter() {
+            record_transaction(&mut tx, *user_id as u64, -bones, Currency::Bones, &memo).await?;
+        }
+        // bones escrowed in sell orders decay too
+        for (id, _, quantity, bones) in orders.iter() {
+            if bones >= quantity {
+                sqlx::query("UPDATE market_orders SET status = 'expired' WHERE id = $1")
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            } else {
+                sqlx::query("UPDATE market_orders SET quantity = quantity - $2 WHERE id = $1")
+                    .bind(id)
+                    .bind(bones)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(bones_lost_per_user(&balances, &orders))
+    }
+
+    async fn get_decaying_bones(
+        &self,
+        percent: i32,
+        acquired_before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<(u64, i32)>, Error> {
+        let mut conn = self.connection.acquire().await?;
+        let (balances, orders) = decaying_bones(&mut conn, percent, acquired_before).await?;
+        Ok(bones_lost_per_user(&balances, &orders))
     }
 
     async fn get_bones_leaderboard(&self) -> Result<Vec<(u64, i32, i32)>, Error> {
@@ -1028,32 +2454,233 @@ impl BalanceDatabase for Database {
             .map(|(id, bones, balance)| (*id as u64, *bones, *balance))
             .collect())
     }
-}
-
-#[derive(Debug, sqlx::FromRow)]
-struct UserCrownTime {
-    id: i64,
-    hours_held: f32,
-}
 
-#[derive(Debug, sqlx::FromRow)]
-struct Tickets {
-    tickets: i32,
-}
+    async fn link_transactions(&self, correlation_id: &str, link: &str) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO correlation_links (correlation_id, link) VALUES ($1, $2)",
+        )
+        .bind(correlation_id)
+        .bind(link)
+        .execute(&self.connection)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_transactions(
+        &self,
+        user_id: u64,
+        currency: Option<Currency>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Transaction>, Error> {
+        Ok(sqlx::query_as::<_, Transaction>(
+            "SELECT t.amount, t.currency, t.reason, t.balance, t.created_at, l.link FROM transactions t LEFT JOIN correlation_links l ON t.correlation_id = l.correlation_id WHERE t.user_id = $1 AND ($2 IS NULL OR t.currency = $2) ORDER BY t.id DESC LIMIT $3 OFFSET $4",
+        )
+        .bind(user_id as i64)
+        .bind(currency.map(|c| c.as_str()))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.connection)
+        .await?)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct UserCrownTime {
+    id: i64,
+    hours_held: f32,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Tickets {
+    tickets: i32,
+}
+
+// drawn rounds with their ticket counts, order is trusted sql from this file
+async fn get_draws(
+    pool: &Pool<sqlx::Sqlite>,
+    order: &str,
+    limit: i32,
+) -> Result<Vec<LotteryDraw>, Error> {
+    Ok(sqlx::query_as::<_, LotteryDraw>(
+        format!(
+            "SELECT l.name, l.drawn_at, l.pot, l.winner,
+                COALESCE((SELECT amount FROM lottery_prizes WHERE lottery_id = l.id AND tier = 1), l.pot) as prize,
+                (SELECT COUNT(*) FROM lottery_prizes WHERE lottery_id = l.id) as winners,
+                COALESCE((SELECT tickets FROM lottery_tickets WHERE lottery_id = l.id AND user_id = l.winner), 0) as winner_tickets,
+                COALESCE(SUM(t.tickets), 0) as tickets_sold,
+                COUNT(t.user_id) as players
+            FROM lotteries l LEFT JOIN lottery_tickets t ON t.lottery_id = l.id
+            WHERE l.status = 'drawn'
+            GROUP BY l.id
+            ORDER BY {}
+            LIMIT $1",
+            order
+        )
+        .as_str(),
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
 
 impl LotteryDatabase for Database {
-    async fn bought_lottery_ticket(&self, user_id: u64, amount: i32) -> Result<i32, Error> {
-        let data = sqlx::query_as::<_, Tickets>("INSERT INTO lottery_tickets (id, tickets) VALUES ($1, $2) ON CONFLICT(id) DO UPDATE SET tickets = tickets + $2 RETURNING tickets")
-            .bind(user_id as i64)
-            .bind(amount)
+    async fn create_lottery(
+        &self,
+        name: &str,
+        ticket_price: i32,
+        base_prize: i32,
+        schedule: &str,
+        draw_time: DateTime<Utc>,
+        prize_tiers: &str,
+        min_tickets: i32,
+    ) -> Result<i64, Error> {
+        let (id,) = sqlx::query_as::<_, (i64,)>("INSERT INTO lotteries (name, ticket_price, base_prize, next_ticket_price, next_base_prize, schedule, draw_time, prize_tiers, min_tickets) VALUES ($1, $2, $3, $2, $3, $4, $5, $6, $7) RETURNING id")
+            .bind(name)
+            .bind(ticket_price)
+            .bind(base_prize)
+            .bind(schedule)
+            .bind(draw_time)
+            .bind(prize_tiers)
+            .bind(min_tickets)
             .fetch_one(&self.connection)
-            .await.unwrap();
-        Ok(data.tickets)
+            .await?;
+        Ok(id)
+    }
+
+    async fn get_open_lotteries(&self) -> Result<Vec<Lottery>, Error> {
+        Ok(sqlx::query_as::<_, Lottery>(
+            "SELECT id, name, ticket_price, base_prize, next_ticket_price, next_base_prize, schedule, draw_time, prize_tiers, min_tickets FROM lotteries WHERE status = 'open' ORDER BY draw_time, id",
+        )
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn update_lottery(&self, lottery: &Lottery) -> Result<(), Error> {
+        sqlx::query("UPDATE lotteries SET ticket_price = $1, base_prize = $2, next_ticket_price = $3, next_base_prize = $4, draw_time = $5, prize_tiers = $6, min_tickets = $7 WHERE id = $8")
+            .bind(lottery.ticket_price)
+            .bind(lottery.base_prize)
+            .bind(lottery.next_ticket_price)
+            .bind(lottery.next_base_prize)
+            .bind(lottery.draw_time)
+            .bind(&lottery.prize_tiers)
+            .bind(lottery.min_tickets)
+            .bind(lottery.id)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_to_lottery_prize(&self, lottery_id: i64, amount: i32) -> Result<(), Error> {
+        sqlx::query("UPDATE lotteries SET base_prize = base_prize + $1 WHERE id = $2")
+            .bind(amount)
+            .bind(lottery_id)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn close_lottery(
+        &self,
+        lottery: &Lottery,
+        prizes: &[(u64, i32)],
+        pot: i32,
+        rollover: i32,
+        next_draw: DateTime<Utc>,
+        params: Option<&str>,
+        outcome: &str,
+    ) -> Result<Option<i64>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let closed = sqlx::query(
+            "UPDATE lotteries SET status = $1, winner = $2, pot = $3, drawn_at = $4 WHERE id = $5 AND status = 'open'",
+        )
+        .bind(if prizes.is_empty() { "rolled" } else { "drawn" })
+        .bind(prizes.first().map(|(w, _)| *w as i64))
+        .bind(pot)
+        .bind(Utc::now())
+        .bind(lottery.id)
+        .execute(&mut *tx)
+        .await?;
+        if closed.rows_affected() == 0 {
+            return Ok(None);
+        }
+        let memo = Reason::LotteryPrize.with_id(lottery.id);
+        for (tier, (user, amount)) in prizes.iter().enumerate() {
+            record_transaction(&mut tx, *user, *amount, Currency::Bucks, &memo).await?;
+            sqlx::query("INSERT INTO lottery_prizes (lottery_id, tier, user_id, amount) VALUES ($1, $2, $3, $4)")
+                .bind(lottery.id)
+                .bind(tier as i32 + 1)
+                .bind(*user as i64)
+                .bind(amount)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let (id,) = sqlx::query_as::<_, (i64,)>("INSERT INTO lotteries (name, ticket_price, base_prize, next_ticket_price, next_base_prize, schedule, draw_time, prize_tiers, min_tickets) VALUES ($1, $2, $3, $2, $4, $5, $6, $7, $8) RETURNING id")
+            .bind(&lottery.name)
+            .bind(lottery.next_ticket_price)
+            .bind(lottery.next_base_prize + rollover)
+            .bind(lottery.next_base_prize)
+            .bind(&lottery.schedule)
+            .bind(next_draw)
+            .bind(&lottery.prize_tiers)
+            .bind(lottery.min_tickets)
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE game_seeds SET params = $1, outcome = $2, revealed_at = $3 WHERE game_id = $4",
+        )
+        .bind(params)
+        .bind(outcome)
+        .bind(Utc::now())
+        .bind(lottery.game_id())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some(id))
+    }
+
+    async fn get_recent_draws(&self, limit: i32) -> Result<Vec<LotteryDraw>, Error> {
+        get_draws(&self.connection, "l.drawn_at DESC", limit).await
     }
-    async fn get_bought_tickets(&self) -> Result<Vec<(u64, i32)>, Error> {
+
+    async fn get_biggest_draws(&self, limit: i32) -> Result<Vec<LotteryDraw>, Error> {
+        get_draws(&self.connection, "l.pot DESC", limit).await
+    }
+
+    async fn get_lottery_stats(&self, user_id: u64) -> Result<LotteryStats, Error> {
+        Ok(sqlx::query_as::<_, LotteryStats>(
+            "SELECT
+                (SELECT COUNT(*) FROM lottery_tickets t JOIN lotteries l ON t.lottery_id = l.id WHERE t.user_id = $1 AND l.status != 'open') as draws_entered,
+                (SELECT COALESCE(SUM(t.tickets), 0) FROM lottery_tickets t WHERE t.user_id = $1) as tickets,
+                (SELECT COALESCE(-SUM(amount), 0) FROM transactions WHERE user_id = $1 AND currency = 'bucks' AND reason = 'lottery_ticket') as spent,
+                (SELECT COUNT(*) FROM lottery_prizes WHERE user_id = $1) as wins,
+                (SELECT COALESCE(SUM(amount), 0) FROM lottery_prizes WHERE user_id = $1) as won",
+        )
+        .bind(user_id as i64)
+        .fetch_one(&self.connection)
+        .await?)
+    }
+
+    async fn buy_lottery_tickets(
+        &self,
+        lottery_id: i64,
+        user_id: u64,
+        amount: i32,
+        memo: Memo,
+    ) -> Result<Option<i32>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let tickets = buy_tickets(&mut tx, lottery_id, user_id, amount, &memo).await?;
+        if tickets.is_some() {
+            tx.commit().await?;
+        }
+        Ok(tickets)
+    }
+
+    async fn get_bought_tickets(&self, lottery_id: i64) -> Result<Vec<(u64, i32)>, Error> {
         let data = sqlx::query_as::<_, (i64, i32)>(
-            "SELECT id, tickets as tickets FROM lottery_tickets ORDER BY tickets DESC",
+            "SELECT user_id, tickets FROM lottery_tickets WHERE lottery_id = $1 ORDER BY tickets DESC",
         )
+        .bind(lottery_id)
         .fetch_all(&self.connection)
         .await?;
         Ok(data
@@ -1062,25 +2689,93 @@ impl LotteryDatabase for Database {
             .collect())
     }
 
-    async fn clear_tickets(&self) -> Result<(), Error> {
-        sqlx::query("DELETE FROM lottery_tickets")
+    async fn get_user_tickets(&self, user_id: u64) -> Result<i32, Error> {
+        let data = sqlx::query_as::<_, Tickets>(
+            "SELECT COALESCE(SUM(t.tickets), 0) as tickets FROM lottery_tickets t JOIN lotteries l ON t.lottery_id = l.id WHERE t.user_id = $1 AND l.status = 'open'",
+        )
+        .bind(user_id as i64)
+        .fetch_one(&self.connection)
+        .await?;
+        Ok(data.tickets)
+    }
+
+    async fn subscribe_lottery(
+        &self,
+        user_id: u64,
+        lottery_name: &str,
+        tickets: i32,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query("INSERT INTO lottery_subscriptions (user_id, lottery_name, tickets, expires_at) VALUES ($1, $2, $3, $4) ON CONFLICT(user_id, lottery_name) DO UPDATE SET tickets = $3, expires_at = $4")
+            .bind(user_id as i64)
+            .bind(lottery_name)
+            .bind(tickets)
+            .bind(expires_at)
             .execute(&self.connection)
             .await?;
         Ok(())
     }
 
-    async fn get_user_tickets(&self, user_id: u64) -> Result<i32, Error> {
-        let data =
-            sqlx::query_as::<_, Tickets>("SELECT tickets FROM lottery_tickets WHERE id = $1")
-                .bind(user_id as i64)
-                .fetch_one(&self.connection)
-                .await;
+    async fn unsubscribe_lottery(
+        &self,
+        user_id: u64,
+        lottery_name: Option<&str>,
+    ) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "DELETE FROM lottery_subscriptions WHERE user_id = $1 AND ($2 IS NULL OR lottery_name = $2 COLLATE NOCASE)",
+        )
+        .bind(user_id as i64)
+        .bind(lottery_name)
+        .execute(&self.connection)
+        .await?;
+        Ok(result.rows_affected())
+    }
 
-        match data {
-            Ok(data) => Ok(data.tickets),
-            Err(sqlx::Error::RowNotFound) => Ok(0),
-            Err(e) => Err(e.into()),
+    async fn get_subscriptions(&self) -> Result<Vec<LotterySubscription>, Error> {
+        Ok(sqlx::query_as::<_, LotterySubscription>(
+            "SELECT user_id, lottery_name, tickets, expires_at, last_lottery_id FROM lottery_subscriptions",
+        )
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn get_user_subscriptions(
+        &self,
+        user_id: u64,
+    ) -> Result<Vec<LotterySubscription>, Error> {
+        Ok(sqlx::query_as::<_, LotterySubscription>(
+            "SELECT user_id, lottery_name, tickets, expires_at, last_lottery_id FROM lottery_subscriptions WHERE user_id = $1 ORDER BY lottery_name",
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn charge_subscription(
+        &self,
+        user_id: u64,
+        lottery_name: &str,
+        lottery_id: i64,
+        tickets: i32,
+        memo: Memo,
+    ) -> Result<Option<i32>, Error> {
+        let mut tx = self.connection.begin().await?;
+        sqlx::query("UPDATE lottery_subscriptions SET last_lottery_id = $1 WHERE user_id = $2 AND lottery_name = $3")
+            .bind(lottery_id)
+            .bind(user_id as i64)
+            .bind(lottery_name)
+            .execute(&mut *tx)
+            .await?;
+        // the purchase runs in a savepoint, so an unaffordable one rolls back on its own
+        let mut purchase = sqlx::Acquire::begin(&mut tx).await?;
+        let bought = buy_tickets(&mut purchase, lottery_id, user_id, tickets, &memo).await?;
+        if bought.is_some() {
+            purchase.commit().await?;
+        } else {
+            purchase.rollback().await?;
         }
+        tx.commit().await?;
+        Ok(bought)
     }
 }
 
@@ -1109,3 +2804,1752 @@ impl ShopDatabase for Database {
         Ok(())
     }
 }
+
+impl EscrowDatabase for Database {
+    async fn escrow_stake(&self, user_id: u64, amount: i32, memo: Memo) -> Result<(), Error> {
+        let game_id = memo
+            .correlation_id
+            .clone()
+            .ok_or("escrow needs a game id")?;
+        let mut tx = self.connection.begin().await?;
+        let remaining = record_transaction(&mut tx, user_id, -amount, Currency::Bucks, &memo)
+            .await?
+            .ok_or("player has no balance")?;
+        if remaining < 0 {
+            return Err("insufficient funds".into());
+        }
+        sqlx::query("INSERT INTO escrow (game_id, game, user_id, amount) VALUES ($1, $2, $3, $4)")
+            .bind(game_id)
+            .bind(memo.reason.as_str())
+            .bind(user_id as i64)
+            .bind(amount)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn settle_escrow(
+        &self,
+        game_id: &str,
+        payouts: &[(u64, i32)],
+        memo: Memo,
+    ) -> Result<(), Error> {
+        self.settle_escrows(&[game_id], payouts, None, memo).await
+    }
+
+    // pays out and closes several escrows at once, the crown's cut is booked as crown tax
+    async fn settle_escrows(
+        &self,
+        game_ids: &[&str],
+        payouts: &[(u64, i32)],
+        crown: Option<(u64, i32)>,
+        memo: Memo,
+    ) -> Result<(), Error> {
+        let mut tx = self.connection.begin().await?;
+        for (user_id, amount) in payouts.iter().filter(|(_, amount)| *amount != 0) {
+            record_transaction(&mut tx, *user_id, *amount, Currency::Bucks, &memo).await?;
+        }
+        if let Some((crown, cut)) = crown.filter(|(_, cut)| *cut != 0) {
+            let memo = memo.as_reason(Reason::CrownTax);
+            record_transaction(&mut tx, crown, cut, Currency::Bucks, &memo).await?;
+        }
+        for game_id in game_ids {
+            sqlx::query("DELETE FROM escrow WHERE game_id = $1")
+                .bind(game_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn refund_escrow(&self, game_id: &str) -> Result<Vec<(u64, i32)>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let stakes = sqlx::query_as::<_, (i64, i32)>(
+            "DELETE FROM escrow WHERE game_id = $1 RETURNING user_id, amount",
+        )
+        .bind(game_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let memo = Reason::EscrowRefund.with_id(game_id);
+        for (user_id, amount) in stakes.iter() {
+            record_transaction(&mut tx, *user_id as u64, *amount, Currency::Bucks, &memo).await?;
+        }
+        tx.commit().await?;
+        Ok(stakes
+            .into_iter()
+            .map(|(user_id, amount)| (user_id as u64, amount))
+            .collect())
+    }
+
+    async fn get_unsettled_games(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(sqlx::query_as::<_, (String, String)>(
+            "SELECT game_id, game FROM escrow GROUP BY game_id ORDER BY MIN(id) ASC",
+        )
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    // what each player has held in the game, in the order they first staked
+    async fn get_stakes(&self, game_id: &str) -> Result<Vec<(u64, i32)>, Error> {
+        let stakes = sqlx::query_as::<_, (i64, i32)>(
+            "SELECT user_id, SUM(amount) FROM escrow WHERE game_id = $1 GROUP BY user_id ORDER BY MIN(id) ASC",
+        )
+        .bind(game_id)
+        .fetch_all(&self.connection)
+        .await?;
+        Ok(stakes
+            .into_iter()
+            .map(|(user_id, amount)| (user_id as u64, amount))
+            .collect())
+    }
+}
+
+impl JobDatabase for Database {
+    async fn get_jobs(&self) -> Result<Vec<JobStatus>, Error> {
+        Ok(sqlx::query_as::<_, JobStatus>(
+            "SELECT name, last_run, next_run, last_error, paused FROM scheduled_jobs",
+        )
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn record_job_run(
+        &self,
+        name: &str,
+        last_run: DateTime<Utc>,
+        next_run: Option<DateTime<Utc>>,
+        error: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query("INSERT INTO scheduled_jobs (name, last_run, next_run, last_error) VALUES ($1, $2, $3, $4) ON CONFLICT(name) DO UPDATE SET last_run = $2, next_run = $3, last_error = $4")
+            .bind(name)
+            .bind(last_run)
+            .bind(next_run)
+            .bind(error)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_job_paused(&self, name: &str, paused: bool) -> Result<(), Error> {
+        sqlx::query("UPDATE scheduled_jobs SET paused = $2 WHERE name = $1")
+            .bind(name)
+            .bind(paused)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+}
+
+const ITEM_COLUMNS: &str =
+    "id, name, description, price, stock, max_per_user, tradeable, expires_after_hours";
+
+// how many of the item the user has that haven't run out
+async fn count_items(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: u64,
+    item_id: i64,
+) -> Result<i32, Error> {
+    let (count,) = sqlx::query_as::<_, (i32,)>(
+        "SELECT COALESCE(SUM(quantity), 0) FROM inventories WHERE user_id = $1 AND item_id = $2 AND (expires_at IS NULL OR expires_at > $3)",
+    )
+    .bind(user_id as i64)
+    .bind(item_id)
+    .bind(Utc::now())
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(count)
+}
+
+impl ItemDatabase for Database {
+    async fn create_item(
+        &self,
+        name: &str,
+        description: &str,
+        price: i32,
+        stock: Option<i32>,
+        max_per_user: Option<i32>,
+        tradeable: bool,
+        expires_after_hours: Option<i32>,
+        effect: Option<Effect>,
+    ) -> Result<i64, Error> {
+        let (id,) = sqlx::query_as::<_, (i64,)>("INSERT INTO items (name, description, price, stock, max_per_user, tradeable, expires_after_hours, effect) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT(name) DO UPDATE SET description = $2, price = $3, stock = $4, max_per_user = $5, tradeable = $6, expires_after_hours = $7, effect = $8, for_sale = TRUE RETURNING id")
+            .bind(name)
+            .bind(description)
+            .bind(price)
+            .bind(stock)
+            .bind(max_per_user)
+            .bind(tradeable)
+            .bind(expires_after_hours)
+            .bind(effect.map(|e| e.as_str()))
+            .fetch_one(&self.connection)
+            .await?;
+        Ok(id)
+    }
+
+    async fn get_items(&self) -> Result<Vec<Item>, Error> {
+        Ok(sqlx::query_as::<_, Item>(
+            format!("SELECT {ITEM_COLUMNS} FROM items WHERE for_sale ORDER BY price, name")
+                .as_str(),
+        )
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn get_item(&self, name: &str) -> Result<Option<Item>, Error> {
+        Ok(sqlx::query_as::<_, Item>(
+            format!("SELECT {ITEM_COLUMNS} FROM items WHERE name = $1 COLLATE NOCASE").as_str(),
+        )
+        .bind(name)
+        .fetch_optional(&self.connection)
+        .await?)
+    }
+
+    async fn retire_item(&self, id: i64) -> Result<(), Error> {
+        sqlx::query("UPDATE items SET for_sale = FALSE WHERE id = $1")
+            .bind(id)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn buy_item(
+        &self,
+        user_id: u64,
+        item_id: i64,
+        amount: i32,
+        memo: Memo,
+    ) -> Result<Result<(), ItemRefused>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let item = sqlx::query_as::<_, Item>(
+            format!("SELECT {ITEM_COLUMNS} FROM items WHERE id = $1 AND for_sale").as_str(),
+        )
+        .bind(item_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let item = match item {
+            Some(item) => item,
+            None => return Ok(Err(ItemRefused::NotForSale)),
+        };
+        if item.stock.is_some_and(|stock| stock < amount) {
+            return Ok(Err(ItemRefused::SoldOut));
+        }
+        if let Some(max) = item.max_per_user {
+            if count_items(&mut tx, user_id, item_id).await? + amount > max {
+                return Ok(Err(ItemRefused::TooMany(max)));
+            }
+        }
+        match record_transaction(
+            &mut tx,
+            user_id,
+            -item.price * amount,
+            Currency::Bucks,
+            &memo,
+        )
+        .await?
+        {
+            Some(remaining) if remaining >= 0 => {}
+            _ => return Ok(Err(ItemRefused::CantAfford)),
+        }
+
+        sqlx::query("UPDATE items SET stock = stock - $1 WHERE id = $2 AND stock IS NOT NULL")
+            .bind(amount)
+            .bind(item_id)
+            .execute(&mut *tx)
+            .await?;
+        let expires_at = item
+            .expires_after_hours
+            .map(|hours| Utc::now() + chrono::Duration::hours(hours as i64));
+        sqlx::query("INSERT INTO inventories (user_id, item_id, quantity, expires_at) VALUES ($1, $2, $3, $4)")
+            .bind(user_id as i64)
+            .bind(item_id)
+            .bind(amount)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Ok(()))
+    }
+
+    async fn give_item(
+        &self,
+        from: u64,
+        to: u64,
+        item_id: i64,
+        amount: i32,
+    ) -> Result<Result<(), ItemRefused>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let item = sqlx::query_as::<_, Item>(
+            format!("SELECT {ITEM_COLUMNS} FROM items WHERE id = $1").as_str(),
+        )
+        .bind(item_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !item.tradeable {
+            return Ok(Err(ItemRefused::NotTradeable));
+        }
+        if count_items(&mut tx, from, item_id).await? < amount {
+            return Ok(Err(ItemRefused::NotEnough));
+        }
+        if let Some(max) = item.max_per_user {
+            if count_items(&mut tx, to, item_id).await? + amount > max {
+                return Ok(Err(ItemRefused::TooMany(max)));
+            }
+        }
+
+        let lots = sqlx::query_as::<_, (i64, i32, Option<DateTime<Utc>>)>(
+            "SELECT id, quantity, expires_at FROM inventories WHERE user_id = $1 AND item_id = $2 AND (expires_at IS NULL OR expires_at > $3) ORDER BY expires_at IS NULL, expires_at, id",
+        )
+        .bind(from as i64)
+        .bind(item_id)
+        .bind(Utc::now())
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut left = amount;
+        for (id, quantity, expires_at) in lots {
+            if left == 0 {
+                break;
+            }
+            let moved = quantity.min(left);
+            left -= moved;
+            sqlx::query("UPDATE inventories SET quantity = quantity - $1 WHERE id = $2")
+                .bind(moved)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO inventories (user_id, item_id, quantity, expires_at) VALUES ($1, $2, $3, $4)")
+                .bind(to as i64)
+                .bind(item_id)
+                .bind(moved)
+                .bind(expires_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM inventories WHERE quantity <= 0")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Ok(()))
+    }
+
+    async fn get_inventory(&self, user_id: u64) -> Result<Vec<InventoryItem>, Error> {
+        Ok(sqlx::query_as::<_, InventoryItem>(
+            "SELECT items.name, SUM(i.quantity) AS quantity, MIN(i.expires_at) AS expires_at FROM inventories i JOIN items ON items.id = i.item_id WHERE i.user_id = $1 AND (i.expires_at IS NULL OR i.expires_at > $2) GROUP BY i.item_id, items.name ORDER BY items.name",
+        )
+        .bind(user_id as i64)
+        .bind(Utc::now())
+        .fetch_all(&self.connection)
+        .await?)
+    }
+}
+
+const MARKET_ORDER_COLUMNS: &str = "id, user_id, side, price, quantity";
+
+impl MarketDatabase for Database {
+    async fn place_order(
+        &self,
+        user_id: u64,
+        side: OrderSide,
+        quantity: i32,
+        price: i32,
+    ) -> Result<Result<OrderPlaced, OrderRefused>, Error> {
+        let mut tx = self.connection.begin().await?;
+        // sold bones come out of the oldest lot first, the order keeps its date
+        let acquired_at = match side {
+            OrderSide::Buy => None,
+            OrderSide::Sell => sqlx::query_as::<_, (DateTime<Utc>,)>(
+                "SELECT acquired_at FROM bones_lots WHERE user_id = $1 ORDER BY julianday(acquired_at) ASC, id ASC LIMIT 1",
+            )
+            .bind(user_id as i64)
+            .fetch_optional(&mut *tx)
+            .await?
+            .map(|(at,)| at)
+            .or(Some(Utc::now())),
+        };
+        let (id,) = sqlx::query_as::<_, (i64,)>(
+            "INSERT INTO market_orders (user_id, side, price, quantity, acquired_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        )
+        .bind(user_id as i64)
+        .bind(side.as_str())
+        .bind(price)
+        .bind(quantity)
+        .bind(acquired_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let memo = Reason::MarketOrder.with_id(format!("market-{id}"));
+        let (escrow, currency, refused) = match side {
+            OrderSide::Buy => (-price * quantity, Currency::Bucks, OrderRefused::CantAfford),
+            OrderSide::Sell => (-quantity, Currency::Bones, OrderRefused::NotEnoughBones),
+        };
+        match record_transaction(&mut tx, user_id, escrow, currency, &memo).await? {
+            Some(remaining) if remaining >= 0 => {}
+            _ => return Ok(Err(refused)),
+        }
+
+        let mut remaining = quantity;
+        let mut fills = vec![];
+        while remaining > 0 {
+            let query = match side {
+                OrderSide::Buy => format!("SELECT {MARKET_ORDER_COLUMNS} FROM market_orders WHERE status = 'open' AND side = 'sell' AND price <= $1 AND user_id != $2 ORDER BY price, id LIMIT 1"),
+                OrderSide::Sell => format!("SELECT {MARKET_ORDER_COLUMNS} FROM market_orders WHERE status = 'open' AND side = 'buy' AND price >= $1 AND user_id != $2 ORDER BY price DESC, id LIMIT 1"),
+            };
+            let resting = sqlx::query_as::<_, MarketOrder>(query.as_str())
+                .bind(price)
+                .bind(user_id as i64)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let resting = match resting {
+                Some(order) => order,
+                None => break,
+            };
+
+            let filled = remaining.min(resting.quantity);
+            let (buyer, buy_order, seller, sell_order) = match side {
+                OrderSide::Buy => (user_id, id, resting.user_id as u64, resting.id),
+                OrderSide::Sell => (resting.user_id as u64, resting.id, user_id, id),
+            };
+            let (trade,) = sqlx::query_as::<_, (i64,)>(
+                "INSERT INTO market_trades (buy_order, sell_order, price, quantity) VALUES ($1, $2, $3, $4) RETURNING id",
+            )
+            .bind(buy_order)
+            .bind(sell_order)
+            .bind(resting.price)
+            .bind(filled)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            let memo = Reason::MarketTrade.with_id(format!("market-trade-{trade}"));
+            record_transaction(&mut tx, buyer, filled, Currency::Bones, &memo).await?;
+            record_transaction(
+                &mut tx,
+                seller,
+                resting.price * filled,
+                Currency::Bucks,
+                &memo,
+            )
+            .await?;
+            if side == OrderSide::Buy && price > resting.price {
+                // the buyer escrowed their own price, the difference goes back
+                record_transaction(
+                    &mut tx,
+                    buyer,
+                    (price - resting.price) * filled,
+                    Currency::Bucks,
+                    &memo,
+                )
+                .await?;
+            }
+            sqlx::query("UPDATE market_orders SET quantity = quantity - $1, status = CASE WHEN quantity = $1 THEN 'filled' ELSE status END WHERE id IN ($2, $3)")
+                .bind(filled)
+                .bind(id)
+                .bind(resting.id)
+                .execute(&mut *tx)
+                .await?;
+
+            remaining -= filled;
+            fills.push(Fill {
+                user_id: resting.user_id as u64,
+                quantity: filled,
+                price: resting.price,
+            });
+        }
+        tx.commit().await?;
+        Ok(Ok(OrderPlaced {
+            id,
+            fills,
+            remaining,
+        }))
+    }
+
+    async fn cancel_order(&self, id: i64, user_id: u64) -> Result<Option<MarketOrder>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let order = sqlx::query_as::<_, MarketOrder>(
+            format!("UPDATE market_orders SET status = 'cancelled' WHERE id = $1 AND user_id = $2 AND status = 'open' RETURNING {MARKET_ORDER_COLUMNS}").as_str(),
+        )
+        .bind(id)
+        .bind(user_id as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let order = match order {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+        let memo = Reason::EscrowRefund.with_id(order.game_id());
+        if order.side == OrderSide::Buy.as_str() {
+            record_transaction(
+                &mut tx,
+                user_id,
+                order.price * order.quantity,
+                Currency::Bucks,
+                &memo,
+            )
+            .await?;
+        } else {
+            record_transaction(&mut tx, user_id, order.quantity, Currency::Bones, &memo).await?;
+            // the bones go back as old as they were when the order took them
+            sqlx::query("UPDATE bones_lots SET acquired_at = (SELECT acquired_at FROM market_orders WHERE id = $1) WHERE id = (SELECT MAX(id) FROM bones_lots WHERE user_id = $2)")
+                .bind(order.id)
+                .bind(user_id as i64)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(Some(order))
+    }
+
+    async fn get_order_book(&self, side: OrderSide, depth: i64) -> Result<Vec<(i32, i32)>, Error> {
+        let order = match side {
+            OrderSide::Buy => "DESC",
+            OrderSide::Sell => "ASC",
+        };
+        Ok(sqlx::query_as::<_, (i32, i32)>(
+            format!("SELECT price, SUM(quantity) FROM market_orders WHERE status = 'open' AND side = $1 GROUP BY price ORDER BY price {order} LIMIT $2").as_str(),
+        )
+        .bind(side.as_str())
+        .bind(depth)
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn get_user_orders(&self, user_id: u64) -> Result<Vec<MarketOrder>, Error> {
+        Ok(sqlx::query_as::<_, MarketOrder>(
+            format!("SELECT {MARKET_ORDER_COLUMNS} FROM market_orders WHERE user_id = $1 AND status = 'open' ORDER BY id").as_str(),
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn get_last_trade_price(&self) -> Result<Option<i32>, Error> {
+        Ok(
+            sqlx::query_as::<_, (i32,)>("SELECT price FROM market_trades ORDER BY id DESC LIMIT 1")
+                .fetch_optional(&self.connection)
+                .await?
+                .map(|(price,)| price),
+        )
+    }
+}
+
+impl EffectDatabase for Database {
+    async fn has_effect(&self, user_id: u64, effect: Effect) -> Result<bool, Error> {
+        let (has,) = sqlx::query_as::<_, (bool,)>(
+            "SELECT EXISTS (SELECT 1 FROM inventories i JOIN items ON items.id = i.item_id WHERE i.user_id = $1 AND items.effect = $2 AND i.quantity > 0 AND (i.expires_at IS NULL OR i.expires_at > $3))",
+        )
+        .bind(user_id as i64)
+        .bind(effect.as_str())
+        .bind(Utc::now())
+        .fetch_one(&self.connection)
+        .await?;
+        Ok(has)
+    }
+
+    async fn consume_effect(&self, user_id: u64, effect: Effect) -> Result<bool, Error> {
+        let mut tx = self.connection.begin().await?;
+        let used = use_effect(&mut tx, user_id, effect).await?;
+        tx.commit().await?;
+        Ok(used)
+    }
+
+    async fn award_daily(&self, user_id: u64, amount: i32, memo: Memo) -> Result<bool, Error> {
+        let mut tx = self.connection.begin().await?;
+        let doubled = use_effect(&mut tx, user_id, Effect::DoubleDaily).await?;
+        let award = if doubled { amount * 2 } else { amount };
+        record_transaction(&mut tx, user_id, award, Currency::Bucks, &memo).await?;
+        tx.commit().await?;
+        Ok(doubled)
+    }
+}
+
+/// Takes one of the user's items with the effect, the one running out soonest
+async fn use_effect(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: u64,
+    effect: Effect,
+) -> Result<bool, Error> {
+    let used = sqlx::query(
+        "UPDATE inventories SET quantity = quantity - 1 WHERE id = (SELECT i.id FROM inventories i JOIN items ON items.id = i.item_id WHERE i.user_id = $1 AND items.effect = $2 AND i.quantity > 0 AND (i.expires_at IS NULL OR i.expires_at > $3) ORDER BY i.expires_at IS NULL, i.expires_at, i.id LIMIT 1)",
+    )
+    .bind(user_id as i64)
+    .bind(effect.as_str())
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        > 0;
+    sqlx::query("DELETE FROM inventories WHERE quantity <= 0")
+        .execute(&mut *conn)
+        .await?;
+    Ok(used)
+}
+
+const AUCTION_COLUMNS: &str = "id, role_id, guild_id, channel_id, message_id, reserve, start_time, end_time, status, high_bidder, high_bid";
+
+impl AuctionDatabase for Database {
+    async fn create_auction(
+        &self,
+        role_id: u64,
+        guild_id: u64,
+        channel_id: u64,
+        reserve: i32,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<i64, Error> {
+        let (id,) = sqlx::query_as::<_, (i64,)>("INSERT INTO auctions (role_id, guild_id, channel_id, reserve, start_time, end_time) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id")
+            .bind(role_id as i64)
+            .bind(guild_id as i64)
+            .bind(channel_id as i64)
+            .bind(reserve)
+            .bind(start_time)
+            .bind(end_time)
+            .fetch_one(&self.connection)
+            .await?;
+        Ok(id)
+    }
+
+    async fn get_auction(&self, id: i64) -> Result<Option<Auction>, Error> {
+        Ok(sqlx::query_as::<_, Auction>(
+            format!("SELECT {AUCTION_COLUMNS} FROM auctions WHERE id = $1").as_str(),
+        )
+        .bind(id)
+        .fetch_optional(&self.connection)
+        .await?)
+    }
+
+    async fn get_active_auctions(&self) -> Result<Vec<Auction>, Error> {
+        Ok(sqlx::query_as::<_, Auction>(
+            format!("SELECT {AUCTION_COLUMNS} FROM auctions WHERE status IN ('pending', 'open') ORDER BY end_time, id").as_str(),
+        )
+        .fetch_all(&self.connection)
+        .await?)
+    }
+
+    async fn open_auction(&self, id: i64, message_id: u64) -> Result<(), Error> {
+        sqlx::query("UPDATE auctions SET status = 'open', message_id = $1 WHERE id = $2")
+            .bind(message_id as i64)
+            .bind(id)
+            .execute(&self.connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn place_bid(
+        &self,
+        id: i64,
+        user_id: u64,
+        amount: i32,
+        now: DateTime<Utc>,
+        snipe_window: chrono::Duration,
+    ) -> Result<Result<BidPlaced, BidRefused>, Error> {
+        let mut tx = self.connection.begin().await?;
+        let auction = sqlx::query_as::<_, Auction>(
+            format!("SELECT {AUCTION_COLUMNS} FROM auctions WHERE id = $1").as_str(),
+        )
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let mut auction = match auction {
+            Some(a) if a.status == "open" && a.end_time > now => a,
+            _ => return Ok(Err(BidRefused::Closed)),
+        };
+        if amount < auction.min_bid() {
+            return Ok(Err(BidRefused::TooLow(auction.min_bid())));
+        }
+
+        let game_id = auction.game_id();
+        let outbid = sqlx::query_as::<_, (i64, i32)>(
+            "DELETE FROM escrow WHERE game_id = $1 RETURNING user_id, amount",
+        )
+        .bind(&game_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some((outbid, refund)) = outbid {
+            let memo = Reason::EscrowRefund.with_id(&game_id);
+            record_transaction(&mut tx, outbid as u64, refund, Currency::Bucks, &memo).await?;
+        }
+
+        let memo = Reason::AuctionBid.with_id(&game_id);
+        match record_transaction(&mut tx, user_id, -amount, Currency::Bucks, &memo).await? {
+            Some(remaining) if remaining >= 0 => {}
+            _ => return Ok(Err(BidRefused::CantAfford)),
+        }
+        sqlx::query("INSERT INTO escrow (game_id, game, user_id, amount) VALUES ($1, $2, $3, $4)")
+            .bind(&game_id)
+            .bind(memo.reason.as_str())
+            .bind(user_id as i64)
+            .bind(amount)
+            .execute(&mut *tx)
+            .await?;
+
+        let extended = auction.end_time - now < snipe_window;
+        if extended {
+            auction.end_time = now + snipe_window;
+        }
+        sqlx::query(
+            "UPDATE auctions SET high_bidder = $1, high_bid = $2, end_time = $3 WHERE id = $4",
+        )
+        .bind(user_id as i64)
+        .bind(amount)
+        .bind(auction.end_time)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let outbid = auction
+            .high_bidder
+            .zip(auction.high_bid)
+            .map(|(user, bid)| (user as u64, bid));
+        auction.high_bidder = Some(user_id as i64);
+        auction.high_bid = Some(amount);
+        Ok(Ok(BidPlaced {
+            auction,
+            outbid,
+            extended,
+        }))
+    }
+
+    async fn close_auction(&self, id: i64) -> Result<(), Error> {
+        let mut tx = self.connection.begin().await?;
+        sqlx::query("UPDATE auctions SET status = CASE WHEN high_bidder IS NULL THEN 'unsold' ELSE 'sold' END WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM escrow WHERE game_id = $1")
+            .bind(format!("auction-{id}"))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn cancel_auction(&self, id: i64) -> Result<Vec<(u64, i32)>, Error> {
+        let refunds = self.refund_escrow(&format!("auction-{id}")).await?;
+        sqlx::query("UPDATE auctions SET status = 'cancelled' WHERE id = $1")
+            .bind(id)
+            .execute(&self.connection)
+            .await?;
+        Ok(refunds)
+    }
+}
+
+impl SeedDatabase for Database {
+    async fn commit_seed(
+        &self,
+        game_id: &str,
+        game: &str,
+        seed: &str,
+        commitment: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO game_seeds (game_id, game, seed, commitment) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(game_id)
+        .bind(game)
+        .bind(seed)
+        .bind(commitment)
+        .execute(&self.connection)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_seed(&self, game_id: &str) -> Result<Option<GameSeed>, Error> {
+        Ok(sqlx::query_as::<_, GameSeed>(
+            "SELECT game_id, game, seed, commitment, params, outcome, revealed_at FROM game_seeds WHERE game_id = $1",
+        )
+        .bind(game_id)
+        .fetch_optional(&self.connection)
+        .await?)
+    }
+
+    async fn reveal_seed(
+        &self,
+        game_id: &str,
+        params: Option<&str>,
+        outcome: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "UPDATE game_seeds SET params = $1, outcome = $2, revealed_at = $3 WHERE game_id = $4",
+        )
+        .bind(params)
+        .bind(outcome)
+        .bind(Utc::now())
+        .bind(game_id)
+        .execute(&self.connection)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rand::Rng;
+
+    pub(crate) fn new_user_id() -> u64 {
+        rand::thread_rng().gen_range::<u64, _>(0..1000000000000000000)
+    }
+
+    /// A fresh in-memory database with `N` new players on the starting balance
+    pub(crate) async fn setup<const N: usize>() -> (Database, [u64; N]) {
+        let db = Database::new().await.unwrap();
+        let players = std::array::from_fn(|_| new_user_id());
+        for player in players {
+            db.get_balance(player).await.unwrap();
+        }
+        (db, players)
+    }
+
+    #[tokio::test]
+    async fn test_crown_time_accrues_once() {
+        let (db, [first, second]) = setup().await;
+        let crown_role_id = 1;
+        assert_eq!(
+            db.accrue_crown_time(crown_role_id, chrono::Utc::now())
+                .await
+                .unwrap(),
+            None
+        );
+
+        db.set_unique_role_holder(crown_role_id, first, 10, TenureEnd::Bought)
+            .await
+            .unwrap();
+        let holder = db
+            .get_unique_role_holder(crown_role_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let later = holder.credited_until + chrono::Duration::minutes(90);
+        let (user, hours) = db
+            .accrue_crown_time(crown_role_id, later)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((user, hours), (first, 1.5));
+        // running again for the same moment (e.g. right after a restart) adds nothing
+        db.accrue_crown_time(crown_role_id, later).await.unwrap();
+        assert_eq!(db.get_crown_time(first).await.unwrap().1, 1.5);
+
+        // a new holder starts from their own purchase
+        db.set_unique_role_holder(crown_role_id, second, 11, TenureEnd::Bought)
+            .await
+            .unwrap();
+        let holder = db
+            .get_unique_role_holder(crown_role_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(holder.user_id, second);
+        db.accrue_crown_time(
+            crown_role_id,
+            holder.credited_until + chrono::Duration::hours(2),
+        )
+        .await
+        .unwrap();
+        assert_eq!(db.get_crown_time(first).await.unwrap().1, 1.5);
+        assert_eq!(db.get_crown_time(second).await.unwrap().1, 2.0);
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_role_history() {
+        let (db, [first, second]) = setup().await;
+        let role_id = 2;
+        db.set_unique_role_holder(role_id, first, 10, TenureEnd::Bought)
+            .await
+            .unwrap();
+        db.set_unique_role_holder(role_id, second, 11, TenureEnd::Bought)
+            .await
+            .unwrap();
+        db.set_unique_role_holder(role_id, first, 12, TenureEnd::Auction)
+            .await
+            .unwrap();
+
+        let history = db.get_role_history(role_id, 10).await.unwrap();
+        let owners = history
+            .iter()
+            .map(|t| (t.user_id as u64, t.price, t.lost_how.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            owners,
+            vec![
+                (first, Some(12), None),
+                (second, Some(11), Some("auctioned")),
+                (first, Some(10), Some("bought")),
+            ]
+        );
+        assert!(history[0].lost.is_none() && history[1].lost.is_some());
+
+        let leaderboard = db.get_role_leaderboard(role_id).await.unwrap();
+        assert_eq!(leaderboard.len(), 2);
+        assert!(db.get_role_history(3, 10).await.unwrap().is_empty());
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sell_role() {
+        let (db, [player]) = setup().await;
+        let (vip, crown) = (20, 21);
+        db.set_role_price(vip, 100, Some(10), None, Some(false))
+            .await
+            .unwrap();
+        db.set_role_price(crown, 100, Some(10), None, Some(true))
+            .await
+            .unwrap();
+        db.record_role_purchase(vip, player, 90).await.unwrap();
+        db.set_unique_role_holder(crown, player, 80, TenureEnd::Bought)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_role_tenure(vip, player)
+                .await
+                .unwrap()
+                .unwrap()
+                .price,
+            Some(90)
+        );
+
+        let memo = |role: u64| Reason::RoleSale.with_id(role);
+        assert!(db.sell_role(vip, player, 45, memo(vip)).await.unwrap());
+        assert!(db.sell_role(crown, player, 40, memo(crown)).await.unwrap());
+        assert_eq!(db.get_balance(player).await.unwrap(), 50 + 45 + 40);
+        // selling twice doesn't pay twice
+        assert!(!db.sell_role(vip, player, 45, memo(vip)).await.unwrap());
+        assert_eq!(db.get_balance(player).await.unwrap(), 50 + 45 + 40);
+        assert!(db.get_role_tenure(vip, player).await.unwrap().is_none());
+        assert!(db.get_unique_role_holder(crown).await.unwrap().is_none());
+        let history = db.get_role_history(crown, 1).await.unwrap();
+        assert_eq!(history[0].lost_how.as_deref(), Some("sold"));
+
+        let roles = db.get_purchasable_roles().await.unwrap();
+        for role in roles
+            .iter()
+            .filter(|r| r.role_id == vip || r.role_id == crown)
+        {
+            assert_eq!(role.price, 90);
+        }
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auction_bids() {
+        let (db, [p1, p2]) = setup().await;
+        let start = chrono::Utc::now();
+        let end = start + chrono::Duration::hours(1);
+        let window = chrono::Duration::minutes(2);
+        let id = db.create_auction(1, 2, 3, 20, start, end).await.unwrap();
+
+        // nothing can be bid before the auction is posted
+        let refused = db.place_bid(id, p1, 20, start, window).await.unwrap();
+        assert_eq!(refused.unwrap_err(), BidRefused::Closed);
+        db.open_auction(id, 4).await.unwrap();
+
+        let refused = db.place_bid(id, p1, 10, start, window).await.unwrap();
+        assert_eq!(refused.unwrap_err(), BidRefused::TooLow(20));
+        let placed = db
+            .place_bid(id, p1, 20, start, window)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((placed.outbid, placed.extended), (None, false));
+        assert_eq!(db.get_balance(p1).await.unwrap(), 30);
+
+        let refused = db.place_bid(id, p2, 20, start, window).await.unwrap();
+        assert_eq!(refused.unwrap_err(), BidRefused::TooLow(21));
+        // a bid that can't be paid leaves the high bidder's escrow alone
+        let refused = db.place_bid(id, p2, 60, start, window).await.unwrap();
+        assert_eq!(refused.unwrap_err(), BidRefused::CantAfford);
+        assert_eq!(db.get_balance(p1).await.unwrap(), 30);
+
+        let placed = db
+            .place_bid(id, p2, 25, start, window)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(placed.outbid, Some((p1, 20)));
+        assert_eq!(db.get_balance(p1).await.unwrap(), 50);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 25);
+
+        // a last minute bid pushes the end back
+        let late = end - chrono::Duration::minutes(1);
+        let placed = db
+            .place_bid(id, p1, 30, late, window)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(placed.extended);
+        assert_eq!(placed.auction.end_time, late + window);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 50);
+
+        db.close_auction(id).await.unwrap();
+        let auction = db.get_auction(id).await.unwrap().unwrap();
+        assert_eq!(
+            (auction.status.as_str(), auction.high_bidder),
+            ("sold", Some(p1 as i64))
+        );
+        assert!(db.get_unsettled_games().await.unwrap().is_empty());
+        assert_eq!(db.get_balance(p1).await.unwrap(), 20);
+
+        let id = db.create_auction(1, 2, 3, 20, start, end).await.unwrap();
+        db.open_auction(id, 5).await.unwrap();
+        db.place_bid(id, p2, 20, start, window)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(db.cancel_auction(id).await.unwrap(), vec![(p2, 20)]);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 50);
+        assert!(db.get_active_auctions().await.unwrap().is_empty());
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_items() {
+        let (db, [p1, p2]) = setup().await;
+        let shield = db
+            .create_item(
+                "Robbery Shield",
+                "",
+                10,
+                Some(3),
+                Some(2),
+                false,
+                Some(24),
+                None,
+            )
+            .await
+            .unwrap();
+        let charm = db
+            .create_item("Lucky Charm", "", 5, None, None, true, None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_item("robbery shield").await.unwrap().unwrap().id,
+            shield
+        );
+
+        let memo = || Reason::ItemPurchase.with_id("test");
+        assert_eq!(
+            db.buy_item(p1, shield, 3, memo()).await.unwrap(),
+            Err(ItemRefused::TooMany(2))
+        );
+        db.buy_item(p1, shield, 2, memo()).await.unwrap().unwrap();
+        assert_eq!(
+            db.buy_item(p2, shield, 2, memo()).await.unwrap(),
+            Err(ItemRefused::SoldOut)
+        );
+        assert_eq!(
+            db.buy_item(p2, charm, 11, memo()).await.unwrap(),
+            Err(ItemRefused::CantAfford)
+        );
+        assert_eq!(db.get_balance(p2).await.unwrap(), 50);
+        db.buy_item(p1, charm, 3, memo()).await.unwrap().unwrap();
+        assert_eq!(db.get_balance(p1).await.unwrap(), 15);
+
+        assert_eq!(
+            db.give_item(p1, p2, shield, 1).await.unwrap(),
+            Err(ItemRefused::NotTradeable)
+        );
+        assert_eq!(
+            db.give_item(p1, p2, charm, 4).await.unwrap(),
+            Err(ItemRefused::NotEnough)
+        );
+        db.give_item(p1, p2, charm, 2).await.unwrap().unwrap();
+
+        let inventory = db.get_inventory(p1).await.unwrap();
+        let held = inventory
+            .iter()
+            .map(|i| (i.name.as_str(), i.quantity, i.expires_at.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            held,
+            vec![("Lucky Charm", 1, false), ("Robbery Shield", 2, true)]
+        );
+        assert_eq!(db.get_inventory(p2).await.unwrap()[0].quantity, 2);
+
+        db.retire_item(shield).await.unwrap();
+        assert_eq!(db.get_items().await.unwrap().len(), 1);
+        assert_eq!(
+            db.buy_item(p2, shield, 1, memo()).await.unwrap(),
+            Err(ItemRefused::NotForSale)
+        );
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bones_flow() {
+        let (db, [player]) = setup().await;
+        let since = chrono::Utc::now() - chrono::Duration::minutes(1);
+        db.add_bones(player, 7, Reason::BonesPurchase.into())
+            .await
+            .unwrap();
+        db.remove_bones(player, 3, Reason::BonesSale.into())
+            .await
+            .unwrap();
+        db.remove_bones(player, 1, Reason::Bury.into())
+            .await
+            .unwrap();
+        assert_eq!(db.get_bones_flow(since).await.unwrap(), (7, 3));
+        assert_eq!(
+            db.get_bones_flow(chrono::Utc::now() + chrono::Duration::minutes(1))
+                .await
+                .unwrap(),
+            (0, 0)
+        );
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bones_price_history() {
+        use crate::chart::{price_chart, price_stats, PriceStats};
+        let (db, []) = setup().await;
+        let now = chrono::Utc::now();
+        let day = chrono::Duration::days(1);
+        for (days_ago, price) in [(10, 40), (5, 20), (3, 30), (1, 25)] {
+            db.record_bones_price(price, now - day * days_ago)
+                .await
+                .unwrap();
+        }
+
+        // the window starts with the price that was in effect at its start
+        let prices = db.get_bones_prices(now - day * 7).await.unwrap();
+        assert_eq!(
+            prices.iter().map(|p| p.1).collect::<Vec<_>>(),
+            vec![40, 20, 30, 25]
+        );
+        assert_eq!(
+            price_stats(&prices),
+            Some(PriceStats {
+                min: 20,
+                max: 40,
+                average: 28.75
+            })
+        );
+        assert_eq!(db.get_bones_prices(now).await.unwrap().len(), 1);
+        assert_eq!(price_stats(&[]), None);
+
+        let png = price_chart(&prices, now - day * 7, now).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_market_orders() {
+        let (db, [seller, buyer]) = setup().await;
+        db.add_bones(seller, 10, Reason::BonesPurchase.into())
+            .await
+            .unwrap();
+
+        let bid = db
+            .place_order(buyer, OrderSide::Buy, 3, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(bid.fills.is_empty());
+        assert_eq!(db.get_balance(buyer).await.unwrap(), 20);
+
+        // a crossing order trades at the price already on the book
+        let ask = db
+            .place_order(seller, OrderSide::Sell, 5, 8)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ask.fills,
+            vec![Fill {
+                user_id: buyer,
+                quantity: 3,
+                price: 10
+            }]
+        );
+        assert_eq!(ask.remaining, 2);
+        assert_eq!(db.get_balance(seller).await.unwrap(), 80);
+        assert_eq!(db.get_bones(seller).await.unwrap(), 5);
+        assert_eq!(db.get_bones(buyer).await.unwrap(), 3);
+
+        assert_eq!(
+            db.place_order(buyer, OrderSide::Buy, 4, 9)
+                .await
+                .unwrap()
+                .unwrap_err(),
+            OrderRefused::CantAfford
+        );
+        assert_eq!(
+            db.place_order(buyer, OrderSide::Sell, 4, 9)
+                .await
+                .unwrap()
+                .unwrap_err(),
+            OrderRefused::NotEnoughBones
+        );
+        assert_eq!(db.get_balance(buyer).await.unwrap(), 20);
+
+        // bidding above the ask gets the difference back
+        db.place_order(buyer, OrderSide::Buy, 1, 9)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(db.get_balance(buyer).await.unwrap(), 12);
+        assert_eq!(db.get_last_trade_price().await.unwrap(), Some(8));
+        assert_eq!(
+            db.get_order_book(OrderSide::Sell, 10).await.unwrap(),
+            vec![(8, 1)]
+        );
+
+        // orders never fill against the same user
+        let own = db
+            .place_order(seller, OrderSide::Buy, 1, 8)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(own.remaining, 1);
+        assert!(db.cancel_order(own.id, buyer).await.unwrap().is_none());
+        db.cancel_order(own.id, seller).await.unwrap().unwrap();
+        db.cancel_order(ask.id, seller).await.unwrap().unwrap();
+        assert_eq!(db.get_balance(seller).await.unwrap(), 88);
+        assert_eq!(db.get_bones(seller).await.unwrap(), 6);
+        assert!(db.get_user_orders(seller).await.unwrap().is_empty());
+
+        // escrowed bones decay like the rest
+        db.place_order(buyer, OrderSide::Sell, 4, 20)
+            .await
+            .unwrap()
+            .unwrap();
+        let decayed = db.decay_bones(100, None).await.unwrap();
+        assert!(decayed.iter().any(|(user, _)| *user == buyer));
+        assert!(db.get_user_orders(buyer).await.unwrap().is_empty());
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bones_decay_by_lot() {
+        let (db, [p1, p2]) = setup().await;
+        db.add_bones(p1, 10, Reason::BonesPurchase.into())
+            .await
+            .unwrap();
+        // selling takes from the oldest lot
+        db.remove_bones(p1, 4, Reason::BonesSale.into())
+            .await
+            .unwrap();
+        let bought = chrono::Utc::now();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        db.add_bones(p1, 5, Reason::BonesPurchase.into())
+            .await
+            .unwrap();
+
+        let day_ago = chrono::Utc::now() - chrono::Duration::days(1);
+        assert!(db
+            .get_decaying_bones(50, Some(day_ago))
+            .await
+            .unwrap()
+            .is_empty());
+        // half of the 6 older bones, the 5 bought later are too new
+        assert_eq!(
+            db.get_decaying_bones(50, Some(bought)).await.unwrap(),
+            vec![(p1, 3)]
+        );
+        assert_eq!(
+            db.decay_bones(100, Some(bought)).await.unwrap(),
+            vec![(p1, 6)]
+        );
+        assert_eq!(db.get_bones(p1).await.unwrap(), 5);
+
+        // bones back from a cancelled sell order are as old as they were
+        let order = db
+            .place_order(p1, OrderSide::Sell, 5, 30)
+            .await
+            .unwrap()
+            .unwrap();
+        let cancelled = chrono::Utc::now();
+        assert!(db.cancel_order(order.id, p1).await.unwrap().is_some());
+        assert_eq!(
+            db.get_decaying_bones(100, Some(cancelled)).await.unwrap(),
+            vec![(p1, 5)]
+        );
+
+        // a partial decay rounds up and shrinks sell orders too
+        db.add_bones(p2, 4, Reason::BonesPurchase.into())
+            .await
+            .unwrap();
+        db.place_order(p2, OrderSide::Sell, 4, 30)
+            .await
+            .unwrap()
+            .unwrap();
+        let decayed = db.decay_bones(50, None).await.unwrap();
+        assert!(decayed.contains(&(p1, 3)));
+        assert!(decayed.contains(&(p2, 2)));
+        assert_eq!(db.get_bones(p1).await.unwrap(), 2);
+        assert_eq!(db.get_user_orders(p2).await.unwrap()[0].quantity, 2);
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_item_effects() {
+        let (db, [p1, p2]) = setup().await;
+        let charm = db
+            .create_item(
+                "Lucky Charm",
+                "",
+                5,
+                None,
+                None,
+                true,
+                None,
+                Some(Effect::LuckyCharm),
+            )
+            .await
+            .unwrap();
+        db.buy_item(p1, charm, 1, Reason::ItemPurchase.with_id("test"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(db.has_effect(p1, Effect::LuckyCharm).await.unwrap());
+        assert!(!db.has_effect(p1, Effect::RobberyShield).await.unwrap());
+        assert!(!db.consume_effect(p2, Effect::LuckyCharm).await.unwrap());
+        assert!(db.consume_effect(p1, Effect::LuckyCharm).await.unwrap());
+        assert!(!db.consume_effect(p1, Effect::LuckyCharm).await.unwrap());
+        assert!(db.get_inventory(p1).await.unwrap().is_empty());
+
+        let double = db
+            .create_item(
+                "Double Daily",
+                "",
+                5,
+                None,
+                None,
+                true,
+                None,
+                Some(Effect::DoubleDaily),
+            )
+            .await
+            .unwrap();
+        db.buy_item(p2, double, 1, Reason::ItemPurchase.with_id("test"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(db.award_daily(p2, 10, Reason::Daily.into()).await.unwrap());
+        assert!(!db.award_daily(p2, 10, Reason::Daily.into()).await.unwrap());
+        assert_eq!(db.get_balance(p2).await.unwrap(), 50 - 5 + 20 + 10);
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_role_rentals() {
+        let (db, [player]) = setup().await;
+        let (vip, crown) = (10, 11);
+        db.set_role_price(vip, 100, None, None, Some(false))
+            .await
+            .unwrap();
+        db.set_role_price(crown, 100, None, None, Some(true))
+            .await
+            .unwrap();
+        db.set_role_rent(vip, 50, 7).await.unwrap();
+        db.set_role_rent(crown, 50, 7).await.unwrap();
+
+        let roles = db.get_purchasable_roles().await.unwrap();
+        let rent = |id| roles.iter().find(|r| r.role_id == id).unwrap().rent();
+        assert_eq!(rent(vip), Some((50, 7)));
+        // unique roles have a single holder, they can't be rented
+        assert_eq!(rent(crown), None);
+
+        let memo = || Reason::RoleRental.with_id(vip);
+        let expires_at = chrono::Utc::now() + chrono::Duration::days(7);
+        let renewed = expires_at + chrono::Duration::days(7);
+        assert!(db
+            .set_rental(vip, player, 1, expires_at, 50, memo())
+            .await
+            .unwrap());
+        db.set_rental_reminded(vip, player).await.unwrap();
+        assert!(db.get_rental(vip, player).await.unwrap().unwrap().reminded);
+
+        // the rent is spent, renewing needs more
+        assert!(!db
+            .set_rental(vip, player, 1, renewed, 50, memo())
+            .await
+            .unwrap());
+        assert_eq!(
+            db.get_rental(vip, player)
+                .await
+                .unwrap()
+                .unwrap()
+                .expires_at,
+            expires_at
+        );
+        db.award_balances(vec![player], 50, Reason::Daily.into())
+            .await
+            .unwrap();
+
+        // renewing sends the reminder again before the new expiry
+        assert!(db
+            .set_rental(vip, player, 1, renewed, 50, memo())
+            .await
+            .unwrap());
+        let rentals = db.get_user_rentals(player).await.unwrap();
+        assert_eq!(rentals.len(), 1);
+        assert!(!rentals[0].reminded);
+        assert_eq!(
+            rentals[0].expires_at,
+            expires_at + chrono::Duration::days(7)
+        );
+
+        // a renewal that couldn't be handed out goes back to the old expiry
+        db.undo_rental(vip, player, Some(expires_at), 50, memo())
+            .await
+            .unwrap();
+        assert_eq!(db.get_balance(player).await.unwrap(), 50);
+        assert_eq!(
+            db.get_rental(vip, player)
+                .await
+                .unwrap()
+                .unwrap()
+                .expires_at,
+            expires_at
+        );
+        db.undo_rental(vip, player, None, 0, memo()).await.unwrap();
+        assert!(db.get_rental(vip, player).await.unwrap().is_none());
+
+        db.end_rental(vip, player).await.unwrap();
+        assert!(db.get_rental(vip, player).await.unwrap().is_none());
+        db.set_role_rent(vip, 0, 7).await.unwrap();
+        let roles = db.get_purchasable_roles().await.unwrap();
+        assert_eq!(
+            roles.iter().find(|r| r.role_id == vip).unwrap().rent(),
+            None
+        );
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_filters_and_links() {
+        let (db, [p1]) = setup().await;
+        db.award_balances(vec![p1], 10, Reason::Daily.into())
+            .await
+            .unwrap();
+        db.add_bones(p1, 3, Reason::BonesPurchase.with_id("game"))
+            .await
+            .unwrap();
+        db.link_transactions("game", "https://discord.com/game")
+            .await
+            .unwrap();
+
+        let all = db.get_transactions(p1, None, 10, 0).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].reason, "bones_purchase");
+        assert_eq!(all[0].link.as_deref(), Some("https://discord.com/game"));
+        assert_eq!(all[1].balance, 60);
+        assert_eq!(all[1].link, None);
+
+        let bucks = db
+            .get_transactions(p1, Some(Currency::Bucks), 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(bucks.len(), 1);
+        assert_eq!(bucks[0].amount, 10);
+
+        let paged = db.get_transactions(p1, None, 1, 1).await.unwrap();
+        assert_eq!(paged[0].reason, "daily");
+    }
+
+    #[tokio::test]
+    async fn test_transfer_is_atomic() {
+        let (db, [p1, p2, p3]) = setup().await;
+
+        db.transfer(
+            p1,
+            &[(p2, 30), (p3, 15)],
+            Currency::Bucks,
+            Reason::Give.into(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(db.get_balance(p1).await.unwrap(), 5);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 80);
+        assert_eq!(db.get_balance(p3).await.unwrap(), 65);
+
+        // overdraw leaves everyone untouched
+        let err = db
+            .transfer(
+                p1,
+                &[(p2, 3), (p3, 3)],
+                Currency::Bucks,
+                Reason::Give.into(),
+            )
+            .await;
+        assert!(err.is_err());
+        // unknown recipient rolls back the debit
+        let err = db
+            .transfer(
+                p2,
+                &[(p3, 10), (new_user_id(), 10)],
+                Currency::Bucks,
+                Reason::Give.into(),
+            )
+            .await;
+        assert!(err.is_err());
+        assert_eq!(db.get_balance(p1).await.unwrap(), 5);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 80);
+        assert_eq!(db.get_balance(p3).await.unwrap(), 65);
+    }
+
+    #[tokio::test]
+    async fn test_crown_tax_is_booked_or_burned() {
+        let (db, [p1, p2, crown]) = setup().await;
+
+        db.transfer_taxed(
+            p1,
+            &[(p2, 9)],
+            1,
+            Some(crown),
+            Currency::Bucks,
+            Reason::Give.with_id("g1"),
+        )
+        .await
+        .unwrap();
+        // nobody wears the crown, the tax is burned
+        db.transfer_taxed(
+            p1,
+            &[(p2, 9)],
+            1,
+            None,
+            Currency::Bucks,
+            Reason::Give.with_id("g2"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(db.get_balance(p1).await.unwrap(), 30);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 68);
+        assert_eq!(db.get_balance(crown).await.unwrap(), 51);
+
+        db.escrow_stake(p1, 10, Reason::CoinGamble.with_id("c1"))
+            .await
+            .unwrap();
+        db.settle_escrows(
+            &["c1"],
+            &[(p2, 9)],
+            Some((crown, 1)),
+            Reason::CoinGamble.with_id("c1"),
+        )
+        .await
+        .unwrap();
+        let ledger = db.get_transactions(crown, None, 10, 0).await.unwrap();
+        assert_eq!(ledger.len(), 2);
+        assert!(ledger
+            .iter()
+            .all(|t| t.reason == "crown_tax" && t.amount == 1));
+        assert!(db.get_unsettled_games().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_escrow_refund_and_settle() {
+        let (db, [p1, p2]) = setup().await;
+
+        db.escrow_stake(p1, 20, Reason::Gamble.with_id("g1"))
+            .await
+            .unwrap();
+        db.escrow_stake(p2, 20, Reason::Gamble.with_id("g1"))
+            .await
+            .unwrap();
+        db.escrow_stake(p1, 10, Reason::RpsGamble.with_id("g2"))
+            .await
+            .unwrap();
+        assert!(db
+            .escrow_stake(p2, 100, Reason::Gamble.with_id("g1"))
+            .await
+            .is_err());
+        assert_eq!(db.get_balance(p1).await.unwrap(), 20);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 30);
+        assert_eq!(
+            db.get_unsettled_games().await.unwrap(),
+            vec![
+                (String::from("g1"), String::from("gamble")),
+                (String::from("g2"), String::from("rpsgamble"))
+            ]
+        );
+
+        db.settle_escrow("g1", &[(p2, 40)], Reason::Gamble.with_id("g1"))
+            .await
+            .unwrap();
+        assert_eq!(db.refund_escrow("g2").await.unwrap(), vec![(p1, 10)]);
+        assert!(db.get_unsettled_games().await.unwrap().is_empty());
+        assert_eq!(db.get_balance(p1).await.unwrap(), 30);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 70);
+    }
+
+    #[tokio::test]
+    async fn test_lottery_subscriptions() {
+        let (db, [player]) = setup().await;
+        let until = chrono::Utc::now() + chrono::Duration::days(7);
+
+        db.subscribe_lottery(player, "daily", 1, until)
+            .await
+            .unwrap();
+        db.subscribe_lottery(player, "mega", 2, until)
+            .await
+            .unwrap();
+        // subscribing again replaces the old subscription
+        db.subscribe_lottery(player, "daily", 3, until)
+            .await
+            .unwrap();
+        let daily = db.get_open_lotteries().await.unwrap().remove(0);
+        let memo = || Reason::LotteryTicket.with_id(daily.id);
+        assert_eq!(
+            db.charge_subscription(player, "daily", daily.id, 3, memo())
+                .await
+                .unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            db.get_balance(player).await.unwrap(),
+            50 - 3 * daily.ticket_price
+        );
+        // a round that can't be afforded is still marked, without charging anything
+        assert_eq!(
+            db.charge_subscription(player, "mega", daily.id, 1000, memo())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            db.get_balance(player).await.unwrap(),
+            50 - 3 * daily.ticket_price
+        );
+
+        let subs = db.get_user_subscriptions(player).await.unwrap();
+        assert_eq!(subs.len(), 2);
+        assert_eq!(
+            (subs[0].tickets, subs[0].last_lottery_id),
+            (3, Some(daily.id))
+        );
+        assert_eq!(
+            (subs[1].tickets, subs[1].last_lottery_id),
+            (2, Some(daily.id))
+        );
+
+        assert_eq!(
+            db.unsubscribe_lottery(player, Some("Mega")).await.unwrap(),
+            1
+        );
+        assert_eq!(db.unsubscribe_lottery(player, None).await.unwrap(), 1);
+        assert!(db.get_subscriptions().await.unwrap().is_empty());
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buying_lottery_tickets_charges_the_round() {
+        let (db, [player]) = setup().await;
+        let daily = db.get_open_lotteries().await.unwrap().remove(0);
+        let memo = || Reason::LotteryTicket.with_id(daily.id);
+
+        let affordable = 50 / daily.ticket_price;
+        assert_eq!(
+            db.buy_lottery_tickets(daily.id, player, affordable + 1, memo())
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(db.get_balance(player).await.unwrap(), 50);
+        assert!(db.get_bought_tickets(daily.id).await.unwrap().is_empty());
+
+        assert_eq!(
+            db.buy_lottery_tickets(daily.id, player, affordable, memo())
+                .await
+                .unwrap(),
+            Some(affordable)
+        );
+        assert_eq!(
+            db.get_balance(player).await.unwrap(),
+            50 - affordable * daily.ticket_price
+        );
+        db.link_transactions(&daily.id.to_string(), "https://discord.com/daily")
+            .await
+            .unwrap();
+        let ledger = db.get_transactions(player, None, 10, 0).await.unwrap();
+        assert_eq!(ledger.len(), 1);
+        assert_eq!(ledger[0].link.as_deref(), Some("https://discord.com/daily"));
+
+        db.close_lottery(&daily, &[], 0, 0, daily.draw_time, None, "rollover")
+            .await
+            .unwrap();
+        assert!(db
+            .buy_lottery_tickets(daily.id, player, 1, memo())
+            .await
+            .is_err());
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lotteries_keep_separate_pots() {
+        let (db, [player]) = setup().await;
+        let draw = chrono::Utc::now() + chrono::Duration::days(3);
+
+        let daily = db.get_open_lotteries().await.unwrap().remove(0);
+        assert_eq!(daily.name, "daily");
+        let mega = db
+            .create_lottery("mega", 50, 1000, "0 0 18 * * Sun", draw, "100", 0)
+            .await
+            .unwrap();
+
+        db.award_balances(vec![player], 500, Reason::Daily.into())
+            .await
+            .unwrap();
+        let buy = |lottery_id: i64, amount: i32| {
+            db.buy_lottery_tickets(
+                lottery_id,
+                player,
+                amount,
+                Reason::LotteryTicket.with_id(lottery_id),
+            )
+        };
+        buy(daily.id, 2).await.unwrap();
+        assert_eq!(buy(mega, 1).await.unwrap(), Some(1));
+        assert_eq!(buy(mega, 2).await.unwrap(), Some(3));
+        assert_eq!(db.get_user_tickets(player).await.unwrap(), 5);
+        db.add_to_lottery_prize(mega, 20).await.unwrap();
+
+        let lotteries = db.get_open_lotteries().await.unwrap();
+        let mega_round = lotteries.iter().find(|l| l.id == mega).unwrap();
+        assert_eq!(mega_round.prize(3), 3 * 49 + 1020);
+
+        // drawing the daily round leaves the mega tickets alone
+        let balance = db.get_balance(player).await.unwrap();
+        let outcome = player.to_string();
+        let next = db
+            .close_lottery(&daily, &[(player, 40)], 40, 5, draw, None, &outcome)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(db.get_balance(player).await.unwrap(), balance + 40);
+        // a second draw of the same round pays nothing
+        assert_eq!(
+            db.close_lottery(&daily, &[(player, 40)], 40, 5, draw, None, &outcome)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(db.get_balance(player).await.unwrap(), balance + 40);
+        assert_eq!(db.get_user_tickets(player).await.unwrap(), 3);
+        assert!(db.get_bought_tickets(next).await.unwrap().is_empty());
+        let lotteries = db.get_open_lotteries().await.unwrap();
+        assert_eq!(lotteries.len(), 2);
+        let new_daily = lotteries.iter().find(|l| l.id == next).unwrap();
+        assert_eq!(new_daily.name, "daily");
+        assert_eq!(new_daily.base_prize, daily.next_base_prize + 5);
+        assert_eq!(new_daily.next_base_prize, daily.next_base_prize);
+
+        let draws = db.get_recent_draws(10).await.unwrap();
+        assert_eq!(draws.len(), 1);
+        assert_eq!(draws[0].winner, player as i64);
+        assert_eq!(
+            (draws[0].pot, draws[0].prize, draws[0].winners),
+            (40, 40, 1)
+        );
+        assert_eq!((draws[0].winner_tickets, draws[0].tickets_sold), (2, 2));
+        assert_eq!(draws[0].players, 1);
+
+        let stats = db.get_lottery_stats(player).await.unwrap();
+        assert_eq!(stats.draws_entered, 1);
+        assert_eq!(stats.tickets, 5);
+        assert_eq!(stats.spent, 2 * daily.ticket_price + 3 * 50);
+        assert_eq!((stats.wins, stats.won), (1, 40));
+        db.close().await.unwrap();
+    }
+}
@@ -1,4 +1,6 @@
-use crate::database::{self, BalanceDatabase, ConfigDatabase};
+use crate::commands::auction::{bid_on, BID_BUTTON};
+use crate::commands::role::{rent_role, RENEW_BUTTON};
+use crate::database::{self, BalanceDatabase, ConfigDatabase, Effect, EffectDatabase, Reason};
 use crate::discord::{EGG_ROLE, NICKNAME_LICENCE};
 use crate::{Data, Error};
 use ::serenity::all::{
@@ -92,6 +94,47 @@ pub async fn event_handler(
         return Ok(());
     };
 
+    if let poise::serenity_prelude::FullEvent::InteractionCreate {
+        interaction: serenity::Interaction::Component(click),
+    } = event
+    {
+        if let Some(ids) = click.data.custom_id.strip_prefix(RENEW_BUTTON) {
+            let (guild, role) = ids.split_once('-').ok_or("Bad renew button")?;
+            let (guild, role) = (
+                serenity::GuildId::new(guild.parse()?),
+                serenity::RoleId::new(role.parse()?),
+            );
+            let content = match rent_role(&ctx.http, &data.db, guild, click.user.id, role).await? {
+                Ok(expires_at) => format!("Renewed until <t:{}:f>!", expires_at.timestamp()),
+                Err(e) => e,
+            };
+            click
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::Message(
+                        serenity::CreateInteractionResponseMessage::default().content(content),
+                    ),
+                )
+                .await?;
+        } else if let Some(id) = click.data.custom_id.strip_prefix(BID_BUTTON) {
+            let content =
+                match bid_on(&ctx.http, &data.db, id.parse()?, click.user.id, None).await? {
+                    Ok(content) | Err(content) => content,
+                };
+            click
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::Message(
+                        serenity::CreateInteractionResponseMessage::default()
+                            .content(content)
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+        }
+        return Ok(());
+    }
+
     if let poise::serenity_prelude::FullEvent::Message { new_message } = event {
         if new_message.author.bot {
             if new_message.author.id != data.bot_id {
@@ -153,7 +196,15 @@ pub async fn event_handler(
 
                 let mut roles = member.roles.clone();
 
-                if !roles.contains(&RoleId::new(EGG_ROLE)) {
+                let insured = !roles.contains(&RoleId::new(EGG_ROLE))
+                    && data
+                        .db
+                        .consume_effect(user.id.get(), Effect::EggInsurance)
+                        .await?;
+
+                if insured {
+                    tracing::info!("{nick} was insured against the egg");
+                } else if !roles.contains(&RoleId::new(EGG_ROLE)) {
                     roles.push(RoleId::new(EGG_ROLE));
 
                     data.config.write().unwrap().just_egged = Some(user.id.get());
@@ -189,7 +240,14 @@ pub async fn event_handler(
                     .create_response(ctx, {
                         serenity::CreateInteractionResponse::UpdateMessage(
                             serenity::CreateInteractionResponseMessage::default()
-                                .content(":egg:")
+                                .content(if insured {
+                                    format!(":egg: {}'s egg insurance paid out", user)
+                                } else {
+                                    ":egg:".to_string()
+                                })
+                                .allowed_mentions(
+                                    serenity::CreateAllowedMentions::new().empty_users(),
+                                )
                                 .components(vec![]),
                         )
                     })
@@ -238,7 +296,14 @@ pub async fn event_handler(
             }
 
             data.db
-                .subtract_balances(vec![new_message.author.id.get()], price)
+                .subtract_balances(
+                    vec![new_message.author.id.get()],
+                    price,
+                    Reason::PaidChannel.with_id(new_message.id),
+                )
+                .await?;
+            data.db
+                .link_transactions(&new_message.id.to_string(), &new_message.link())
                 .await?;
 
             match new_message
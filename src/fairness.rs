@@ -0,0 +1,64 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::{database::SeedDatabase, Error};
+
+/// How every roll is derived, shown by `/verify` so players can redo it themselves
+pub const DERIVATION: &str = "roll n = first 8 bytes of sha256(\"<seed>:<game id>:<n>\") as a big endian integer, divided by 2^64";
+
+/// A fresh secret server seed, 32 random bytes as hex
+pub fn new_seed() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// What gets published before the draw, sha256 of the seed as hex
+pub fn commitment(seed: &str) -> String {
+    hex::encode(Sha256::digest(seed.as_bytes()))
+}
+
+/// The n-th roll of a game, a number in [0, 1)
+pub fn roll(seed: &str, game_id: &str, n: u32) -> f64 {
+    let digest = Sha256::digest(format!("{}:{}:{}", seed, game_id, n).as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes) as f64 / 2f64.powi(64)
+}
+
+/// Stores a new seed for the game and returns it together with its commitment
+pub async fn commit_new_seed(
+    db: &impl SeedDatabase,
+    game_id: &str,
+    game: &str,
+) -> Result<(String, String), Error> {
+    let seed = new_seed();
+    let commitment = commitment(&seed);
+    db.commit_seed(game_id, game, &seed, &commitment).await?;
+    Ok((seed, commitment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commitment_is_sha256_of_seed() {
+        assert_eq!(
+            commitment("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_rolls_are_reproducible() {
+        let seed = new_seed();
+        assert_eq!(seed.len(), 64);
+        assert_eq!(roll(&seed, "1234", 0), roll(&seed, "1234", 0));
+        assert_ne!(roll(&seed, "1234", 0), roll(&seed, "1234", 1));
+        assert_ne!(roll(&seed, "1234", 0), roll(&seed, "1235", 0));
+        for n in 0..100 {
+            assert!((0.0..1.0).contains(&roll(&seed, "1234", n)));
+        }
+    }
+}
@@ -2,7 +2,11 @@ use rand::{seq::SliceRandom, Rng};
 
 use crate::{
     commands::coingamble::HeadsOrTail,
-    database::{self, BalanceDatabase, ConfigDatabase, RoleDatabase},
+    database::{
+        self, BalanceDatabase, Effect, EffectDatabase, EscrowDatabase, LotteryDatabase, Reason,
+        RoleDatabase, SeedDatabase,
+    },
+    fairness,
 };
 
 #[derive(Debug)]
@@ -54,8 +58,14 @@ impl CoinSides {
     }
 }
 
+/// How much each lucky charm tips the coin towards its owner's side
+pub const LUCKY_CHARM_ODDS: f32 = 0.05;
+
 #[derive(Debug)]
 pub struct CoinGame {
+    pub id: String,
+    /// Committed before the game starts, revealed with the result
+    pub seed: String,
     pub players: Vec<u64>,
     pub heads: Vec<u64>,
     pub tails: Vec<u64>,
@@ -72,10 +82,14 @@ pub struct CoinGameResult {
     pub johnnys_multiplier: Option<f32>,
     pub leader: Option<u64>,
     pub remainder: Option<i32>,
+    /// Players whose lucky charm was used up on this flip
+    pub lucky: Vec<u64>,
 }
 
 impl CoinGame {
     pub fn new(
+        id: String,
+        seed: String,
         game_starter: u64,
         choice: HeadsOrTail,
         amount: i32,
@@ -91,6 +105,8 @@ impl CoinGame {
             HeadsOrTail::Tails => tails.push(game_starter),
         }
         Self {
+            id,
+            seed,
             players,
             heads,
             tails,
@@ -103,7 +119,7 @@ impl CoinGame {
 
     pub async fn player_joined(
         &mut self,
-        db: &impl database::BalanceDatabase,
+        db: &(impl database::BalanceDatabase + EscrowDatabase),
         player: u64,
         choice: &String,
     ) -> Result<(), GameError> {
@@ -115,9 +131,9 @@ impl CoinGame {
         if player_balance < self.amount {
             return Err(GameError::PlayerCantAfford);
         }
-        db.subtract_balances(vec![player], self.amount)
+        db.escrow_stake(player, self.amount, Reason::CoinGamble.with_id(&self.id))
             .await
-            .unwrap();
+            .map_err(|_| GameError::PlayerCantAfford)?;
         self.players.push(player);
         if choice == "Heads" {
             self.heads.push(player);
@@ -128,13 +144,49 @@ impl CoinGame {
         Ok(())
     }
 
-    pub async fn get_winner<T: BalanceDatabase + RoleDatabase + ConfigDatabase>(
+    /// Flip from the game seed: roll 0 lands the coin on its side below the side chance,
+    /// otherwise roll 1 is heads below the heads odds
+    pub fn flip(seed: &str, game_id: &str, side_chance: u32, heads_odds: f32) -> CoinSides {
+        if fairness::roll(seed, game_id, 0) < side_chance as f64 / 100.0 {
+            CoinSides::Side
+        } else if fairness::roll(seed, game_id, 1) < heads_odds as f64 {
+            CoinSides::Heads
+        } else {
+            CoinSides::Tails
+        }
+    }
+
+    pub async fn get_winner<
+        T: BalanceDatabase
+            + RoleDatabase
+            + LotteryDatabase
+            + EscrowDatabase
+            + SeedDatabase
+            + EffectDatabase,
+    >(
         &mut self,
         db: &T,
         bot_id: u64,
         crown_role_id: u64,
     ) -> CoinGameResult {
-        let (heads_odds, tails_odds) = (self.odds_bot_wins, 1.0 - self.odds_bot_wins);
+        let mut heads_odds = self.odds_bot_wins;
+        let mut lucky = vec![];
+        for (players, tip) in [
+            (&self.heads, LUCKY_CHARM_ODDS),
+            (&self.tails, -LUCKY_CHARM_ODDS),
+        ] {
+            for player in players {
+                match db.consume_effect(*player, Effect::LuckyCharm).await {
+                    Ok(true) => {
+                        heads_odds += tip;
+                        lucky.push(*player);
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::debug!(e),
+                }
+            }
+        }
+        let heads_odds = heads_odds.clamp(0.0, 1.0);
         if self.heads.is_empty() {
             self.heads.push(bot_id);
             self.players.push(bot_id);
@@ -144,41 +196,41 @@ impl CoinGame {
             self.players.push(bot_id);
             self.pot += self.pot;
         }
-        let result = {
-            let mut rng = rand::thread_rng();
-            if rng.gen_bool(self.side_chance as f64 / 100.0) {
-                CoinSides::Side
-            } else {
-                [
-                    (CoinSides::Heads, heads_odds),
-                    (CoinSides::Tails, tails_odds),
-                ]
-                .choose_weighted(&mut rng, |item| item.1)
-                .unwrap()
-                .to_owned()
-                .0
-            }
-        };
+        let result = Self::flip(&self.seed, &self.id, self.side_chance, heads_odds);
+        let params = format!("side_chance={};heads_odds={}", self.side_chance, heads_odds);
+        if let Err(e) = db
+            .reveal_seed(&self.id, Some(&params), &result.to_uppercase())
+            .await
+        {
+            tracing::debug!(e);
+        }
 
         match result {
             CoinSides::Side => {
-                let current_pot = {
-                    db.get_config()
-                        .await
-                        .unwrap()
-                        .lottery_base_prize
-                        .unwrap_or(10)
+                // the next lottery to be drawn gets the pot
+                match db.get_open_lotteries().await {
+                    Ok(lotteries) => {
+                        if let Some(lottery) = lotteries.first() {
+                            if let Err(e) = db.add_to_lottery_prize(lottery.id, self.pot).await {
+                                tracing::error!(
+                                    "Failed to add coin game {} to the lottery: {}",
+                                    self.id,
+                                    e
+                                );
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to find a lottery for coin game {}: {}", self.id, e)
+                    }
                 };
-
-                let new_pot = current_pot + self.pot;
-
-                match db
-                    .set_config_value(database::ConfigKey::LotteryBasePrize, &new_pot.to_string())
+                // the stakes went into the lottery pot
+                if let Err(e) = db
+                    .settle_escrow(&self.id, &[], Reason::CoinGamble.with_id(&self.id))
                     .await
                 {
-                    Ok(_) => {}
-                    Err(e) => tracing::debug!(e),
-                };
+                    tracing::error!("Failed to settle coin game {}: {}", self.id, e);
+                }
 
                 CoinGameResult {
                     result,
@@ -187,6 +239,7 @@ impl CoinGame {
                     leader: None,
                     johnnys_multiplier: None,
                     remainder: None,
+                    lucky,
                 }
             }
             _ => {
@@ -205,24 +258,25 @@ impl CoinGame {
                 let prize = self.pot / winners.len() as i32;
                 let remainder = self.pot % winners.len() as i32;
                 let prize_with_multiplier = prize + (prize as f32 * johnnys_multiplier) as i32;
-                let leader = if let Ok(Some(user)) = db.get_unique_role_holder(crown_role_id).await
-                {
-                    match db.award_balances(vec![user.user_id], remainder).await {
-                        Ok(_) => {}
-                        Err(e) => tracing::debug!(e),
-                    };
-                    Some(user.user_id)
-                } else {
-                    None
+                let leader = match db.get_unique_role_holder(crown_role_id).await {
+                    Ok(Some(user)) => Some(user.user_id),
+                    _ => None,
                 };
+                let mut payouts = vec![];
                 if winners[0] != bot_id {
-                    match db
-                        .award_balances(winners.to_vec(), prize_with_multiplier)
-                        .await
-                    {
-                        Ok(_) => {}
-                        Err(e) => tracing::debug!(e),
-                    }
+                    payouts.extend(winners.iter().map(|w| (*w, prize_with_multiplier)));
+                }
+                // crown remainder and winnings land together with closing the escrow
+                if let Err(e) = db
+                    .settle_escrows(
+                        &[&self.id],
+                        &payouts,
+                        leader.map(|leader| (leader, remainder)),
+                        Reason::CoinGamble.with_id(&self.id),
+                    )
+                    .await
+                {
+                    tracing::error!("Failed to settle coin game {}: {}", self.id, e);
                 }
                 CoinGameResult {
                     result,
@@ -231,6 +285,7 @@ impl CoinGame {
                     leader,
                     johnnys_multiplier: Some(johnnys_multiplier),
                     remainder: Some(remainder),
+                    lucky,
                 }
             }
         }
@@ -244,10 +299,13 @@ impl CoinGame {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::database::tests::{new_user_id, setup};
 
     #[tokio::test]
     async fn test_coin_game_get_winner() {
         let mut game = CoinGame {
+            id: String::from("test"),
+            seed: fairness::new_seed(),
             players: vec![8222483375454858662, 5607624227456207587],
             heads: vec![8222483375454858662],
             tails: vec![5607624227456207587],
@@ -267,6 +325,7 @@ mod tests {
         let num_games = 100;
 
         for _i in 0..num_games {
+            game.seed = fairness::new_seed();
             match game.get_winner(&db, bot_id, crown_role_id).await.result {
                 CoinSides::Heads => heads += 1,
                 CoinSides::Tails => tails += 1,
@@ -291,6 +350,8 @@ mod tests {
         let p1 = new_user_id();
         let p2 = new_user_id();
         let mut game = CoinGame {
+            id: String::from("test"),
+            seed: fairness::new_seed(),
             players: vec![p1, p2],
             heads: vec![p1],
             tails: vec![p2],
@@ -308,6 +369,7 @@ mod tests {
         let num_games = 100;
 
         for _i in 0..num_games {
+            game.seed = fairness::new_seed();
             match game.get_winner(&db, bot_id, crown_role_id).await.result {
                 CoinSides::Heads => {}
                 CoinSides::Tails => {}
@@ -322,6 +384,8 @@ mod tests {
     async fn test_coin_game_get_winner_adds_bot() {
         let p1 = new_user_id();
         let mut game = CoinGame {
+            id: String::from("test"),
+            seed: fairness::new_seed(),
             players: vec![p1],
             heads: vec![p1],
             tails: vec![],
@@ -344,6 +408,8 @@ mod tests {
     async fn test_coin_game_get_winners_award() {
         let (p1, p2) = (new_user_id(), new_user_id());
         let mut game = CoinGame {
+            id: String::from("test"),
+            seed: fairness::new_seed(),
             players: vec![p1, p2],
             heads: vec![p1],
             tails: vec![p2],
@@ -378,6 +444,8 @@ mod tests {
     async fn test_coin_game_get_winners_side_award() {
         let (p1, p2) = (new_user_id(), new_user_id());
         let mut game = CoinGame {
+            id: String::from("test"),
+            seed: fairness::new_seed(),
             players: vec![p1, p2],
             heads: vec![p1],
             tails: vec![p2],
@@ -405,10 +473,6 @@ mod tests {
         db.close().await.unwrap();
     }
 
-    fn new_user_id() -> u64 {
-        rand::thread_rng().gen_range::<u64, _>(0..1000000000000000000)
-    }
-
     #[tokio::test]
     async fn test_coin_game_get_winners_remainder() {
         let p1 = new_user_id();
@@ -416,6 +480,8 @@ mod tests {
         let p3 = new_user_id();
         let p4 = new_user_id();
         let mut game = CoinGame {
+            id: String::from("test"),
+            seed: fairness::new_seed(),
             players: vec![p1, p2, p3, p4],
             heads: vec![p1, p2],
             tails: vec![p3, p4],
@@ -435,7 +501,9 @@ mod tests {
         }
         let p5 = new_user_id();
         db.get_balance(p5).await.unwrap();
-        db.set_unique_role_holder(crown_role_id, p5).await.unwrap();
+        db.set_unique_role_holder(crown_role_id, p5, 10, database::TenureEnd::Bought)
+            .await
+            .unwrap();
 
         let _ = game.get_winner(&db, bot_id, crown_role_id).await;
         let crown_balance = db.get_balance(p5).await.unwrap();
@@ -443,6 +511,468 @@ mod tests {
         db.close().await.unwrap();
     }
 
+    mod blackjack {
+        use super::super::{hand_total, Blackjack, BlackjackError, Card, Shoe, Suit};
+        use rand::SeedableRng;
+
+        fn cards(ranks: &[u8]) -> Vec<Card> {
+            ranks.iter().map(|r| Card::new(*r, Suit::Spades)).collect()
+        }
+
+        // the shoe deals a card to each player in turn then the dealer, twice
+        fn table(players: &[u64], ranks: &[u8]) -> Blackjack {
+            let mut game = Blackjack::new(Shoe::stacked(cards(ranks)));
+            for player in players {
+                game.join(*player, 10).unwrap();
+            }
+            game.deal().unwrap();
+            game
+        }
+
+        #[test]
+        fn test_shoe_is_seeded() {
+            let mut a = Shoe::new(2, &mut rand::rngs::StdRng::seed_from_u64(7));
+            let mut b = Shoe::new(2, &mut rand::rngs::StdRng::seed_from_u64(7));
+            let mut dealt = 0;
+            while let Some(card) = a.draw() {
+                assert_eq!(Some(card), b.draw());
+                dealt += 1;
+            }
+            assert_eq!(dealt, 104);
+        }
+
+        #[test]
+        fn test_hand_totals() {
+            assert_eq!(hand_total(&cards(&[14, 6])), (17, true));
+            assert_eq!(hand_total(&cards(&[14, 6, 10])), (17, false));
+            assert_eq!(hand_total(&cards(&[14, 14, 9])), (21, true));
+            assert_eq!(hand_total(&cards(&[13, 12, 2])), (22, false));
+        }
+
+        #[test]
+        fn test_blackjack_pays_3_to_2() {
+            let mut game = table(&[1], &[14, 9, 13, 7]);
+            assert!(game.is_finished());
+            game.play_dealer().unwrap();
+            // nothing left to play for, the dealer keeps 16
+            assert_eq!(game.dealer.len(), 2);
+            assert_eq!(game.payouts(), vec![(1, 25)]);
+
+            // an odd bet's half rounds in the player's favour
+            let mut game = Blackjack::new(Shoe::stacked(cards(&[14, 9, 13, 7])));
+            game.join(1, 5).unwrap();
+            game.deal().unwrap();
+            game.play_dealer().unwrap();
+            assert_eq!(game.payouts(), vec![(1, 13)]);
+        }
+
+        #[test]
+        fn test_dealer_draws_to_17() {
+            let mut game = table(&[1], &[10, 5, 8, 6, 2, 4, 10]);
+            game.stand(1).unwrap();
+            game.play_dealer().unwrap();
+            assert_eq!(game.dealer_total(), 17);
+            assert_eq!(game.payouts(), vec![(1, 20)]);
+
+            // stands on a soft 17
+            let mut game = table(&[1], &[10, 6, 7, 14]);
+            game.stand(1).unwrap();
+            game.play_dealer().unwrap();
+            assert_eq!(game.dealer.len(), 2);
+            assert_eq!(game.payouts(), vec![(1, 10)]);
+        }
+
+        #[test]
+        fn test_bust_and_dealer_bust() {
+            let mut game = table(&[1, 2], &[10, 10, 10, 6, 9, 5, 8, 9]);
+            // player 1 hits 16 into a bust, player 2 stands on 19
+            game.hit(1).unwrap();
+            assert_eq!(game.hit(1), Err(BlackjackError::HandsDone));
+            game.stand(2).unwrap();
+            assert!(game.is_finished());
+            game.play_dealer().unwrap();
+            // a bust loses even when the dealer busts too
+            assert_eq!(game.dealer_total(), 24);
+            assert_eq!(game.payouts(), vec![(1, 0), (2, 20)]);
+        }
+
+        #[test]
+        fn test_double_down() {
+            let mut game = table(&[1], &[5, 10, 6, 7, 10]);
+            assert_eq!(game.double_cost(1), Ok(10));
+            assert_eq!(game.double(1).unwrap(), Card::new(10, Suit::Spades));
+            assert!(game.is_finished());
+            assert_eq!(game.seats[0].staked(), 20);
+            game.play_dealer().unwrap();
+            assert_eq!(game.payouts(), vec![(1, 40)]);
+
+            let mut game = table(&[1], &[2, 10, 3, 7, 4]);
+            game.hit(1).unwrap();
+            assert_eq!(game.double_cost(1), Err(BlackjackError::CantDouble));
+        }
+
+        #[test]
+        fn test_split() {
+            let mut game = table(&[1], &[8, 10, 8, 8, 3, 10, 10]);
+            assert_eq!(game.split_cost(1), Ok(10));
+            game.split(1).unwrap();
+            assert_eq!(game.seats[0].hands.len(), 2);
+            // 8 3 then a 10 makes 21 and moves on to the second hand, 8 10
+            game.hit(1).unwrap();
+            assert_eq!(game.seats[0].hands[0].total(), 21);
+            assert!(!game.seats[0].hands[0].is_blackjack());
+            game.stand(1).unwrap();
+            assert!(game.is_finished());
+            game.play_dealer().unwrap();
+            assert_eq!(game.seats[0].staked(), 20);
+            assert_eq!(game.payouts(), vec![(1, 30)]);
+
+            let game = table(&[1], &[8, 10, 9, 8]);
+            assert_eq!(game.split_cost(1), Err(BlackjackError::CantSplit));
+        }
+
+        #[test]
+        fn test_split_aces_get_one_card() {
+            let mut game = table(&[1], &[14, 10, 14, 8, 13, 5]);
+            game.split(1).unwrap();
+            assert!(game.is_finished());
+            game.play_dealer().unwrap();
+            // 21 on a split hand pays 1:1, 16 loses to 18
+            assert_eq!(game.payouts(), vec![(1, 20)]);
+        }
+
+        #[test]
+        fn test_dealer_blackjack_ends_the_round() {
+            let mut game = table(&[1, 2], &[10, 14, 14, 9, 13, 13]);
+            assert!(game.is_finished());
+            assert_eq!(game.hit(1), Err(BlackjackError::HandsDone));
+            game.play_dealer().unwrap();
+            // a player blackjack pushes, anything else loses
+            assert_eq!(game.payouts(), vec![(1, 0), (2, 10)]);
+        }
+
+        #[test]
+        fn test_seating() {
+            let mut game = Blackjack::new(Shoe::stacked(cards(&[2, 3, 4, 5])));
+            assert_eq!(game.hit(1), Err(BlackjackError::NotDealt));
+            assert_eq!(game.deal(), Err(BlackjackError::NoPlayers));
+            game.join(1, 10).unwrap();
+            assert_eq!(game.join(1, 10), Err(BlackjackError::AlreadySeated));
+            game.deal().unwrap();
+            assert_eq!(game.join(2, 10), Err(BlackjackError::AlreadyDealt));
+            assert_eq!(game.stand(2), Err(BlackjackError::NotSeated));
+            // out of cards
+            assert_eq!(game.hit(1), Err(BlackjackError::ShoeEmpty));
+        }
+    }
+
+    mod poker {
+        use super::super::{
+            rank_hand, Card, HandCategory, Poker, PokerAction, PokerError, Shoe, Suit,
+        };
+
+        // "Ah Td 9c" style
+        fn cards(hand: &str) -> Vec<Card> {
+            hand.split_whitespace()
+                .map(|card| {
+                    let (rank, suit) = card.split_at(card.len() - 1);
+                    let rank = match rank {
+                        "T" => 10,
+                        "J" => 11,
+                        "Q" => 12,
+                        "K" => 13,
+                        "A" => 14,
+                        rank => rank.parse().unwrap(),
+                    };
+                    let suit = match suit {
+                        "c" => Suit::Clubs,
+                        "d" => Suit::Diamonds,
+                        "h" => Suit::Hearts,
+                        _ => Suit::Spades,
+                    };
+                    Card::new(rank, suit)
+                })
+                .collect()
+        }
+
+        // hole cards go round the table twice starting left of the dealer, then the board
+        fn table(players: &[(u64, i32)], deck: &str) -> Poker {
+            let mut game = Poker::new(1000);
+            for (player, chips) in players {
+                game.sit(*player, *chips).unwrap();
+            }
+            game.start_hand(Shoe::stacked(cards(deck))).unwrap();
+            game
+        }
+
+        #[test]
+        fn test_hand_categories() {
+            let category = |hand: &str| rank_hand(&cards(hand)).category;
+            assert_eq!(category("2c 7d 9h Js Kc"), HandCategory::HighCard);
+            assert_eq!(category("2c 2d 9h Js Kc"), HandCategory::Pair);
+            assert_eq!(category("2c 2d 9h 9s Kc"), HandCategory::TwoPair);
+            assert_eq!(category("2c 2d 2h Js Kc"), HandCategory::ThreeOfAKind);
+            assert_eq!(category("Ac 2d 3h 4s 5c"), HandCategory::Straight);
+            assert_eq!(category("2c 7c 9c Jc Kc"), HandCategory::Flush);
+            assert_eq!(category("2c 2d 2h Ks Kc"), HandCategory::FullHouse);
+            assert_eq!(category("2c 2d 2h 2s Kc"), HandCategory::FourOfAKind);
+            assert_eq!(category("9h Th Jh Qh Kh"), HandCategory::StraightFlush);
+            // not enough cards for a straight or flush yet
+            assert_eq!(category("Ah Kh"), HandCategory::HighCard);
+        }
+
+        #[test]
+        fn test_hand_ranking() {
+            let rank = |hand: &str| rank_hand(&cards(hand));
+            // the wheel is the lowest straight
+            assert!(rank("Ac 2d 3h 4s 5c") < rank("2c 3d 4h 5s 6c"));
+            assert!(rank("Tc Jd Qh Ks Ac") > rank("9c Td Jh Qs Kc"));
+            // kickers
+            assert!(rank("Ac Ad Kh 4s 3c") > rank("Ac Ad Qh Js Tc"));
+            assert!(rank("9c 9d 4h 4s Ac") > rank("9c 9d 4h 4s Kc"));
+            assert!(rank("3c 3d 3h 2s 2c") < rank("4c 4d 4h 2s 2c"));
+            assert_eq!(rank("Ac Kd Qh Js 9c"), rank("As Kh Qd Jc 9s"));
+            // best five out of seven
+            assert_eq!(rank("2h 7h Ah 9c 9d Kh Qh").category, HandCategory::Flush);
+            assert_eq!(rank("9c 9d 9h Ks Kc 2d 2h").tiebreak, vec![9, 13]);
+        }
+
+        #[test]
+        fn test_heads_up_blinds() {
+            let mut game = table(&[(1, 100), (2, 100)], "2c 3c 4c 5c 6d 7d 8d 9d Td");
+            assert_eq!((game.small_blind, game.big_blind), (10, 20));
+            // the button posts the small blind and acts first before the flop
+            assert_eq!(game.button(), Some(1));
+            assert_eq!(game.to_act(), Some(1));
+            assert_eq!(game.to_call(1), 10);
+            assert_eq!(
+                game.act(2, PokerAction::Check),
+                Err(PokerError::NotYourTurn)
+            );
+            assert_eq!(game.act(1, PokerAction::Check), Err(PokerError::CantCheck));
+            assert_eq!(game.act_on_timeout(), Ok((1, PokerAction::Fold)));
+            assert!(game.is_hand_over());
+            let result = game.settle().unwrap();
+            // no flop, no rake
+            assert_eq!(result.winnings, vec![(2, 30)]);
+            assert_eq!((result.rake, result.shown.len()), (0, 0));
+            assert_eq!(game.seats[0].chips, 90);
+            assert_eq!(game.seats[1].chips, 110);
+        }
+
+        #[test]
+        fn test_showdown() {
+            let mut game = table(
+                &[(1, 100), (2, 100), (3, 100)],
+                "Ac Kc 2d Ad Kd 7h 3s 8h 9c Js 5d",
+            );
+            // left of the big blind starts
+            assert_eq!(game.to_act(), Some(1));
+            game.act(1, PokerAction::Call).unwrap();
+            game.act(2, PokerAction::Call).unwrap();
+            assert_eq!(
+                game.act(3, PokerAction::Call),
+                Err(PokerError::NothingToCall)
+            );
+            game.act(3, PokerAction::Check).unwrap();
+            assert_eq!(game.board.len(), 3);
+            // left of the button starts after the flop
+            for _ in 0..3 {
+                for player in [2, 3, 1] {
+                    assert_eq!(game.to_act(), Some(player));
+                    game.act(player, PokerAction::Check).unwrap();
+                }
+            }
+            assert!(game.is_hand_over());
+            let result = game.settle().unwrap();
+            assert_eq!(result.winnings, vec![(1, 57)]);
+            assert_eq!(result.rake, 3);
+            assert_eq!(result.shown.len(), 3);
+            assert_eq!(result.shown[0].1.category, HandCategory::Pair);
+        }
+
+        #[test]
+        fn test_side_pots() {
+            let mut game = table(
+                &[(1, 50), (2, 200), (3, 200)],
+                "Ac Kc 2d Ad Kd 7h 3s 8h 9c Js 5d",
+            );
+            game.act(1, PokerAction::AllIn).unwrap();
+            game.act(2, PokerAction::Call).unwrap();
+            game.act(3, PokerAction::Call).unwrap();
+            game.act(2, PokerAction::Raise).unwrap();
+            assert_eq!(game.to_call(3), 20);
+            game.act(3, PokerAction::AllIn).unwrap();
+            game.act(2, PokerAction::Call).unwrap();
+            // nobody has chips left to bet so the board runs out
+            assert_eq!(game.board.len(), 5);
+            let result = game.settle().unwrap();
+            // the short stack can only win the main pot
+            assert_eq!(result.winnings, vec![(1, 143), (2, 285)]);
+            assert_eq!(result.rake, 22);
+            assert_eq!(game.ready_players(), 2);
+        }
+
+        #[test]
+        fn test_split_pot() {
+            let mut game = table(
+                &[(1, 100), (2, 100), (3, 100)],
+                "2c 4c 2d 3d 5d 3c Ts Jh Qs Kh Ac",
+            );
+            game.act(1, PokerAction::Call).unwrap();
+            game.act(2, PokerAction::Call).unwrap();
+            game.act(3, PokerAction::Check).unwrap();
+            game.act(2, PokerAction::Check).unwrap();
+            game.act(3, PokerAction::Raise).unwrap();
+            game.act(1, PokerAction::Call).unwrap();
+            game.act(2, PokerAction::Fold).unwrap();
+            for _ in 0..2 {
+                game.act(3, PokerAction::Check).unwrap();
+                game.act(1, PokerAction::Check).unwrap();
+            }
+            let result = game.settle().unwrap();
+            // both play the board, the odd chip goes to the first after the button
+            assert_eq!(result.rake, 5);
+            assert_eq!(result.winnings, vec![(1, 47), (3, 48)]);
+        }
+
+        #[test]
+        fn test_uncalled_bet_is_not_raked() {
+            let mut game = table(
+                &[(1, 1000), (2, 1000), (3, 1000)],
+                "Ac Kc 2d Ad Kd 7h 3s 8h 9c Js 5d",
+            );
+            game.act(1, PokerAction::Call).unwrap();
+            game.act(2, PokerAction::Call).unwrap();
+            game.act(3, PokerAction::Check).unwrap();
+            game.act(2, PokerAction::AllIn).unwrap();
+            game.act(3, PokerAction::Fold).unwrap();
+            game.act(1, PokerAction::Fold).unwrap();
+            assert!(game.is_hand_over());
+            let result = game.settle().unwrap();
+            // only the 60 everyone called is raked, the all-in comes back whole
+            assert_eq!(result.rake, 3);
+            assert_eq!(result.winnings, vec![(2, 57 + 980)]);
+            assert_eq!(game.seats[1].chips, 1037);
+        }
+
+        #[test]
+        fn test_seating() {
+            let mut game = Poker::new(100);
+            assert_eq!((game.small_blind, game.big_blind), (1, 2));
+            game.sit(1, 100).unwrap();
+            assert_eq!(game.sit(1, 100), Err(PokerError::AlreadySeated));
+            assert_eq!(
+                game.start_hand(Shoe::stacked(vec![])),
+                Err(PokerError::NotEnoughPlayers)
+            );
+            for player in 2..=8 {
+                game.sit(player, 100).unwrap();
+            }
+            assert_eq!(game.sit(9, 100), Err(PokerError::TableFull));
+            assert_eq!(
+                game.start_hand(Shoe::stacked(vec![])),
+                Err(PokerError::ShoeEmpty)
+            );
+            assert_eq!(game.leave(9), Err(PokerError::NotSeated));
+            assert_eq!(game.leave(8), Ok(100));
+            let shoe = Shoe::new(1, &mut rand::thread_rng());
+            game.start_hand(shoe).unwrap();
+            assert_eq!(game.leave(1), Err(PokerError::HandInProgress));
+            assert_eq!(game.pot(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blackjack_settles_escrow() {
+        use crate::game::{Blackjack, Card, Shoe, Suit};
+        let (db, [p1, p2]) = setup().await;
+        let shoe = Shoe::stacked(
+            [8, 10, 10, 8, 10, 7, 3, 10]
+                .iter()
+                .map(|r| Card::new(*r, Suit::Hearts))
+                .collect(),
+        );
+        let mut game = Blackjack::new(shoe);
+        for player in [p1, p2] {
+            game.join(player, 10).unwrap();
+            db.escrow_stake(player, 10, Reason::Blackjack.with_id("bj"))
+                .await
+                .unwrap();
+        }
+        game.deal().unwrap();
+        // p1 splits the eights and puts up another bet
+        let cost = game.split_cost(p1).unwrap();
+        db.escrow_stake(p1, cost, Reason::Blackjack.with_id("bj"))
+            .await
+            .unwrap();
+        game.split(p1).unwrap();
+        game.stand_all();
+        game.play_dealer().unwrap();
+        db.settle_escrow("bj", &game.payouts(), Reason::Blackjack.with_id("bj"))
+            .await
+            .unwrap();
+        // against 17 the 8 3 loses and the 8 10 wins, 10 10 wins
+        assert_eq!(db.get_balance(p1).await.unwrap(), 50);
+        assert_eq!(db.get_balance(p2).await.unwrap(), 60);
+        assert!(db.get_unsettled_games().await.unwrap().is_empty());
+        db.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lucky_charm_tips_the_coin() {
+        use crate::database::ItemDatabase;
+        let (db, [p1, p2]) = setup().await;
+        let charm = db
+            .create_item(
+                "Lucky Charm",
+                "",
+                5,
+                None,
+                None,
+                true,
+                None,
+                Some(Effect::LuckyCharm),
+            )
+            .await
+            .unwrap();
+        db.buy_item(p1, charm, 1, Reason::ItemPurchase.with_id("test"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the charm is used up by the flip and tips the coin to the owner's side
+        let (seed, _) = fairness::commit_new_seed(&db, "charmed", "coingamble")
+            .await
+            .unwrap();
+        let mut game = CoinGame {
+            id: String::from("charmed"),
+            seed,
+            players: vec![p1, p2],
+            heads: vec![p2],
+            tails: vec![p1],
+            amount: 10,
+            pot: 20,
+            side_chance: 0,
+            odds_bot_wins: 0.5,
+        };
+        let result = game.get_winner(&db, new_user_id(), 1).await;
+        assert_eq!(result.lucky, vec![p1]);
+        assert_eq!(
+            db.get_seed("charmed")
+                .await
+                .unwrap()
+                .unwrap()
+                .params
+                .as_deref(),
+            Some("side_chance=0;heads_odds=0.45")
+        );
+        assert!(!db.has_effect(p1, Effect::LuckyCharm).await.unwrap());
+        assert!(!db.consume_effect(p1, Effect::LuckyCharm).await.unwrap());
+        assert!(db.get_inventory(p1).await.unwrap().is_empty());
+        db.close().await.unwrap();
+    }
+
     mod lottery {
         use super::super::Lottery;
 
@@ -453,7 +983,7 @@ mod tests {
             let mut winners = vec![];
 
             for _i in 0..100 {
-                let winner = lottery.get_winner();
+                let winner = lottery.get_winners(&crate::fairness::new_seed(), "lottery-1", 1)[0];
                 winners.push(winner);
             }
 
@@ -472,7 +1002,7 @@ mod tests {
             let mut winners = vec![];
 
             for _i in 0..100 {
-                let winner = lottery.get_winner();
+                let winner = lottery.get_winners(&crate::fairness::new_seed(), "lottery-1", 1)[0];
                 winners.push(winner);
             }
 
@@ -480,50 +1010,1198 @@ mod tests {
 
             assert_eq!(num_3, 100)
         }
+
+        #[test]
+        fn test_lottery_winner_ignores_ticket_order() {
+            let seed = crate::fairness::new_seed();
+            let a = Lottery::new(vec![(1, 3), (2, 1), (3, 10)]);
+            let b = Lottery::new(vec![(3, 10), (1, 3), (2, 1)]);
+            assert_eq!(
+                a.get_winners(&seed, "lottery-7", 3),
+                b.get_winners(&seed, "lottery-7", 3)
+            );
+            assert!(Lottery::new(vec![])
+                .get_winners(&seed, "lottery-7", 1)
+                .is_empty());
+        }
+
+        #[test]
+        fn test_lottery_tiers_draw_without_replacement() {
+            let lottery = Lottery::new(vec![(1, 1), (2, 0), (3, 10), (4, 2)]);
+            for _i in 0..100 {
+                let mut winners = lottery.get_winners(&crate::fairness::new_seed(), "l", 5);
+                assert_eq!(winners.len(), 3);
+                winners.sort();
+                assert_eq!(winners, vec![1, 3, 4]);
+            }
+        }
+
+        #[test]
+        fn test_split_pot() {
+            use super::super::{parse_tiers, split_pot};
+            let tiers = parse_tiers("70, 20, 10").unwrap();
+            assert_eq!(
+                split_pot(101, &tiers, &[1, 2, 3]),
+                (vec![(1, 71), (2, 20), (3, 10)], 0)
+            );
+            // nobody to take third place, its share rolls over
+            assert_eq!(
+                split_pot(100, &tiers, &[1, 2]),
+                (vec![(1, 70), (2, 20)], 10)
+            );
+            assert_eq!(split_pot(100, &tiers, &[]), (vec![], 100));
+            assert_eq!(split_pot(100, &[50], &[1]), (vec![(1, 50)], 50));
+            assert!(parse_tiers("60,50").is_err());
+            assert!(parse_tiers("first").is_err());
+        }
+    }
+
+    #[test]
+    fn test_settle_predictions() {
+        // 5% of the 100 lost is kept, the 95 left split 1:3
+        assert_eq!(
+            settle_predictions(&[(1, 10), (2, 30)], &[(3, 60), (4, 40)], 5),
+            (vec![(1, 33), (2, 101)], 6)
+        );
+        // nobody to win from, stakes come back
+        assert_eq!(settle_predictions(&[(1, 10)], &[], 5), (vec![(1, 10)], 0));
+        // nobody won, the losers get their stakes back
+        assert_eq!(settle_predictions(&[], &[(3, 60)], 5), (vec![(3, 60)], 0));
+    }
+
+    #[tokio::test]
+    async fn test_coin_game_records_transactions() {
+        let p1 = new_user_id();
+        let p2 = new_user_id();
+        let mut game = CoinGame {
+            id: String::from("ledger"),
+            seed: fairness::new_seed(),
+            players: vec![p1, p2],
+            heads: vec![p1],
+            tails: vec![p2],
+            amount: 100,
+            pot: 200,
+            side_chance: 0,
+            odds_bot_wins: 1.0,
+        };
+
+        let db = database::Database::new().await.unwrap();
+        for p in &game.players {
+            db.get_balance(*p).await.unwrap();
+        }
+        // johnny's bonus is random, the ledger has to show whatever was paid
+        let prize = game
+            .get_winner(&db, new_user_id(), 1)
+            .await
+            .prize_with_multiplier;
+
+        let rows: Vec<(i64, i32, String, String, Option<String>, i32)> = sqlx::query_as(
+            "SELECT user_id, amount, currency, reason, correlation_id, balance FROM transactions",
+        )
+        .fetch_all(&db.connection)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![(
+                p1 as i64,
+                prize,
+                String::from("bucks"),
+                String::from("coingamble"),
+                Some(String::from("ledger")),
+                50 + prize
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coin_game_reveals_committed_seed() {
+        use crate::database::SeedDatabase;
+        let (p1, p2) = (new_user_id(), new_user_id());
+        let db = database::Database::new().await.unwrap();
+        let (seed, commitment) = fairness::commit_new_seed(&db, "fair", "coingamble")
+            .await
+            .unwrap();
+        let mut game = CoinGame {
+            id: String::from("fair"),
+            seed: seed.clone(),
+            players: vec![p1, p2],
+            heads: vec![p1],
+            tails: vec![p2],
+            amount: 100,
+            pot: 200,
+            side_chance: 2,
+            odds_bot_wins: 0.5,
+        };
+
+        let stored = db.get_seed("fair").await.unwrap().unwrap();
+        assert_eq!(stored.commitment, commitment);
+        assert!(stored.outcome.is_none());
+
+        let result = game.get_winner(&db, new_user_id(), 1).await;
+        assert_eq!(result.result, CoinGame::flip(&seed, "fair", 2, 0.5));
+
+        let stored = db.get_seed("fair").await.unwrap().unwrap();
+        assert_eq!(stored.outcome, Some(result.result.to_uppercase()));
+        assert_eq!(
+            stored.params.as_deref(),
+            Some("side_chance=2;heads_odds=0.5")
+        );
+        assert_eq!(fairness::commitment(&stored.seed), stored.commitment);
+        db.close().await.unwrap();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
+}
+
+impl Suit {
+    pub const ALL: [Suit; 4] = [Suit::Clubs, Suit::Diamonds, Suit::Hearts, Suit::Spades];
+}
+
+/// A playing card. Ranks go from 2 to 14, jack, queen, king and ace are 11 to 14.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Card {
+    pub rank: u8,
+    pub suit: Suit,
+}
+
+impl Card {
+    pub fn new(rank: u8, suit: Suit) -> Self {
+        Self { rank, suit }
+    }
+
+    /// What the card counts in blackjack, aces count 11 until that would bust the hand
+    pub fn blackjack_value(&self) -> u32 {
+        match self.rank {
+            14 => 11,
+            11..=13 => 10,
+            rank => rank as u32,
+        }
+    }
+}
+
+impl std::fmt::Display for Card {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rank = match self.rank {
+            11 => "J".to_string(),
+            12 => "Q".to_string(),
+            13 => "K".to_string(),
+            14 => "A".to_string(),
+            rank => rank.to_string(),
+        };
+        let suit = match self.suit {
+            Suit::Clubs => "♣",
+            Suit::Diamonds => "♦",
+            Suit::Hearts => "♥",
+            Suit::Spades => "♠",
+        };
+        write!(f, "{}{}", rank, suit)
+    }
+}
+
+/// Cards dealt from the top, shuffled once when it's made
+#[derive(Debug, Clone)]
+pub struct Shoe {
+    // the top of the shoe is the end
+    cards: Vec<Card>,
+}
+
+impl Shoe {
+    pub fn new(decks: usize, rng: &mut impl Rng) -> Self {
+        let mut cards = (0..decks)
+            .flat_map(|_| Suit::ALL)
+            .flat_map(|suit| (2..=14).map(move |rank| Card::new(rank, suit)))
+            .collect::<Vec<_>>();
+        cards.shuffle(rng);
+        Self { cards }
+    }
+
+    /// A shoe that deals exactly these cards in this order
+    #[cfg(test)]
+    pub fn stacked(cards: Vec<Card>) -> Self {
+        Self {
+            cards: cards.into_iter().rev().collect(),
+        }
+    }
+
+    pub fn draw(&mut self) -> Option<Card> {
+        self.cards.pop()
+    }
+}
+
+/// Best blackjack total of the cards and whether an ace in it still counts 11
+pub fn hand_total(cards: &[Card]) -> (u32, bool) {
+    let mut total = cards.iter().map(|c| c.blackjack_value()).sum::<u32>();
+    let mut soft_aces = cards.iter().filter(|c| c.rank == 14).count();
+    while total > 21 && soft_aces > 0 {
+        total -= 10;
+        soft_aces -= 1;
+    }
+    (total, soft_aces > 0)
+}
+
+fn is_natural(cards: &[Card]) -> bool {
+    cards.len() == 2 && hand_total(cards).0 == 21
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hand {
+    pub cards: Vec<Card>,
+    pub bet: i32,
+    pub doubled: bool,
+    /// Split hands that make 21 with two cards don't count as blackjack
+    pub from_split: bool,
+    pub done: bool,
+}
+
+impl Hand {
+    fn new(bet: i32) -> Self {
+        Self {
+            cards: vec![],
+            bet,
+            doubled: false,
+            from_split: false,
+            done: false,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        hand_total(&self.cards).0
+    }
+
+    pub fn is_blackjack(&self) -> bool {
+        !self.from_split && is_natural(&self.cards)
+    }
+
+    pub fn is_bust(&self) -> bool {
+        self.total() > 21
+    }
+
+    // nothing to decide on 21 or more
+    fn add(&mut self, card: Card) {
+        self.cards.push(card);
+        if self.total() >= 21 {
+            self.done = true;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlackjackSeat {
+    pub player: u64,
+    pub hands: Vec<Hand>,
+}
+
+impl BlackjackSeat {
+    /// Everything the player has on the table, doubles and splits included
+    pub fn staked(&self) -> i32 {
+        self.hands.iter().map(|h| h.bet).sum()
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlackjackError {
+    NotSeated,
+    AlreadySeated,
+    TableFull,
+    AlreadyDealt,
+    NotDealt,
+    NoPlayers,
+    HandsDone,
+    CantDouble,
+    CantSplit,
+    ShoeEmpty,
+}
+
+impl std::fmt::Display for BlackjackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            BlackjackError::NotSeated => "You're not playing at this table",
+            BlackjackError::AlreadySeated => "You're already at the table",
+            BlackjackError::TableFull => "The table is full",
+            BlackjackError::AlreadyDealt => "The cards are already dealt",
+            BlackjackError::NotDealt => "The cards haven't been dealt yet",
+            BlackjackError::NoPlayers => "Nobody is at the table",
+            BlackjackError::HandsDone => "You're done for this round",
+            BlackjackError::CantDouble => "You can only double down on your first two cards",
+            BlackjackError::CantSplit => "You can only split a pair",
+            BlackjackError::ShoeEmpty => "The shoe ran out of cards",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for BlackjackError {}
+
+/// Seats at a blackjack table
+pub const BLACKJACK_SEATS: usize = 7;
+/// A player can split into at most this many hands
+pub const BLACKJACK_MAX_HANDS: usize = 4;
+
+/// A round of blackjack against the house. Players play their hands in any order, each
+/// action goes to their first hand that isn't done. The dealer peeks for blackjack after
+/// the deal and draws to 17 once everyone is done, standing on a soft 17.
 #[derive(Debug, Clone)]
 pub struct Blackjack {
-    pub players: Vec<u64>,
-    pub players_scores: Vec<i32>,
-    pub pot: i32,
+    pub seats: Vec<BlackjackSeat>,
+    pub dealer: Vec<Card>,
+    pub dealt: bool,
+    shoe: Shoe,
 }
 
 impl Blackjack {
-    pub fn new() -> Self {
+    pub fn new(shoe: Shoe) -> Self {
         Self {
-            players: vec![],
-            players_scores: vec![],
-            pot: 0,
+            seats: vec![],
+            dealer: vec![],
+            dealt: false,
+            shoe,
         }
     }
-    pub fn player_joined(&mut self, player: u64) {
-        self.players.push(player);
-        self.players_scores.push(0);
+
+    pub fn join(&mut self, player: u64, bet: i32) -> Result<(), BlackjackError> {
+        if self.dealt {
+            return Err(BlackjackError::AlreadyDealt);
+        }
+        if self.seats.iter().any(|s| s.player == player) {
+            return Err(BlackjackError::AlreadySeated);
+        }
+        if self.seats.len() >= BLACKJACK_SEATS {
+            return Err(BlackjackError::TableFull);
+        }
+        self.seats.push(BlackjackSeat {
+            player,
+            hands: vec![Hand::new(bet)],
+        });
+        Ok(())
     }
 
-    pub fn get_winners(&self) -> Vec<u64> {
-        let mut winners = vec![];
-        let mut max_score = 0;
-        for (i, score) in self.players_scores.iter().enumerate() {
-            if score > &max_score && score <= &21 {
-                max_score = *score;
-                winners = vec![self.players[i]];
-            } else if score == &max_score && score <= &21 {
-                winners.push(self.players[i]);
+    /// Two cards to everyone and the dealer. A dealer blackjack ends the round right away.
+    pub fn deal(&mut self) -> Result<(), BlackjackError> {
+        if self.dealt {
+            return Err(BlackjackError::AlreadyDealt);
+        }
+        if self.seats.is_empty() {
+            return Err(BlackjackError::NoPlayers);
+        }
+        for _ in 0..2 {
+            for i in 0..self.seats.len() {
+                let card = self.draw()?;
+                self.seats[i].hands[0].add(card);
             }
+            let card = self.draw()?;
+            self.dealer.push(card);
         }
-        winners
+        self.dealt = true;
+        if is_natural(&self.dealer) {
+            self.stand_all();
+        }
+        Ok(())
+    }
+
+    pub fn hit(&mut self, player: u64) -> Result<Card, BlackjackError> {
+        let card = self.next_card_for(player)?;
+        self.hand_mut(player)?.add(card);
+        Ok(card)
+    }
+
+    pub fn stand(&mut self, player: u64) -> Result<(), BlackjackError> {
+        self.hand_mut(player)?.done = true;
+        Ok(())
+    }
+
+    /// What doubling down costs the player, the bet of the hand they're on
+    pub fn double_cost(&self, player: u64) -> Result<i32, BlackjackError> {
+        let hand = self.hand(player)?;
+        if hand.cards.len() != 2 {
+            return Err(BlackjackError::CantDouble);
+        }
+        Ok(hand.bet)
+    }
+
+    /// Doubles the bet for exactly one more card
+    pub fn double(&mut self, player: u64) -> Result<Card, BlackjackError> {
+        self.double_cost(player)?;
+        let card = self.next_card_for(player)?;
+        let hand = self.hand_mut(player)?;
+        hand.bet *= 2;
+        hand.doubled = true;
+        hand.add(card);
+        hand.done = true;
+        Ok(card)
     }
 
-    pub fn get_leaderboard(&self) -> Vec<(u64, i32)> {
-        self.players
+    /// What splitting costs the player, another bet like the hand they're on
+    pub fn split_cost(&self, player: u64) -> Result<i32, BlackjackError> {
+        let seat = self.seat(player)?;
+        let hand = self.hand(player)?;
+        if hand.cards.len() != 2
+            || hand.cards[0].blackjack_value() != hand.cards[1].blackjack_value()
+            || seat.hands.len() >= BLACKJACK_MAX_HANDS
+        {
+            return Err(BlackjackError::CantSplit);
+        }
+        Ok(hand.bet)
+    }
+
+    /// Splits a pair into two hands with a new card each. Split aces get one card only.
+    pub fn split(&mut self, player: u64) -> Result<(), BlackjackError> {
+        self.split_cost(player)?;
+        let (first, second) = (self.draw()?, self.draw()?);
+        let seat = self.seat_mut(player)?;
+        let index = seat.hands.iter().position(|h| !h.done).unwrap();
+        let hand = &mut seat.hands[index];
+        let aces = hand.cards[0].rank == 14;
+        let mut other = Hand::new(hand.bet);
+        other.from_split = true;
+        other.cards.push(hand.cards.pop().unwrap());
+        hand.from_split = true;
+        hand.add(first);
+        other.add(second);
+        if aces {
+            hand.done = true;
+            other.done = true;
+        }
+        seat.hands.insert(index + 1, other);
+        Ok(())
+    }
+
+    /// Stands every hand still in play, for when time runs out
+    pub fn stand_all(&mut self) {
+        for hand in self.seats.iter_mut().flat_map(|s| s.hands.iter_mut()) {
+            hand.done = true;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.dealt
+            && self
+                .seats
+                .iter()
+                .flat_map(|s| s.hands.iter())
+                .all(|h| h.done)
+    }
+
+    /// Draws to 17, unless no hand is left that the dealer's cards could matter for
+    pub fn play_dealer(&mut self) -> Result<(), BlackjackError> {
+        let live = self
+            .seats
             .iter()
-            .zip(self.players_scores.iter())
-            .map(|(player, score)| (*player, *score))
+            .flat_map(|s| s.hands.iter())
+            .any(|h| !h.is_bust() && !h.is_blackjack());
+        if !live || is_natural(&self.dealer) {
+            return Ok(());
+        }
+        while hand_total(&self.dealer).0 < 17 {
+            let card = self.draw()?;
+            self.dealer.push(card);
+        }
+        Ok(())
+    }
+
+    pub fn dealer_total(&self) -> u32 {
+        hand_total(&self.dealer).0
+    }
+
+    /// What each hand returns against the dealer, stake included. Blackjack pays 3:2
+    /// rounded up, a win 1:1 and a push returns the bet.
+    pub fn hand_payout(&self, hand: &Hand) -> i32 {
+        let dealer = self.dealer_total();
+        let dealer_blackjack = is_natural(&self.dealer);
+        if hand.is_blackjack() {
+            return match dealer_blackjack {
+                true => hand.bet,
+                false => hand.bet + (hand.bet * 3 + 1) / 2,
+            };
+        }
+        if hand.is_bust() || dealer_blackjack {
+            return 0;
+        }
+        if dealer > 21 || hand.total() > dealer {
+            hand.bet * 2
+        } else if hand.total() == dealer {
+            hand.bet
+        } else {
+            0
+        }
+    }
+
+    /// What every player gets back once the dealer has played
+    pub fn payouts(&self) -> Vec<(u64, i32)> {
+        self.seats
+            .iter()
+            .map(|s| {
+                let payout = s.hands.iter().map(|h| self.hand_payout(h)).sum();
+                (s.player, payout)
+            })
             .collect()
     }
+
+    fn draw(&mut self) -> Result<Card, BlackjackError> {
+        self.shoe.draw().ok_or(BlackjackError::ShoeEmpty)
+    }
+
+    // checks the player can act before a card leaves the shoe
+    fn next_card_for(&mut self, player: u64) -> Result<Card, BlackjackError> {
+        self.hand(player)?;
+        self.draw()
+    }
+
+    fn seat(&self, player: u64) -> Result<&BlackjackSeat, BlackjackError> {
+        self.seats
+            .iter()
+            .find(|s| s.player == player)
+            .ok_or(BlackjackError::NotSeated)
+    }
+
+    fn seat_mut(&mut self, player: u64) -> Result<&mut BlackjackSeat, BlackjackError> {
+        self.seats
+            .iter_mut()
+            .find(|s| s.player == player)
+            .ok_or(BlackjackError::NotSeated)
+    }
+
+    fn hand(&self, player: u64) -> Result<&Hand, BlackjackError> {
+        if !self.dealt {
+            return Err(BlackjackError::NotDealt);
+        }
+        self.seat(player)?
+            .hands
+            .iter()
+            .find(|h| !h.done)
+            .ok_or(BlackjackError::HandsDone)
+    }
+
+    fn hand_mut(&mut self, player: u64) -> Result<&mut Hand, BlackjackError> {
+        if !self.dealt {
+            return Err(BlackjackError::NotDealt);
+        }
+        self.seat_mut(player)?
+            .hands
+            .iter_mut()
+            .find(|h| !h.done)
+            .ok_or(BlackjackError::HandsDone)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HandCategory {
+    HighCard,
+    Pair,
+    TwoPair,
+    ThreeOfAKind,
+    Straight,
+    Flush,
+    FullHouse,
+    FourOfAKind,
+    StraightFlush,
+}
+
+impl std::fmt::Display for HandCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HandCategory::HighCard => "high card",
+            HandCategory::Pair => "a pair",
+            HandCategory::TwoPair => "two pair",
+            HandCategory::ThreeOfAKind => "three of a kind",
+            HandCategory::Straight => "a straight",
+            HandCategory::Flush => "a flush",
+            HandCategory::FullHouse => "a full house",
+            HandCategory::FourOfAKind => "four of a kind",
+            HandCategory::StraightFlush => "a straight flush",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How strong a poker hand is, a higher rank beats a lower one and equal ranks split
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HandRank {
+    pub category: HandCategory,
+    /// Card ranks that break ties within the category, most important first
+    pub tiebreak: Vec<u8>,
+}
+
+// ranks up to five cards, fewer can't make a straight or flush
+fn rank_five(cards: &[Card]) -> HandRank {
+    let mut ranks = cards.iter().map(|c| c.rank).collect::<Vec<_>>();
+    ranks.sort_unstable_by(|a, b| b.cmp(a));
+    // (count, rank) of each rank, biggest groups first
+    let mut groups: Vec<(u8, u8)> = vec![];
+    for rank in ranks.iter() {
+        match groups.iter_mut().find(|(_, r)| r == rank) {
+            Some(group) => group.0 += 1,
+            None => groups.push((1, *rank)),
+        }
+    }
+    groups.sort_unstable_by(|a, b| b.cmp(a));
+    let tiebreak = groups.iter().map(|(_, rank)| *rank).collect::<Vec<_>>();
+
+    let flush = cards.len() == 5 && cards.iter().all(|c| c.suit == cards[0].suit);
+    let straight_high = match (groups.len(), ranks.as_slice()) {
+        (5, [14, 5, 4, 3, 2]) => Some(5),
+        (5, [high, .., low]) if high - low == 4 => Some(*high),
+        _ => None,
+    };
+    let category = match (
+        straight_high,
+        flush,
+        groups[0].0,
+        groups.get(1).map(|g| g.0),
+    ) {
+        (Some(_), true, _, _) => HandCategory::StraightFlush,
+        (_, _, 4, _) => HandCategory::FourOfAKind,
+        (_, _, 3, Some(2)) => HandCategory::FullHouse,
+        (_, true, _, _) => HandCategory::Flush,
+        (Some(_), _, _, _) => HandCategory::Straight,
+        (_, _, 3, _) => HandCategory::ThreeOfAKind,
+        (_, _, 2, Some(2)) => HandCategory::TwoPair,
+        (_, _, 2, _) => HandCategory::Pair,
+        _ => HandCategory::HighCard,
+    };
+    match straight_high {
+        Some(high) => HandRank {
+            category,
+            tiebreak: vec![high],
+        },
+        _ => HandRank { category, tiebreak },
+    }
+}
+
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    if n < k {
+        return vec![];
+    }
+    let mut with_last = combinations(n - 1, k - 1);
+    for combination in with_last.iter_mut() {
+        combination.push(n - 1);
+    }
+    let mut all = combinations(n - 1, k);
+    all.extend(with_last);
+    all
+}
+
+/// The best five card hand out of the hole cards and the board
+pub fn rank_hand(cards: &[Card]) -> HandRank {
+    combinations(cards.len(), cards.len().min(5))
+        .iter()
+        .map(|picked| rank_five(&picked.iter().map(|i| cards[*i]).collect::<Vec<_>>()))
+        .max()
+        .unwrap()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Street {
+    Preflop,
+    Flop,
+    Turn,
+    River,
+    Showdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PokerAction {
+    Fold,
+    Check,
+    Call,
+    /// Raises by the minimum, the big blind or the last raise
+    Raise,
+    AllIn,
+}
+
+impl std::fmt::Display for PokerAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let verb = match self {
+            PokerAction::Fold => "folds",
+            PokerAction::Check => "checks",
+            PokerAction::Call => "calls",
+            PokerAction::Raise => "raises",
+            PokerAction::AllIn => "goes all in",
+        };
+        write!(f, "{}", verb)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PokerSeat {
+    pub player: u64,
+    pub chips: i32,
+    pub hole: Vec<Card>,
+    /// Put in on this street
+    pub bet: i32,
+    /// Put in this hand
+    pub committed: i32,
+    pub folded: bool,
+    /// Players who sat down mid-hand wait for the next one
+    pub in_hand: bool,
+    acted: bool,
+}
+
+impl PokerSeat {
+    fn live(&self) -> bool {
+        self.in_hand && !self.folded
+    }
+
+    // still has decisions to make this hand
+    fn can_act(&self) -> bool {
+        self.live() && self.chips > 0
+    }
+
+    fn put_in(&mut self, amount: i32) {
+        let amount = amount.min(self.chips);
+        self.chips -= amount;
+        self.bet += amount;
+        self.committed += amount;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PokerError {
+    NotSeated,
+    AlreadySeated,
+    TableFull,
+    HandInProgress,
+    NoHand,
+    NotEnoughPlayers,
+    NotYourTurn,
+    CantCheck,
+    NothingToCall,
+    ShoeEmpty,
+}
+
+impl std::fmt::Display for PokerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            PokerError::NotSeated => "You're not sitting at this table",
+            PokerError::AlreadySeated => "You're already at the table",
+            PokerError::TableFull => "The table is full",
+            PokerError::HandInProgress => "Wait for the hand to finish",
+            PokerError::NoHand => "No hand is being played",
+            PokerError::NotEnoughPlayers => "A hand needs at least two players with chips",
+            PokerError::NotYourTurn => "It's not your turn",
+            PokerError::CantCheck => "You can't check, there's a bet to call",
+            PokerError::NothingToCall => "There's nothing to call, check instead",
+            PokerError::ShoeEmpty => "The deck ran out of cards",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for PokerError {}
+
+/// Seats at a poker table
+pub const POKER_SEATS: usize = 8;
+/// The house keeps this percentage of every pot that sees a flop
+pub const POKER_RAKE_PERCENT: i32 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PokerResult {
+    /// What each player won, uncalled bets included
+    pub winnings: Vec<(u64, i32)>,
+    /// The hands that were shown down, empty when everyone else folded
+    pub shown: Vec<(u64, HandRank)>,
+    pub rake: i32,
+}
+
+/// A Texas Hold'em cash table. Blinds are a fiftieth of the buy-in, the button moves one
+/// seat every hand and players act in turn until the betting on each street is even.
+#[derive(Debug, Clone)]
+pub struct Poker {
+    pub seats: Vec<PokerSeat>,
+    pub board: Vec<Card>,
+    pub small_blind: i32,
+    pub big_blind: i32,
+    /// None between hands
+    pub street: Option<Street>,
+    to_act: Option<usize>,
+    button: Option<usize>,
+    min_raise: i32,
+    shoe: Shoe,
+}
+
+impl Poker {
+    pub fn new(buy_in: i32) -> Self {
+        let big_blind = (buy_in / 50).max(2);
+        Self {
+            seats: vec![],
+            board: vec![],
+            small_blind: big_blind / 2,
+            big_blind,
+            street: None,
+            to_act: None,
+            button: None,
+            min_raise: big_blind,
+            shoe: Shoe { cards: vec![] },
+        }
+    }
+
+    pub fn sit(&mut self, player: u64, chips: i32) -> Result<(), PokerError> {
+        if self.seats.iter().any(|s| s.player == player) {
+            return Err(PokerError::AlreadySeated);
+        }
+        if self.seats.len() >= POKER_SEATS {
+            return Err(PokerError::TableFull);
+        }
+        self.seats.push(PokerSeat {
+            player,
+            chips,
+            hole: vec![],
+            bet: 0,
+            committed: 0,
+            folded: false,
+            in_hand: false,
+            acted: false,
+        });
+        Ok(())
+    }
+
+    /// Gets up from the table with the chips in front of the player, only between hands
+    pub fn leave(&mut self, player: u64) -> Result<i32, PokerError> {
+        let index = self.seat_index(player)?;
+        if self.street.is_some() {
+            return Err(PokerError::HandInProgress);
+        }
+        let seat = self.seats.remove(index);
+        // the button moves back a seat so the next hand still moves it to the right player
+        self.button = match self.button {
+            _ if self.seats.is_empty() => None,
+            Some(button) if button >= index => {
+                Some((button + self.seats.len() - 1) % self.seats.len())
+            }
+            button => button,
+        };
+        Ok(seat.chips)
+    }
+
+    /// Seats that have chips to play a hand with
+    pub fn ready_players(&self) -> usize {
+        self.seats.iter().filter(|s| s.chips > 0).count()
+    }
+
+    pub fn start_hand(&mut self, shoe: Shoe) -> Result<(), PokerError> {
+        if self.street.is_some() {
+            return Err(PokerError::HandInProgress);
+        }
+        if self.ready_players() < 2 {
+            return Err(PokerError::NotEnoughPlayers);
+        }
+        if shoe.cards.len() < self.ready_players() * 2 + 5 {
+            return Err(PokerError::ShoeEmpty);
+        }
+        self.shoe = shoe;
+        self.board.clear();
+        for seat in self.seats.iter_mut() {
+            seat.hole.clear();
+            seat.bet = 0;
+            seat.committed = 0;
+            seat.folded = false;
+            seat.in_hand = seat.chips > 0;
+            seat.acted = false;
+        }
+        let button = match self.button {
+            Some(button) => self.next_seat(button, |s| s.in_hand),
+            None => self.next_seat(self.seats.len() - 1, |s| s.in_hand),
+        };
+        self.button = Some(button);
+        // heads up the button is the small blind
+        let small = match self.ready_players() {
+            2 => button,
+            _ => self.next_seat(button, |s| s.in_hand),
+        };
+        let big = self.next_seat(small, |s| s.in_hand);
+        self.seats[small].put_in(self.small_blind);
+        self.seats[big].put_in(self.big_blind);
+        self.min_raise = self.big_blind;
+
+        for _ in 0..2 {
+            for i in 0..self.seats.len() {
+                if self.seats[i].in_hand {
+                    let card = self.draw()?;
+                    self.seats[i].hole.push(card);
+                }
+            }
+        }
+        self.street = Some(Street::Preflop);
+        self.to_act = Some(big);
+        self.advance()
+    }
+
+    /// Who has to act, None between hands and once the hand is decided
+    pub fn to_act(&self) -> Option<u64> {
+        self.to_act.map(|i| self.seats[i].player)
+    }
+
+    pub fn button(&self) -> Option<u64> {
+        self.button.map(|i| self.seats[i].player)
+    }
+
+    pub fn pot(&self) -> i32 {
+        self.seats.iter().map(|s| s.committed).sum()
+    }
+
+    fn current_bet(&self) -> i32 {
+        self.seats.iter().map(|s| s.bet).max().unwrap_or(0)
+    }
+
+    /// What the player has to put in to stay in the hand
+    pub fn to_call(&self, player: u64) -> i32 {
+        match self.seats.iter().find(|s| s.player == player) {
+            Some(seat) => (self.current_bet() - seat.bet).min(seat.chips),
+            None => 0,
+        }
+    }
+
+    pub fn is_hand_over(&self) -> bool {
+        self.street == Some(Street::Showdown)
+    }
+
+    pub fn act(&mut self, player: u64, action: PokerAction) -> Result<(), PokerError> {
+        let index = self.seat_index(player)?;
+        match self.street {
+            None | Some(Street::Showdown) => return Err(PokerError::NoHand),
+            _ => {}
+        }
+        if self.to_act != Some(index) {
+            return Err(PokerError::NotYourTurn);
+        }
+        let current_bet = self.current_bet();
+        let seat = &mut self.seats[index];
+        match action {
+            PokerAction::Fold => seat.folded = true,
+            PokerAction::Check if seat.bet < current_bet => return Err(PokerError::CantCheck),
+            PokerAction::Check => {}
+            PokerAction::Call if seat.bet >= current_bet => return Err(PokerError::NothingToCall),
+            PokerAction::Call => seat.put_in(current_bet - seat.bet),
+            PokerAction::Raise | PokerAction::AllIn => {
+                match action {
+                    PokerAction::Raise => seat.put_in(current_bet + self.min_raise - seat.bet),
+                    _ => seat.put_in(seat.chips),
+                }
+                let raised_by = seat.bet - current_bet;
+                if raised_by > 0 {
+                    self.min_raise = self.min_raise.max(raised_by);
+                    // everyone else gets to answer the raise
+                    for other in self.seats.iter_mut() {
+                        other.acted = false;
+                    }
+                }
+            }
+        }
+        self.seats[index].acted = true;
+        self.advance()
+    }
+
+    /// Checks for the player to act if they can, folds them otherwise
+    pub fn act_on_timeout(&mut self) -> Result<(u64, PokerAction), PokerError> {
+        let player = self.to_act().ok_or(PokerError::NoHand)?;
+        let action = match self.to_call(player) {
+            0 => PokerAction::Check,
+            _ => PokerAction::Fold,
+        };
+        self.act(player, action)?;
+        Ok((player, action))
+    }
+
+    // moves on to the next player, street or the showdown
+    fn advance(&mut self) -> Result<(), PokerError> {
+        if self.seats.iter().filter(|s| s.live()).count() == 1 {
+            return self.finish();
+        }
+        let current_bet = self.current_bet();
+        let waiting = |s: &PokerSeat| s.can_act() && (!s.acted || s.bet < current_bet);
+        // a lone player who has matched the bet has nobody left to bet against
+        let unopposed = self.seats.iter().filter(|s| s.can_act()).count() == 1
+            && self
+                .seats
+                .iter()
+                .all(|s| !s.can_act() || s.bet >= current_bet);
+        if self.seats.iter().any(waiting) && !unopposed {
+            let after = self.to_act.or(self.button).unwrap_or(0);
+            self.to_act = Some(self.next_seat(after, waiting));
+            return Ok(());
+        }
+
+        for seat in self.seats.iter_mut() {
+            seat.bet = 0;
+            seat.acted = false;
+        }
+        self.min_raise = self.big_blind;
+        let (street, cards) = match self.street {
+            Some(Street::Preflop) => (Street::Flop, 3),
+            Some(Street::Flop) => (Street::Turn, 1),
+            Some(Street::Turn) => (Street::River, 1),
+            _ => return self.finish(),
+        };
+        for _ in 0..cards {
+            let card = self.draw()?;
+            self.board.push(card);
+        }
+        self.street = Some(street);
+        if self.seats.iter().filter(|s| s.can_act()).count() < 2 {
+            // nobody left to bet against, run out the board
+            self.to_act = None;
+            return self.advance();
+        }
+        self.to_act = Some(self.next_seat(self.button.unwrap_or(0), |s| s.can_act()));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), PokerError> {
+        self.street = Some(Street::Showdown);
+        self.to_act = None;
+        Ok(())
+    }
+
+    /// Splits the pots once the hand is decided. Every all-in makes a side pot that only
+    /// the players who covered it can win, odd chips go to the first winners after the button.
+    pub fn settle(&mut self) -> Result<PokerResult, PokerError> {
+        if !self.is_hand_over() {
+            return Err(PokerError::HandInProgress);
+        }
+        let showdown = self.seats.iter().filter(|s| s.live()).count() > 1;
+        let shown = match showdown {
+            true => self
+                .seats
+                .iter()
+                .filter(|s| s.live())
+                .map(|s| {
+                    let cards = [s.hole.as_slice(), self.board.as_slice()].concat();
+                    (s.player, rank_hand(&cards))
+                })
+                .collect::<Vec<_>>(),
+            false => vec![],
+        };
+        let rake_percent = match self.board.len() >= 3 {
+            true => POKER_RAKE_PERCENT,
+            false => 0,
+        };
+
+        let mut committed = self.seats.iter().map(|s| s.committed).collect::<Vec<_>>();
+        let mut winnings = vec![0; self.seats.len()];
+        // whatever the biggest bet put in above everyone else was never called, it goes
+        // straight back before any pot is made or raked
+        let top = (0..committed.len())
+            .max_by_key(|i| committed[*i])
+            .unwrap_or(0);
+        let called = (0..committed.len())
+            .filter(|i| *i != top)
+            .map(|i| committed[i])
+            .max()
+            .unwrap_or(0);
+        if let Some(uncalled) = committed.get(top).map(|c| c - called).filter(|u| *u > 0) {
+            committed[top] -= uncalled;
+            winnings[top] += uncalled;
+        }
+
+        let mut levels = self
+            .seats
+            .iter()
+            .zip(committed.iter())
+            .filter(|(s, _)| s.live())
+            .map(|(_, c)| *c)
+            .collect::<Vec<_>>();
+        levels.sort_unstable();
+        levels.dedup();
+        // seats in the order odd chips go out, starting left of the button
+        let button = self.button.unwrap_or(0);
+        let order = (1..=self.seats.len())
+            .map(|i| (button + i) % self.seats.len())
+            .collect::<Vec<_>>();
+
+        let mut rake = 0;
+        let mut previous = 0;
+        for (n, level) in levels.iter().enumerate() {
+            let mut pot = committed
+                .iter()
+                .map(|c| c.min(level) - c.min(&previous))
+                .sum::<i32>();
+            // folded chips above the last level go to the last pot
+            if n == levels.len() - 1 {
+                pot += committed.iter().map(|c| (c - level).max(0)).sum::<i32>();
+            }
+            // only pots someone else put chips into are raked
+            let contested = committed.iter().filter(|c| **c > previous).count() > 1;
+            previous = *level;
+            let pot_rake = match contested {
+                true => pot * rake_percent / 100,
+                false => 0,
+            };
+            rake += pot_rake;
+            pot -= pot_rake;
+
+            let eligible = order
+                .iter()
+                .filter(|i| self.seats[**i].live() && committed[**i] >= *level)
+                .copied()
+                .collect::<Vec<_>>();
+            let best = eligible
+                .iter()
+                .filter_map(|i| shown.iter().find(|(p, _)| *p == self.seats[*i].player))
+                .map(|(_, rank)| rank)
+                .max();
+            let winners = eligible
+                .iter()
+                .filter(|i| match best {
+                    Some(best) => shown
+                        .iter()
+                        .any(|(p, rank)| *p == self.seats[**i].player && rank == best),
+                    None => true,
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            for (k, i) in winners.iter().enumerate() {
+                let odd_chip = (k < (pot % winners.len() as i32) as usize) as i32;
+                winnings[*i] += pot / winners.len() as i32 + odd_chip;
+            }
+        }
+
+        for (seat, won) in self.seats.iter_mut().zip(winnings.iter()) {
+            seat.chips += won;
+            seat.bet = 0;
+            seat.committed = 0;
+        }
+        self.street = None;
+        Ok(PokerResult {
+            winnings: self
+                .seats
+                .iter()
+                .zip(winnings)
+                .filter(|(_, won)| *won > 0)
+                .map(|(s, won)| (s.player, won))
+                .collect(),
+            shown,
+            rake,
+        })
+    }
+
+    fn draw(&mut self) -> Result<Card, PokerError> {
+        self.shoe.draw().ok_or(PokerError::ShoeEmpty)
+    }
+
+    fn seat_index(&self, player: u64) -> Result<usize, PokerError> {
+        self.seats
+            .iter()
+            .position(|s| s.player == player)
+            .ok_or(PokerError::NotSeated)
+    }
+
+    // the first seat after `after` going round the table that matches
+    fn next_seat(&self, after: usize, matches: impl Fn(&PokerSeat) -> bool) -> usize {
+        (1..=self.seats.len())
+            .map(|i| (after + i) % self.seats.len())
+            .find(|i| matches(&self.seats[*i]))
+            .unwrap_or(after)
+    }
 }
 
 pub struct Lottery {
@@ -535,14 +2213,99 @@ impl Lottery {
         Self { players }
     }
 
-    pub fn get_winner(&self) -> u64 {
-        if self.players.is_empty() {
-            return 0;
+    /// One winner per prize tier, drawn without replacement. Tickets are numbered in order
+    /// of user id and roll n of the seed picks the winning ticket of tier n + 1 among the
+    /// players that haven't won yet.
+    pub fn get_winners(&self, seed: &str, game_id: &str, tiers: usize) -> Vec<u64> {
+        let mut players = self
+            .players
+            .iter()
+            .filter(|(_, t)| *t > 0)
+            .copied()
+            .collect::<Vec<_>>();
+        players.sort_by_key(|(user, _)| *user);
+
+        let mut winners = vec![];
+        for n in 0..tiers {
+            let total = players.iter().map(|(_, t)| *t as i64).sum::<i64>();
+            if total == 0 {
+                break;
+            }
+            let mut ticket = (fairness::roll(seed, game_id, n as u32) * total as f64) as i64;
+            let index = players
+                .iter()
+                .position(|(_, tickets)| {
+                    let hit = ticket < *tickets as i64;
+                    ticket -= *tickets as i64;
+                    hit
+                })
+                .unwrap_or(players.len() - 1);
+            winners.push(players.remove(index).0);
         }
-        self.players
-            .choose_weighted(&mut rand::thread_rng(), |item| item.1)
-            .unwrap()
-            .to_owned()
-            .0
+        winners
+    }
+}
+
+/// Parses prize tiers like `70,20,10`, the percentage of the pot each place gets
+pub fn parse_tiers(tiers: &str) -> Result<Vec<i32>, String> {
+    let tiers = tiers
+        .split(',')
+        .map(|t| t.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Invalid prize tiers: {}", tiers))?;
+    if tiers.is_empty() || tiers.iter().any(|t| *t <= 0) || tiers.iter().sum::<i32>() > 100 {
+        return Err("Prize tiers must be positive percentages adding up to at most 100".into());
+    }
+    Ok(tiers)
+}
+
+/// Splits the pot between the winners by tier. Shares of tiers without a winner and any
+/// percentage the tiers don't hand out are returned to roll over into the next round,
+/// rounding leftovers go to first place.
+pub fn split_pot(pot: i32, tiers: &[i32], winners: &[u64]) -> (Vec<(u64, i32)>, i32) {
+    let shares = tiers.iter().map(|t| pot * t / 100).collect::<Vec<_>>();
+    let rollover = shares.iter().skip(winners.len()).sum::<i32>()
+        + pot * (100 - tiers.iter().sum::<i32>()) / 100;
+    let mut prizes = winners
+        .iter()
+        .zip(shares.iter())
+        .map(|(w, s)| (*w, *s))
+        .collect::<Vec<_>>();
+    if let Some(first) = prizes.first_mut() {
+        first.1 += pot - rollover - shares.iter().take(winners.len()).sum::<i32>();
+    }
+    let rollover = if prizes.is_empty() { pot } else { rollover };
+    (prizes, rollover)
+}
+
+/// The house keeps this percentage of what the losers of a bones prediction staked
+pub const PREDICTION_HOUSE_CUT_PERCENT: i32 = 5;
+
+/// Bones predictions close this many minutes before the next price is set, so late bets
+/// can't read the buying and selling that moves it
+pub const PREDICTION_CUTOFF_MINUTES: i64 = 30;
+
+/// Pays the winners of a bones prediction their stake back plus a share of the losers'
+/// stakes in proportion to what they put in, less the house cut. Rounding leftovers go to
+/// the house. When nobody won, the losers get their stakes back instead.
+pub fn settle_predictions(
+    winners: &[(u64, i32)],
+    losers: &[(u64, i32)],
+    cut_percent: i32,
+) -> (Vec<(u64, i32)>, i32) {
+    if winners.is_empty() {
+        return (losers.to_vec(), 0);
     }
+    let pool = losers.iter().map(|(_, stake)| *stake as i64).sum::<i64>();
+    let staked = winners.iter().map(|(_, stake)| *stake as i64).sum::<i64>();
+    let prize = pool * (100 - cut_percent) as i64 / 100;
+    let payouts = winners
+        .iter()
+        .map(|(user, stake)| (*user, stake + (prize * *stake as i64 / staked) as i32))
+        .collect::<Vec<_>>();
+    let paid = payouts
+        .iter()
+        .map(|(_, payout)| *payout as i64)
+        .sum::<i64>();
+    (payouts, (staked + pool - paid) as i32)
 }
@@ -1,21 +1,32 @@
-use chrono::{Datelike, NaiveTime, TimeDelta, Timelike};
+use chrono::{Datelike, TimeDelta};
+use cron::Schedule;
 use rand::seq::SliceRandom;
 use rand::Rng;
 use serenity::all::{
-    CreateMessage, EditChannel, PermissionOverwrite, PermissionOverwriteType, Permissions,
+    CreateAllowedMentions, CreateButton, CreateMessage, EditChannel, PermissionOverwrite,
+    PermissionOverwriteType, Permissions,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
 
 use poise::serenity_prelude::RoleId;
 
+use crate::commands::{auction, buy};
 use crate::database::ConfigKey;
 use crate::discord::JBUCK_EMOJI;
+use crate::scheduler::{self, Job, JobCommand};
 use crate::{
-    database::{self, BalanceDatabase, ConfigDatabase, LotteryDatabase},
-    game, Config, RoleDatabase,
+    database::{
+        self, AuctionDatabase, BalanceDatabase, BonesPriceDatabase, ConfigDatabase, EscrowDatabase,
+        JobDatabase, LotteryDatabase, Reason, SeedDatabase,
+    },
+    fairness, game, pricing, Config, Error, RoleDatabase,
 };
 
+/// Hours before a bones decay that holders get a DM about it
+const BONES_DECAY_WARNINGS: [i32; 2] = [1, 24];
+
 type RolePrice = (i32, Option<RoleId>);
 type RolePriceConfig = HashMap<RoleId, RolePrice>;
 
@@ -27,6 +38,7 @@ pub struct Johnny {
     channel: poise::serenity_prelude::ChannelId,
     message_client: Option<Arc<poise::serenity_prelude::Http>>,
     dev_env: bool,
+    crown_role_id: u64,
     egg_channels: Vec<poise::serenity_prelude::ChannelId>,
 }
 
@@ -38,6 +50,7 @@ impl Johnny {
         channel: poise::serenity_prelude::ChannelId,
         client: &serenity::Client,
         dev_env: bool,
+        crown_role_id: u64,
     ) -> Self {
         let channels = match dev_env {
             true => vec![poise::serenity_prelude::ChannelId::from(
@@ -55,14 +68,46 @@ impl Johnny {
             channel,
             message_client: Some(client.http.clone()),
             dev_env,
+            crown_role_id,
             egg_channels: channels,
         }
     }
-    pub async fn start(&self, signal: std::sync::mpsc::Receiver<()>) {
-        let mut minute_counter = tokio::time::Instant::now();
-        let mut five_minute_counter = tokio::time::Instant::now();
-        let mut last_lottery: Option<chrono::NaiveDateTime> = None;
+    pub async fn start(
+        &self,
+        signal: std::sync::mpsc::Receiver<()>,
+        mut commands: mpsc::UnboundedReceiver<JobCommand>,
+    ) {
         self.refresh_config().await;
+        let schedules = Job::ALL.map(|job| (job, job.schedule(self.dev_env)));
+        let stored = match self.db.get_jobs().await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                tracing::error!(e);
+                vec![]
+            }
+        };
+        let now = chrono::Utc::now();
+        let mut last_runs = HashMap::new();
+        let mut paused = HashSet::new();
+        for (job, schedule) in schedules.iter() {
+            match stored.iter().find(|s| s.name == job.name()) {
+                Some(status) => {
+                    last_runs.insert(*job, status.last_run);
+                    if status.paused {
+                        paused.insert(*job);
+                    }
+                }
+                None => {
+                    // a job we have never run before starts counting from now instead of firing at once
+                    last_runs.insert(*job, now);
+                    let next = scheduler::next_run(schedule, now);
+                    if let Err(e) = self.db.record_job_run(job.name(), now, next, None).await {
+                        tracing::error!(e);
+                    }
+                }
+            }
+        }
+
         loop {
             match signal.try_recv() {
                 Ok(_) => {
@@ -75,83 +120,352 @@ impl Johnny {
                     break;
                 }
             }
-            if self.should_trigger_lottery(last_lottery).await {
-                last_lottery = Some(chrono::Utc::now().naive_utc());
-                self.lottery().await;
-            }
-
-            if self.should_update_skewed_odds().await {
-                self.update_skewed_odds().await;
-            }
 
-            let force_egg = {
-                match self.config.read() {
-                    Ok(c) => c.force_egg,
-                    Err(e) => {
-                        tracing::error!("{e}");
-                        false
+            while let Ok(command) = commands.try_recv() {
+                match command {
+                    JobCommand::Run(job, reply) => {
+                        let schedule = &schedules.iter().find(|(j, _)| *j == job).unwrap().1;
+                        let result = self.execute(job, schedule, true).await;
+                        last_runs.insert(job, chrono::Utc::now());
+                        let _ = reply.send(result);
                     }
-                }
-            };
-            if self.should_run_egg(force_egg).await {
-                match self.config.write() {
-                    Ok(mut c) => {
-                        c.force_egg = false;
-                    }
-                    Err(e) => {
-                        tracing::error!("{e}");
+                    JobCommand::Pause(job) => {
+                        paused.insert(job);
+                        if let Err(e) = self.db.set_job_paused(job.name(), true).await {
+                            tracing::error!(e);
+                        }
                     }
-                }
-                match self.db.set_config_value(ConfigKey::ForceEgg, "false").await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        tracing::error!("{e}");
+                    JobCommand::Resume(job) => {
+                        paused.remove(&job);
+                        if let Err(e) = self.db.set_job_paused(job.name(), false).await {
+                            tracing::error!(e);
+                        }
                     }
                 }
+            }
 
-                tracing::info!("running egg");
-                self.run_egg().await;
+            let now = chrono::Utc::now();
+            for (job, schedule) in schedules.iter() {
+                if paused.contains(job) {
+                    continue;
+                }
+                if !scheduler::is_due(schedule, last_runs[job], now) && !self.is_forced(*job) {
+                    continue;
+                }
+                let _ = self.execute(*job, schedule, false).await;
+                last_runs.insert(*job, now);
             }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    }
 
-            let (deadline, c) = {
-                let config = self.config.read().unwrap();
-                (config.unghost_time, config.ghost_channel_id)
-            };
-            if let Some(chan_id) = c {
-                let c = poise::serenity_prelude::ChannelId::from(chan_id);
-                self.unghost_channel(deadline, c).await;
+    // runs a job and stores the outcome so /jobs can show it
+    async fn execute(&self, job: Job, schedule: &Schedule, forced: bool) -> Result<(), String> {
+        tracing::debug!("running job {}", job.name());
+        let now = chrono::Utc::now();
+        let result = self.run_job(job, forced).await.map_err(|e| e.to_string());
+        if let Err(e) = &result {
+            tracing::error!("job {} failed: {}", job.name(), e);
+        }
+        let next = scheduler::next_run(schedule, now);
+        if let Err(e) = self
+            .db
+            .record_job_run(
+                job.name(),
+                now,
+                next,
+                result.as_ref().err().map(|e| e.as_str()),
+            )
+            .await
+        {
+            tracing::error!(e);
+        }
+        result
+    }
+
+    // admins can ask for some jobs to run early through /config
+    fn is_forced(&self, job: Job) -> bool {
+        let config = match self.config.read() {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::error!("{e}");
+                return false;
             }
+        };
+        match job {
+            Job::BonesPrice => config.bones_price_force_update,
+            Job::Egg => config.force_egg,
+            _ => false,
+        }
+    }
 
-            if minute_counter.elapsed().as_secs() >= 60 {
-                self.refresh_config().await;
-                let (bones_price_updated, force) = {
-                    let c = self.config.read().unwrap();
-                    (c.bones_price_updated, c.bones_price_force_update)
-                };
-                if self.should_update_bones_price(bones_price_updated) || force {
-                    self.update_bones_price().await;
-                }
-                if force {
+    pub async fn run_job(&self, job: Job, forced: bool) -> Result<(), Error> {
+        match job {
+            Job::Lottery => self.lottery(forced).await?,
+            Job::BonesPrice => {
+                self.update_bones_price().await?;
+                if self.is_forced(job) {
                     tracing::info!("toggling bones price force");
                     self.db
                         .set_config_value(ConfigKey::ForceBonesPriceUpdate, "false")
-                        .await
-                        .unwrap();
+                        .await?;
                     self.config.write().unwrap().bones_price_force_update = false;
                 }
-                if self.should_decay_bones() {
-                    self.decay_bones().await;
+            }
+            Job::BonesDecay => self.bones_decay(forced).await?,
+            Job::RoleDecay => self.decay().await,
+            Job::SkewedOdds => {
+                if forced || self.should_update_skewed_odds().await {
+                    self.update_skewed_odds().await?;
                 }
+            }
+            Job::Unghost => {
+                let (deadline, c) = {
+                    let config = self.config.read().unwrap();
+                    (config.unghost_time, config.ghost_channel_id)
+                };
+                if let Some(chan_id) = c {
+                    let c = poise::serenity_prelude::ChannelId::from(chan_id);
+                    self.unghost_channel(deadline, c).await;
+                }
+            }
+            Job::Egg => {
+                let force_egg = forced || self.is_forced(job);
+                if self.should_run_egg(force_egg).await {
+                    match self.config.write() {
+                        Ok(mut c) => {
+                            c.force_egg = false;
+                        }
+                        Err(e) => {
+                            tracing::error!("{e}");
+                        }
+                    }
+                    match self.db.set_config_value(ConfigKey::ForceEgg, "false").await {
+                        Ok(_) => {}
+                        Err(e) => {
+                            tracing::error!("{e}");
+                        }
+                    }
 
-                minute_counter = tokio::time::Instant::now();
+                    tracing::info!("running egg");
+                    self.run_egg().await;
+                }
             }
+            Job::CrownTime => self.accrue_crown_time().await?,
+            Job::RoleRentals => self.role_rentals().await?,
+            Job::Auctions => self.auctions().await?,
+            Job::RefreshConfig => self.refresh_config().await,
+        }
+        Ok(())
+    }
 
-            if five_minute_counter.elapsed().as_secs() >= 300 {
-                self.decay().await;
-                five_minute_counter = tokio::time::Instant::now();
+    /// Credits the crown holder with the time since they were last credited. The first
+    /// run after a restart catches up from wherever the last one left off.
+    async fn accrue_crown_time(&self) -> Result<(), Error> {
+        if let Some((user_id, hours)) = self
+            .db
+            .accrue_crown_time(self.crown_role_id, chrono::Utc::now())
+            .await?
+        {
+            tracing::debug!("credited {user_id} with {hours:.2} crown hours");
+        }
+        Ok(())
+    }
+
+    /// Posts auctions that are due to start and settles the ones that ended
+    async fn auctions(&self) -> Result<(), Error> {
+        let client = self
+            .message_client
+            .as_ref()
+            .ok_or("Discord client not set")?;
+        let now = chrono::Utc::now();
+        for auction in self.db.get_active_auctions().await? {
+            if auction.status == "pending" && auction.start_time <= now {
+                auction::post_auction(client, &self.db, auction).await?;
+            } else if auction.status == "open" && auction.end_time <= now {
+                self.settle_auction(auction).await?;
             }
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         }
+        Ok(())
+    }
+
+    /// The high bidder gets the role the same way as buying it, the bid is spent
+    async fn settle_auction(&self, mut auction: database::Auction) -> Result<(), Error> {
+        let client = self
+            .message_client
+            .as_ref()
+            .ok_or("Discord client not set")?;
+        let channel = poise::serenity_prelude::ChannelId::new(auction.channel_id as u64);
+        let (winner, price) = match auction.high_bidder.zip(auction.high_bid) {
+            Some((winner, price)) => (poise::serenity_prelude::UserId::new(winner as u64), price),
+            None => {
+                self.db.close_auction(auction.id).await?;
+                auction.status = "unsold".to_string();
+                auction::update_auction_message(client, &auction).await;
+                return Ok(());
+            }
+        };
+        let guild = poise::serenity_prelude::GuildId::new(auction.guild_id as u64);
+        let role = RoleId::new(auction.role_id as u64);
+
+        if let Err(e) = client
+            .add_member_role(guild, winner, role, Some("Won the auction"))
+            .await
+        {
+            tracing::error!("Error giving {winner} the auctioned role {role}: {e}");
+            self.db.cancel_auction(auction.id).await?;
+            auction.status = "cancelled".to_string();
+            auction::update_auction_message(client, &auction).await;
+            channel
+                .say(
+                    client,
+                    format!(
+                        "I couldn't give <@{}> the role from auction #{}, their bid was refunded",
+                        winner, auction.id
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+
+        // the bid is only spent once the auction is closed, without that the role goes back
+        if let Err(e) = self.db.close_auction(auction.id).await {
+            tracing::error!("Error closing auction #{}: {e}", auction.id);
+            if let Err(e) = client
+                .remove_member_role(guild, winner, role, Some("The auction couldn't be closed"))
+                .await
+            {
+                tracing::error!("Error taking the auctioned role {role} back from {winner}: {e}");
+            }
+            self.db.cancel_auction(auction.id).await?;
+            auction.status = "cancelled".to_string();
+            auction::update_auction_message(client, &auction).await;
+            return Err(e);
+        }
+        auction.status = "sold".to_string();
+        auction::update_auction_message(client, &auction).await;
+
+        // the bid is spent and the role given, a failed handover needs someone to finish it
+        if let Err(e) = buy::take_unique_role(
+            client,
+            &self.db,
+            guild,
+            role,
+            winner,
+            price,
+            self.crown_role_id,
+            database::TenureEnd::Auction,
+        )
+        .await
+        {
+            tracing::error!("Error handing the auctioned role {role} over to {winner}: {e}");
+            channel
+                .send_message(
+                    client,
+                    CreateMessage::new()
+                        .content(format!(
+                            "<@{}> won auction #{} but I couldn't finish handing over <@&{}>, an admin needs to check who holds it",
+                            winner, auction.id, role
+                        ))
+                        .allowed_mentions(CreateAllowedMentions::new().empty_roles()),
+                )
+                .await?;
+            return Err(e);
+        }
+        self.db.increment_role_price(role.to_string()).await?;
+        buy::reload_role_prices(&self.db, &self.price_config).await?;
+
+        channel
+            .send_message(
+                client,
+                CreateMessage::new()
+                    .content(format!(
+                        "> <@{}> won auction #{} and takes <@&{}> for {} {}!",
+                        winner, auction.id, role, price, JBUCK_EMOJI
+                    ))
+                    .allowed_mentions(CreateAllowedMentions::new().empty_roles()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Takes lapsed rented roles away and reminds renters a day before theirs lapse
+    async fn role_rentals(&self) -> Result<(), Error> {
+        let client = self
+            .message_client
+            .as_ref()
+            .ok_or("Discord client not set")?;
+        let now = chrono::Utc::now();
+        let rentals = self.db.get_rentals().await?;
+        let prices = self.db.get_purchasable_roles().await?;
+        let mut role_names = HashMap::new();
+
+        for rental in rentals {
+            let (role_id, user_id) = (rental.role_id as u64, rental.user_id as u64);
+            let expired = rental.expires_at <= now;
+            if !expired && (rental.reminded || rental.expires_at - TimeDelta::days(1) > now) {
+                continue;
+            }
+
+            let guild = poise::serenity_prelude::GuildId::new(rental.guild_id as u64);
+            if let std::collections::hash_map::Entry::Vacant(e) = role_names.entry(guild) {
+                let roles = client.get_guild_roles(guild).await?;
+                e.insert(
+                    roles
+                        .into_iter()
+                        .map(|r| (r.id.get(), r.name))
+                        .collect::<HashMap<_, _>>(),
+                );
+            }
+            let name = role_names[&guild]
+                .get(&role_id)
+                .cloned()
+                .unwrap_or_else(|| "your role".to_string());
+
+            if expired {
+                self.db.end_rental(role_id, user_id).await?;
+                if let Err(e) = client
+                    .remove_member_role(
+                        guild,
+                        poise::serenity_prelude::UserId::new(user_id),
+                        RoleId::new(role_id),
+                        Some("Rental ran out"),
+                    )
+                    .await
+                {
+                    tracing::error!("Error removing rented role {role_id} from {user_id}: {e}");
+                }
+                self.dm(
+                    user_id,
+                    format!("Your rental of **{name}** ran out, rent it again with `/role rent`"),
+                )
+                .await;
+                continue;
+            }
+
+            self.db.set_rental_reminded(role_id, user_id).await?;
+            let mut m = CreateMessage::new().content(format!(
+                "Your rental of **{name}** runs out <t:{}:R>",
+                rental.expires_at.timestamp()
+            ));
+            if let Some((price, days)) = prices
+                .iter()
+                .find(|r| r.role_id == role_id)
+                .and_then(|r| r.rent())
+            {
+                m = m.button(
+                    CreateButton::new(format!(
+                        "{}{}-{}",
+                        crate::commands::role::RENEW_BUTTON,
+                        guild,
+                        role_id
+                    ))
+                    .label(format!("Renew for {days} days ({price} J-Bucks)")),
+                );
+            }
+            self.send_dm(user_id, m).await;
+        }
+        Ok(())
     }
 
     pub async fn decay(&self) {
@@ -231,276 +545,517 @@ impl Johnny {
         counter > limit
     }
 
-    pub async fn update_skewed_odds(&self) {
+    pub async fn update_skewed_odds(&self) -> Result<(), Error> {
         let bot_odds = rand::thread_rng().gen_range(0.3..=0.7);
         self.db
             .set_config_value(database::ConfigKey::BotOdds, &bot_odds.to_string())
-            .await
-            .unwrap();
+            .await?;
         self.db
             .set_config_value(
                 database::ConfigKey::BotOddsUpdated,
                 &chrono::Utc::now().timestamp().to_string(),
             )
-            .await
-            .unwrap();
+            .await?;
 
         let mut c = match self.config.write() {
             Ok(c) => c,
             Err(e) => {
                 tracing::error!("{e}");
-                return;
+                return Ok(());
             }
         };
         c.bot_odds_updated = Some(chrono::Utc::now());
         c.bot_odds = bot_odds;
         c.bot_odds_game_counter = 0;
+        Ok(())
     }
 
-    pub async fn should_trigger_lottery(&self, last: Option<chrono::NaiveDateTime>) -> bool {
-        let time = chrono::Utc::now()
-            .time()
-            .with_second(0)
-            .unwrap()
-            .with_nanosecond(0)
-            .unwrap();
+    pub async fn lottery(&self, forced: bool) -> Result<(), Error> {
+        let now = chrono::Utc::now();
+        for lottery in self.db.get_open_lotteries().await? {
+            // rounds from before seeds were committed get one as soon as possible
+            if self.db.get_seed(&lottery.game_id()).await?.is_none() {
+                fairness::commit_new_seed(&self.db, &lottery.game_id(), "lottery").await?;
+            }
+            if forced || lottery.draw_time <= now {
+                self.draw_lottery(lottery).await?;
+            }
+        }
+        self.charge_subscriptions().await
+    }
 
-        let today = chrono::Utc::now().date_naive();
-        let time = today.and_time(time).time();
+    // subscribers get their tickets once per round, as soon as it is open
+    async fn charge_subscriptions(&self) -> Result<(), Error> {
+        let now = chrono::Utc::now();
+        let lotteries = self.db.get_open_lotteries().await?;
+        for sub in self.db.get_subscriptions().await? {
+            let user = sub.user_id as u64;
+            if sub.expires_at <= now {
+                self.db
+                    .unsubscribe_lottery(user, Some(&sub.lottery_name))
+                    .await?;
+                self.dm(
+                    user,
+                    format!(
+                        "Your subscription to the **{}** lottery has ended",
+                        sub.lottery_name
+                    ),
+                )
+                .await;
+                continue;
+            }
+            let lottery = match lotteries.iter().find(|l| l.name == sub.lottery_name) {
+                Some(l) => l,
+                None => continue,
+            };
+            if sub.last_lottery_id == Some(lottery.id) {
+                continue;
+            }
 
-        if (time == NaiveTime::from_hms_opt(18, 00, 0).unwrap()
-            && (last.is_none() || last.unwrap().date() != today))
-            || self.dev_env
-        {
-            return true;
+            let bought = self
+                .db
+                .charge_subscription(
+                    user,
+                    &sub.lottery_name,
+                    lottery.id,
+                    sub.tickets,
+                    Reason::LotteryTicket.with_id(lottery.id),
+                )
+                .await?;
+            if bought.is_none() {
+                let cost = lottery.ticket_price * sub.tickets;
+                self.dm(
+                    user,
+                    format!(
+                        "You couldn't afford your {} :tickets: for the **{}** lottery ({} {}), I skipped this round",
+                        sub.tickets, sub.lottery_name, cost, JBUCK_EMOJI
+                    ),
+                )
+                .await;
+            }
         }
-        false
+        Ok(())
     }
 
-    pub async fn lottery(&self) {
-        let (base_prize, price) = {
-            let config = self.config.read().unwrap();
-            (config.lottery_base_prize, config.lottery_ticket_price)
-        };
-        let lottery_tickets = self.db.get_bought_tickets().await.unwrap();
-        let pot = lottery_tickets
-            .iter()
-            .map(|(_, x)| x * (price - 1))
-            .sum::<i32>()
-            + base_prize;
-        let lottery = game::Lottery::new(lottery_tickets.clone());
-        let winner = match self.config.read().unwrap().lottery_winner {
-            Some(x) => x,
-            None => lottery.get_winner(),
+    async fn dm(&self, user: u64, text: String) {
+        self.send_dm(user, CreateMessage::new().content(text)).await
+    }
+
+    async fn send_dm(&self, user: u64, m: CreateMessage) {
+        let client = match &self.message_client {
+            Some(client) => client,
+            None => {
+                tracing::warn!("Discord client not set");
+                return;
+            }
         };
+        if let Err(e) = poise::serenity_prelude::UserId::new(user)
+            .dm(client, m)
+            .await
+        {
+            tracing::error!("Error when dm'ing {user}: {e}");
+        }
+    }
+
+    async fn draw_lottery(&self, mut lottery: database::Lottery) -> Result<(), Error> {
+        let now = chrono::Utc::now();
+        let next_draw = scheduler::next_draw(&lottery.schedule, now)
+            .ok_or(format!("lottery {} has no upcoming draw", lottery.name))?;
+        let tiers = game::parse_tiers(&lottery.prize_tiers)?;
+        let lottery_tickets = self.db.get_bought_tickets(lottery.id).await?;
+        let tickets_sold = lottery_tickets.iter().map(|(_, x)| x).sum::<i32>();
+        let pot = lottery.prize(tickets_sold);
+        let seed = self
+            .db
+            .get_seed(&lottery.game_id())
+            .await?
+            .ok_or("lottery seed was never committed")?;
 
-        if winner == 0 {
-            return;
+        // nobody played, the same round carries on until the next draw
+        if tickets_sold == 0 {
+            lottery.draw_time = next_draw;
+            self.db.update_lottery(&lottery).await?;
+            return Ok(());
         }
 
-        self.db.award_balances(vec![winner], pot).await.unwrap();
-        let (new_base_prize, new_ticket_price) = {
-            let config = self.config.read().unwrap();
-            (
-                config.future_lottery_base_prize,
-                config.future_lottery_ticket_price,
+        let winners = if tickets_sold < lottery.min_tickets {
+            vec![]
+        } else {
+            game::Lottery::new(lottery_tickets.clone()).get_winners(
+                &seed.seed,
+                &seed.game_id,
+                tiers.len(),
             )
         };
+        let (prizes, rollover) = game::split_pot(pot, &tiers, &winners);
+
+        let outcome = if winners.is_empty() {
+            "rollover".to_string()
+        } else {
+            winners
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let next_id = match self
+            .db
+            .close_lottery(
+                &lottery,
+                &prizes,
+                pot,
+                rollover,
+                next_draw,
+                Some(&format!("tiers={}", tiers.len())),
+                &outcome,
+            )
+            .await?
         {
-            let mut config = self.config.write().unwrap();
-            config.lottery_base_prize = new_base_prize;
-            config.lottery_ticket_price = new_ticket_price;
-        }
-        self.db
-            .set_config_value(
-                database::ConfigKey::LotteryTicketPrice,
-                &new_ticket_price.to_string(),
+            Some(id) => id,
+            None => {
+                tracing::warn!("lottery {} was already drawn", lottery.id);
+                return Ok(());
+            }
+        };
+        let next_game_id = format!("lottery-{}", next_id);
+        let (_, next_commitment) =
+            fairness::commit_new_seed(&self.db, &next_game_id, "lottery").await?;
+
+        let mut text = if prizes.is_empty() {
+            format!(
+                "> :hourglass: Only **{} :tickets:** sold for the **{}** lottery, {} needed for a draw\n> The pot of **{} {}** rolls over!\n",
+                tickets_sold, lottery.name, lottery.min_tickets, pot, JBUCK_EMOJI
             )
-            .await
-            .unwrap();
-        self.db
-            .set_config_value(
-                database::ConfigKey::LotteryBasePrize,
-                &new_base_prize.to_string(),
+        } else {
+            format!(
+                "> :tada: :tada: WOW! The **{}** lottery has been drawn!\n",
+                lottery.name
             )
-            .await
-            .unwrap();
-
-        self.db
-            .del_config_value(database::ConfigKey::LotteryWinner)
-            .await
-            .unwrap();
-        self.config.write().unwrap().lottery_winner = None;
+        };
+        for (tier, (winner, prize)) in prizes.iter().enumerate() {
+            let medal = match tier {
+                0 => ":first_place:",
+                1 => ":second_place:",
+                2 => ":third_place:",
+                _ => ":medal:",
+            };
+            let num_tickets = match lottery_tickets.iter().find(|a| a.0 == *winner) {
+                Some(a) => a.1,
+                None => 0,
+            };
+            text.push_str(&format!(
+                "> {} <@{}> won **{} {}** with **{} :tickets:**\n",
+                medal, winner, prize, JBUCK_EMOJI, num_tickets
+            ));
+        }
+        if !prizes.is_empty() && rollover > 0 {
+            text.push_str(&format!(
+                "> **{} {}** rolls over into the next round\n",
+                rollover, JBUCK_EMOJI
+            ));
+        }
 
         let losers = lottery_tickets
             .iter()
             .map(|(a, _)| a)
-            .filter(|a| **a != winner)
+            .filter(|a| !winners.contains(a))
             .collect::<Vec<_>>();
-
-        let loser_text = if losers.is_empty() {
-            "".to_string()
-        } else {
-            let text = losers
+        if !prizes.is_empty() && !losers.is_empty() {
+            let loser_text = losers
                 .iter()
                 .map(|a| format!("<@{}>", a))
                 .collect::<Vec<String>>()
                 .join(", ");
-            format!("> Losers: {}\n", text)
-        };
+            text.push_str(&format!("> Losers: {}\n", loser_text));
+        }
 
-        let num_tickets = match lottery_tickets.iter().find(|a| a.0 == winner) {
-            Some(a) => a.1,
-            None => 0,
-        };
-        let text = format!("> :tada: :tada: WOW! <@{}> just won the lottery!\n> They won **{} {}** by buying only **{} :tickets:**\n{}> \n> **New lottery starting... NOW**\n> Prize pool: {} {}\n> Use ***/buy lottery*** to purchase a ticket for {} {}",
-            winner, pot, JBUCK_EMOJI, num_tickets, loser_text, new_base_prize, JBUCK_EMOJI, new_ticket_price, JBUCK_EMOJI);
+        text.push_str(&format!("> \n> **New {} lottery starting... NOW**\n> Prize pool: {} {}\n> Draw: <t:{}:R>\n> Use ***/buy lottery*** to purchase a ticket for {} {}\n> -# Seed `{}`, check it with `/verify {}`\n> -# Next commitment `{}`",
+            lottery.name, lottery.next_base_prize + rollover, JBUCK_EMOJI, next_draw.timestamp(), lottery.next_ticket_price, JBUCK_EMOJI, seed.seed, seed.game_id, next_commitment));
 
         let m = { CreateMessage::new().content(text) };
 
         if let Some(client) = &self.message_client {
-            self.channel.send_message(client, m).await.unwrap();
+            self.channel.send_message(client, m).await?;
         } else {
             tracing::warn!("Discord client not set");
         }
 
-        self.db.clear_tickets().await.unwrap();
+        Ok(())
     }
 
-    fn should_update_bones_price(&self, last: chrono::DateTime<chrono::Utc>) -> bool {
-        let time = chrono::Utc::now()
-            .time()
-            .with_second(0)
-            .unwrap()
-            .with_nanosecond(0)
-            .unwrap();
-
-        if (time == NaiveTime::from_hms_opt(0, 0, 0).unwrap()
-            || time == NaiveTime::from_hms_opt(12, 0, 0).unwrap())
-            && last.checked_add_signed(TimeDelta::minutes(55)).unwrap() < chrono::Utc::now()
-        {
-            return true;
-        }
-        if last.checked_add_signed(TimeDelta::minutes(5)).unwrap() < chrono::Utc::now()
-            && self.dev_env
-        {
-            return true;
-        }
-        false
-    }
-
-    async fn update_bones_price(&self) {
-        let we = is_weekend();
-        let (min, max, old_price, last_was_increase) = {
+    async fn update_bones_price(&self) -> Result<(), Error> {
+        let (model, min, max, since) = {
             let config = self.config.read().unwrap();
-            let a = if we {
-                (25, None)
-            } else {
-                (config.bones_price, config.bones_price_last_was_increase)
-            };
-            (config.bones_price_min, config.bones_price_max, a.0, a.1)
+            (
+                config.bones_price_model,
+                config.bones_price_min,
+                config.bones_price_max,
+                config.bones_price_updated,
+            )
         };
-        if min > max {
-            tracing::error!("Invalid bones price range. Min: {}, Max: {}", min, max);
-            return;
+        // only the random walk uses the range
+        if model == pricing::PriceModelKind::RandomWalk && min > max {
+            return Err(format!("Invalid bones price range. Min: {}, Max: {}", min, max).into());
         }
-        let mut change = min;
-        if min < max {
-            change = rand::thread_rng().gen_range(min..=max);
-        }
-        if we {
-            change /= 2;
-        }
-
-        let odds: f64;
-        if last_was_increase.is_none() {
-            odds = 0.5;
-        } else if last_was_increase.unwrap() {
-            odds = 0.6;
-        } else {
-            odds = 0.4;
-        };
-        let mut price: i32 = if rand::thread_rng().gen_bool(odds) {
-            old_price + change
-        } else {
-            old_price - change
+        let (bought, sold) = self.db.get_bones_flow(since).await?;
+        let (old_price, price) = {
+            let config = self.config.read().unwrap();
+            let market = pricing::Market {
+                price: config.bones_price,
+                last_was_increase: config.bones_price_last_was_increase,
+                weekend: is_weekend(),
+                bought,
+                sold,
+            };
+            let price = pricing::from_config(&config).next_price(&market, &mut rand::thread_rng());
+            (config.bones_price, price.max(0))
         };
-        if price < 0 {
-            price = 0;
-        }
 
+        let now = chrono::Utc::now();
         self.db
             .set_config_value(ConfigKey::BonesPrice, &price.to_string())
-            .await
-            .unwrap();
+            .await?;
         self.db
-            .set_config_value(
-                ConfigKey::BonesPriceUpdated,
-                &chrono::Utc::now().timestamp().to_string(),
-            )
-            .await
-            .unwrap();
+            .set_config_value(ConfigKey::BonesPriceUpdated, &now.timestamp().to_string())
+            .await?;
+        self.db.record_bones_price(price, now).await?;
         self.db
             .set_config_value(
                 ConfigKey::BonesPriceLastWasIncrease,
                 &(price > old_price).to_string(),
             )
-            .await
-            .unwrap();
+            .await?;
         {
             let mut config = self.config.write().unwrap();
             config.bones_price = price;
-            config.bones_price_updated = chrono::Utc::now();
+            config.bones_price_updated = now;
             config.bones_price_last_was_increase = Some(price > old_price);
         }
 
-        let m = {
-            CreateMessage::new().content(format!(
-                ":bone: I just set the bones price to {} {}",
-                price, JBUCK_EMOJI
-            ))
-        };
+        let mut content = format!(
+            ":bone: I just set the bones price to {} {}",
+            price, JBUCK_EMOJI
+        );
+        match self.settle_predictions(old_price, price).await {
+            Ok(Some(predictions)) => content.push_str(&format!("\n{}", predictions)),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("Failed to settle the bones predictions: {}", e);
+                // the stakes were placed against the old price, they can't wait for the next one
+                let mut refunded = 0;
+                for direction in [buy::Direction::Up, buy::Direction::Down] {
+                    match self.db.refund_escrow(direction.game_id()).await {
+                        Ok(refunds) => refunded += refunds.len(),
+                        Err(e) => tracing::error!(
+                            "Failed to refund the {} predictions: {}",
+                            direction.game_id(),
+                            e
+                        ),
+                    }
+                }
+                if refunded > 0 {
+                    content.push_str(&format!(
+                        "
+> I couldn't settle the predictions, {} stakes were returned",
+                        refunded
+                    ));
+                }
+            }
+        }
+        let m = { CreateMessage::new().content(content) };
 
         if let Some(client) = &self.message_client {
-            self.channel.send_message(client, m).await.unwrap();
+            self.channel.send_message(client, m).await?;
         } else {
             tracing::warn!("Discord client not set");
         }
+        Ok(())
     }
 
-    fn should_decay_bones(&self) -> bool {
-        let time = chrono::Utc::now()
-            .time()
-            .with_second(0)
-            .unwrap()
-            .with_nanosecond(0)
-            .unwrap();
-        if time == NaiveTime::from_hms_opt(0, 0, 0).unwrap()
-            && chrono::Utc::now().weekday() == chrono::Weekday::Sat
-        {
-            return true;
+    /// Pays out the bets on the price that was just set, the house cut goes to the crown
+    async fn settle_predictions(
+        &self,
+        old_price: i32,
+        price: i32,
+    ) -> Result<Option<String>, Error> {
+        let up = self.db.get_stakes(buy::Direction::Up.game_id()).await?;
+        let down = self.db.get_stakes(buy::Direction::Down.game_id()).await?;
+        if up.is_empty() && down.is_empty() {
+            return Ok(None);
+        }
+        let (winners, losers, moved) = match price.cmp(&old_price) {
+            std::cmp::Ordering::Greater => (up, down, "went **up**"),
+            std::cmp::Ordering::Less => (down, up, "went **down**"),
+            // nobody called it, everyone gets their stake back
+            std::cmp::Ordering::Equal => ([up, down].concat(), vec![], "didn't move"),
+        };
+        let (payouts, cut) =
+            game::settle_predictions(&winners, &losers, game::PREDICTION_HOUSE_CUT_PERCENT);
+
+        // the payouts cover both sides, both escrows and the crown's cut settle together
+        let crowned = self
+            .db
+            .get_unique_role_holder(self.crown_role_id)
+            .await?
+            .map(|holder| holder.user_id)
+            .filter(|_| cut > 0);
+        self.db
+            .settle_escrows(
+                &[buy::Direction::Up.game_id(), buy::Direction::Down.game_id()],
+                &payouts,
+                crowned.map(|crown| (crown, cut)),
+                Reason::BonesPrediction.with_id("bones-prediction"),
+            )
+            .await?;
+
+        let mut text = format!("> The price {}!", moved);
+        if !winners.is_empty() && !losers.is_empty() {
+            let won = payouts
+                .iter()
+                .map(|(user, payout)| format!("<@{}> {} {}", user, payout, JBUCK_EMOJI))
+                .collect::<Vec<_>>()
+                .join(", ");
+            text.push_str(&format!(
+                " Paid out: {}
+> {} lost {} {}",
+                won,
+                losers.len(),
+                losers.iter().map(|(_, stake)| stake).sum::<i32>(),
+                JBUCK_EMOJI
+            ));
+        } else {
+            text.push_str(&format!(" {} stakes were returned", payouts.len()));
+        }
+        if let Some(crowned) = crowned {
+            text.push_str(&format!(
+                "
+> {} {} was paid to <@{}>! (Crown's Tax)",
+                cut, JBUCK_EMOJI, crowned
+            ));
         }
-        false
+        Ok(Some(text))
     }
 
-    async fn decay_bones(&self) {
-        let affected = self.db.decay_bones().await.unwrap();
+    /// Decays bones on the schedule in the config, warning the players that are about
+    /// to lose some a day and an hour ahead
+    async fn bones_decay(&self, forced: bool) -> Result<(), Error> {
+        let (schedule, decayed_at, warning, percent, min_age_days) = {
+            let config = self.config.read().unwrap();
+            (
+                config.bones_decay_schedule.clone(),
+                config.bones_decayed_at,
+                config.bones_decay_warning,
+                config.bones_decay_percent,
+                config.bones_decay_min_age_days,
+            )
+        };
+        let now = chrono::Utc::now();
+        let Some(decayed_at) = decayed_at else {
+            // never decayed before, the schedule starts counting from now
+            self.set_bones_decay_state(now, None).await?;
+            return Ok(());
+        };
+        let next = scheduler::next_draw(&schedule, decayed_at)
+            .ok_or_else(|| format!("Invalid bones decay schedule {}", schedule))?;
+
+        if forced || next <= now {
+            self.decay_bones(percent, min_age_days).await?;
+            self.set_bones_decay_state(now, None).await?;
+            return Ok(());
+        }
+        let due = BONES_DECAY_WARNINGS.into_iter().find(|hours| {
+            next - now <= TimeDelta::hours(*hours as i64) && warning.is_none_or(|w| w > *hours)
+        });
+        if let Some(hours) = due {
+            self.warn_bones_decay(next, percent, min_age_days).await?;
+            self.set_bones_decay_state(decayed_at, Some(hours)).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_bones_decay_state(
+        &self,
+        decayed_at: chrono::DateTime<chrono::Utc>,
+        warning: Option<i32>,
+    ) -> Result<(), Error> {
+        self.db
+            .set_config_value(
+                ConfigKey::BonesDecayedAt,
+                &decayed_at.timestamp().to_string(),
+            )
+            .await?;
+        match warning {
+            Some(hours) => {
+                self.db
+                    .set_config_value(ConfigKey::BonesDecayWarning, &hours.to_string())
+                    .await?
+            }
+            None => {
+                self.db
+                    .del_config_value(ConfigKey::BonesDecayWarning)
+                    .await?
+            }
+        }
+        let mut config = self.config.write().unwrap();
+        config.bones_decayed_at = Some(decayed_at);
+        config.bones_decay_warning = warning;
+        Ok(())
+    }
+
+    async fn warn_bones_decay(
+        &self,
+        at: chrono::DateTime<chrono::Utc>,
+        percent: i32,
+        min_age_days: i32,
+    ) -> Result<(), Error> {
+        let acquired_before = (min_age_days > 0).then(|| at - TimeDelta::days(min_age_days as i64));
+        let decaying = self.db.get_decaying_bones(percent, acquired_before).await?;
+        let Some(client) = &self.message_client else {
+            tracing::warn!("Discord client not set");
+            return Ok(());
+        };
+        for (person, bones) in decaying {
+            let u = poise::serenity_prelude::UserId::new(person);
+            let content = format!(
+                ":bone: Heads up, {} of your bones decay <t:{}:R>! Sell them with `/sell bones` or on the `/market` before then",
+                bones,
+                at.timestamp()
+            );
+            if let Err(e) = u
+                .dm(
+                    client,
+                    poise::serenity_prelude::CreateMessage::default().content(content),
+                )
+                .await
+            {
+                tracing::error!("Error when dm'ing {person}: {e}");
+            }
+        }
+        Ok(())
+    }
+
+    async fn decay_bones(&self, percent: i32, min_age_days: i32) -> Result<(), Error> {
+        let acquired_before =
+            (min_age_days > 0).then(|| chrono::Utc::now() - TimeDelta::days(min_age_days as i64));
+        let affected = self.db.decay_bones(percent, acquired_before).await?;
         let m = {
             CreateMessage::new().content(format!(
-                ":bone: I just decayed all bones from the economy! :bone:\n{} people lost all their bones",
-                affected.len()
+                ":bone: I just decayed {} from the economy! :bone:\n{} people lost {} bones",
+                describe_bones_decay(percent, min_age_days),
+                affected.len(),
+                affected.iter().map(|(_, bones)| bones).sum::<i32>()
             ))
         };
 
         if let Some(client) = &self.message_client {
-            self.channel.send_message(client, m).await.unwrap();
-            for person in affected {
+            self.channel.send_message(client, m).await?;
+            for (person, bones) in affected {
                 let u = poise::serenity_prelude::UserId::new(person);
                 match u
                     .dm(
                         client,
                         poise::serenity_prelude::CreateMessage::default()
-                            .content("Oh no, your bones expired!"),
+                            .content(format!("Oh no, {} of your bones expired!", bones)),
                     )
                     .await
                 {
@@ -513,10 +1068,12 @@ impl Johnny {
         } else {
             tracing::warn!("Discord client not set");
         }
+        Ok(())
     }
 
     async fn should_run_egg(&self, force: bool) -> bool {
-        rand::thread_rng().gen_bool(1.0 / 604800.0) || force
+        // checked once a minute, so roughly once a week
+        rand::thread_rng().gen_bool(1.0 / 10080.0) || force
     }
 
     async fn run_egg(&self) {
@@ -616,6 +1173,16 @@ pub fn get_egged_name(nick: &str) -> String {
     }
 }
 
+/// What a bones decay takes, e.g. "50% of the bones older than 7 days"
+pub fn describe_bones_decay(percent: i32, min_age_days: i32) -> String {
+    match (percent, min_age_days) {
+        (100, 0) => "all bones".to_string(),
+        (100, days) => format!("all bones older than {} days", days),
+        (percent, 0) => format!("{}% of all bones", percent),
+        (percent, days) => format!("{}% of the bones older than {} days", percent, days),
+    }
+}
+
 pub fn is_weekend() -> bool {
     let now = chrono::Utc::now();
     now.weekday() == chrono::Weekday::Sat || now.weekday() == chrono::Weekday::Sun
@@ -1,13 +1,18 @@
 use crate::database::{ChannelDatabase, RoleDatabase, ShopDatabase};
+mod chart;
 mod commands;
 mod database;
 mod discord;
 mod eventhandler;
+mod fairness;
 mod game;
 mod johnny;
+mod pricing;
+mod scheduler;
 mod texts;
 
-use database::ConfigDatabase;
+use database::{ConfigDatabase, EscrowDatabase};
+use discord::JBUCK_EMOJI;
 use poise::{serenity_prelude as serenity, CreateReply};
 use std::sync::mpsc;
 use std::sync::RwLock;
@@ -43,10 +48,6 @@ pub struct Config {
     bot_odds_game_counter: u8,
     game_length_seconds: i32,
     robbery_length_seconds: i8,
-    lottery_ticket_price: i32,
-    lottery_base_prize: i32,
-    future_lottery_ticket_price: i32,
-    future_lottery_base_prize: i32,
     side_chance: u32,
     community_emoji_price: i32,
     bones_price: i32,
@@ -55,7 +56,6 @@ pub struct Config {
     bones_price_max: i32,
     bones_price_last_was_increase: Option<bool>,
     bones_price_force_update: bool,
-    lottery_winner: Option<u64>,
     force_egg: bool,
     just_egged: Option<u64>,
     ghost_channel_id: Option<u64>,
@@ -63,6 +63,18 @@ pub struct Config {
     ghost_channel_odds: Option<u8>,
     unghost_time: Option<chrono::DateTime<chrono::Utc>>,
     voice_channel_celebration_amount: i32,
+    role_refund_percent: i32,
+    bones_price_model: pricing::PriceModelKind,
+    bones_price_target: i32,
+    bones_price_pull_percent: i32,
+    bones_demand_impact_percent: i32,
+    bones_price_noise: i32,
+    bones_demand_max_change: i32,
+    bones_decay_schedule: String,
+    bones_decay_percent: i32,
+    bones_decay_min_age_days: i32,
+    bones_decayed_at: Option<chrono::DateTime<chrono::Utc>>,
+    bones_decay_warning: Option<i32>,
 }
 
 impl Config {
@@ -72,10 +84,6 @@ impl Config {
             bot_odds: input.bot_odds.unwrap_or(0.5),
             game_length_seconds: input.game_length_seconds.unwrap_or(30),
             robbery_length_seconds: input.robbery_length_seconds.unwrap_or(60),
-            lottery_ticket_price: input.lottery_ticket_price.unwrap_or(5),
-            lottery_base_prize: input.lottery_base_prize.unwrap_or(10),
-            future_lottery_ticket_price: input.future_lottery_ticket_price.unwrap_or(5),
-            future_lottery_base_prize: input.future_lottery_base_prize.unwrap_or(10),
             side_chance: input.side_chance.unwrap_or(2),
             community_emoji_price: input.community_emoji_price,
             bones_price: input.bones_price,
@@ -87,7 +95,6 @@ impl Config {
             bot_odds_updated: input.bot_odds_updated,
             bot_odds_game_limit: input.bot_odds_game_limit.unwrap_or(10),
             bot_odds_game_counter: input.bot_odds_game_counter.unwrap_or(0),
-            lottery_winner: input.lottery_winner,
             force_egg: input.force_egg,
             just_egged: None,
             ghost_channel_id: input.ghost_channel_id,
@@ -95,6 +102,18 @@ impl Config {
             ghost_channel_odds: input.ghost_channel_odds,
             unghost_time: input.unghost_time,
             voice_channel_celebration_amount: input.voice_channel_celebration_amount,
+            role_refund_percent: input.role_refund_percent,
+            bones_price_model: input.bones_price_model,
+            bones_price_target: input.bones_price_target,
+            bones_price_pull_percent: input.bones_price_pull_percent,
+            bones_demand_impact_percent: input.bones_demand_impact_percent,
+            bones_price_noise: input.bones_price_noise,
+            bones_demand_max_change: input.bones_demand_max_change,
+            bones_decay_schedule: input.bones_decay_schedule,
+            bones_decay_percent: input.bones_decay_percent,
+            bones_decay_min_age_days: input.bones_decay_min_age_days,
+            bones_decayed_at: input.bones_decayed_at,
+            bones_decay_warning: input.bones_decay_warning,
         }
     }
 }
@@ -115,6 +134,7 @@ pub struct Data {
     active_checks: Mutex<HashSet<u64>>,
     config: Arc<RwLock<Config>>,
     cursed_player: Option<u64>,
+    jobs: tokio::sync::mpsc::UnboundedSender<scheduler::JobCommand>,
 }
 
 async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
@@ -159,11 +179,12 @@ async fn main() {
 
     tracing_subscriber::fmt().init();
 
-    let mut commands = vec![
+    let commands = vec![
         commands::help::help(),
         commands::say::say(),
         commands::checkbucks::checkbucks(),
         commands::balance::balance(),
+        commands::balance::history(),
         commands::register::register(),
         commands::give::give(),
         commands::fine::fine(),
@@ -178,18 +199,28 @@ async fn main() {
         commands::robbingevent::robbingevent(),
         commands::leaderboard::leaderboard(),
         commands::rockpaperscissors::rpsgamble(),
+        commands::blackjack::blackjack(),
+        commands::poker::poker(),
         commands::paidchannels::setchannelprice(),
         commands::buy::buy(),
         commands::buy::shop(),
         commands::buy::setroleprice(),
+        commands::buy::setrolerent(),
         commands::buy::decay(),
         commands::buy::list_decays(),
         commands::buy::list_prices(),
         commands::leaderboard::crownleaderboard(),
+        commands::leaderboard::roleleaderboard(),
+        commands::role::role(),
+        commands::auction::auction(),
+        commands::auction::bid(),
+        commands::item::item(),
+        commands::item::inventory(),
+        commands::market::market(),
         commands::config::config(),
         commands::lottery::lottery(),
         commands::giveaway::giveaway(),
-        commands::buy::bones_status(),
+        commands::buy::bonesmarket(),
         commands::buy::sell(),
         commands::gamble::gamble(),
         commands::report::report(),
@@ -197,13 +228,10 @@ async fn main() {
         commands::report::deletereport(),
         commands::robbingevent::buyrobbery(),
         commands::quit::quit(),
+        commands::jobs::jobs(),
+        commands::verify::verify(),
     ];
 
-    if var("MOUNT_ALL").is_ok() {
-        tracing::debug!("Mounting all commands");
-        commands.push(commands::blackjack::blackjack());
-    };
-
     let db: database::Database = database::Database::new().await.unwrap();
     let db2 = database::Database::new().await.unwrap();
 
@@ -323,6 +351,8 @@ async fn main() {
         ..Default::default()
     };
 
+    let (job_tx, job_rx) = tokio::sync::mpsc::unbounded_channel();
+
     let framework = poise::Framework::builder()
         .setup(move |_ctx, _ready, _framework| {
             Box::pin(async move {
@@ -341,6 +371,7 @@ async fn main() {
                     active_checks: Mutex::new(HashSet::new()),
                     config,
                     cursed_player: cursed,
+                    jobs: job_tx,
                 })
             })
         })
@@ -359,6 +390,8 @@ async fn main() {
         .type_map_insert::<HttpKey>(HttpClient::new())
         .await;
 
+    recover_escrow(&db2, den_channel_id, &client.as_ref().unwrap().http).await;
+
     let (tx, rx) = mpsc::channel();
     let johnny = johnny::Johnny::new(
         db2,
@@ -367,9 +400,10 @@ async fn main() {
         den_channel_id,
         client.as_ref().unwrap(),
         in_dev,
+        crown_role_id,
     );
     tokio::spawn(async move {
-        johnny.start(rx).await;
+        johnny.start(rx, job_rx).await;
     });
 
     let shard_manager = client.as_ref().unwrap().shard_manager.clone();
@@ -412,6 +446,57 @@ async fn wait_until_shutdown() {
     tracing::debug!("Received CTRL-C, shutting down...");
 }
 
+// games only live in memory, so any stake still in escrow at startup belongs to a
+// game that died with the last process. hand it back and let the den know.
+async fn recover_escrow(
+    db: &database::Database,
+    den_channel_id: serenity::ChannelId,
+    http: &serenity::Http,
+) {
+    let games = match db.get_unsettled_games().await {
+        Ok(games) => games,
+        Err(e) => {
+            tracing::error!("Failed to load open escrows: {}", e);
+            return;
+        }
+    };
+
+    // auctions and bones predictions outlive restarts, their jobs settle them
+    let games = games.into_iter().filter(|(_, game)| {
+        game != database::Reason::AuctionBid.as_str()
+            && game != database::Reason::BonesPrediction.as_str()
+    });
+    for (game_id, game) in games {
+        let refunds = match db.refund_escrow(&game_id).await {
+            Ok(refunds) => refunds,
+            Err(e) => {
+                tracing::error!("Failed to refund escrow for game {}: {}", game_id, e);
+                continue;
+            }
+        };
+        tracing::info!(
+            "Refunded {} stakes for unfinished game {}",
+            refunds.len(),
+            game_id
+        );
+
+        let players = refunds
+            .iter()
+            .map(|(user_id, amount)| format!("<@{}> {} {}", user_id, amount, JBUCK_EMOJI))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let message = serenity::CreateMessage::default()
+            .content(format!(
+                "> ### I went down in the middle of a {} game!\n> All bets have been refunded: {}",
+                game, players
+            ))
+            .allowed_mentions(serenity::CreateAllowedMentions::new().empty_users());
+        if let Err(e) = den_channel_id.send_message(http, message).await {
+            tracing::error!("Failed to post escrow refund notice: {}", e);
+        }
+    }
+}
+
 async fn setup_community_emojis(db: &database::Database) {
     let emojis = match db.get_community_emojis().await {
         Ok(emojis) => emojis,
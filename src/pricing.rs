@@ -0,0 +1,222 @@
+use rand::{Rng, RngCore};
+
+use crate::Config;
+
+/// The random walk starts over from this price every weekend
+pub const WEEKEND_PRICE: i32 = 25;
+
+/// What the next bones price is worked out from
+#[derive(Debug, Clone)]
+pub struct Market {
+    pub price: i32,
+    pub last_was_increase: Option<bool>,
+    pub weekend: bool,
+    /// Bones bought from Johnny since the last price
+    pub bought: i32,
+    /// Bones sold to Johnny since the last price
+    pub sold: i32,
+}
+
+pub trait PriceModel {
+    fn next_price(&self, market: &Market, rng: &mut dyn RngCore) -> i32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum PriceModelKind {
+    #[name = "Random walk"]
+    RandomWalk,
+    #[name = "Mean reversion"]
+    MeanReversion,
+    #[name = "Supply and demand"]
+    SupplyDemand,
+}
+
+impl PriceModelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceModelKind::RandomWalk => "random_walk",
+            PriceModelKind::MeanReversion => "mean_reversion",
+            PriceModelKind::SupplyDemand => "supply_demand",
+        }
+    }
+}
+
+impl std::str::FromStr for PriceModelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random_walk" => Ok(PriceModelKind::RandomWalk),
+            "mean_reversion" => Ok(PriceModelKind::MeanReversion),
+            "supply_demand" => Ok(PriceModelKind::SupplyDemand),
+            _ => Err(format!("Unknown price model {s}")),
+        }
+    }
+}
+
+/// Moves by a random amount between the min and max change, more likely in the same
+/// direction as last time. Weekends start over from [`WEEKEND_PRICE`] with half the change.
+pub struct RandomWalk {
+    pub min_change: i32,
+    pub max_change: i32,
+}
+
+impl PriceModel for RandomWalk {
+    fn next_price(&self, market: &Market, rng: &mut dyn RngCore) -> i32 {
+        let (price, last_was_increase) = if market.weekend {
+            (WEEKEND_PRICE, None)
+        } else {
+            (market.price, market.last_was_increase)
+        };
+        let mut change = self.min_change;
+        if self.min_change < self.max_change {
+            change = rng.gen_range(self.min_change..=self.max_change);
+        }
+        if market.weekend {
+            change /= 2;
+        }
+
+        let odds = match last_was_increase {
+            None => 0.5,
+            Some(true) => 0.6,
+            Some(false) => 0.4,
+        };
+        if rng.gen_bool(odds) {
+            price + change
+        } else {
+            price - change
+        }
+    }
+}
+
+/// Closes a percentage of the gap to the target every tick, give or take up to `noise`
+pub struct MeanReversion {
+    pub target: i32,
+    pub pull_percent: i32,
+    pub noise: i32,
+}
+
+impl PriceModel for MeanReversion {
+    fn next_price(&self, market: &Market, rng: &mut dyn RngCore) -> i32 {
+        let pull = (self.target - market.price) * self.pull_percent / 100;
+        let noise = self.noise.abs();
+        market.price + pull + rng.gen_range(-noise..=noise)
+    }
+}
+
+/// Every bone more bought than sold since the last tick raises the price by
+/// `impact_percent`, and the other way round, at most `max_change` per tick.
+/// Give or take up to `noise` so a quiet market still moves.
+pub struct SupplyDemand {
+    pub impact_percent: i32,
+    pub max_change: i32,
+    pub noise: i32,
+}
+
+impl PriceModel for SupplyDemand {
+    fn next_price(&self, market: &Market, rng: &mut dyn RngCore) -> i32 {
+        let demand = (market.bought - market.sold) as i64;
+        let change = (market.price as i64 * demand * self.impact_percent as i64 / 100)
+            .clamp(-self.max_change.abs() as i64, self.max_change.abs() as i64)
+            as i32;
+        let noise = self.noise.abs();
+        market.price + change + rng.gen_range(-noise..=noise)
+    }
+}
+
+/// The model admins picked with `/config`, each with its own settings
+pub fn from_config(config: &Config) -> Box<dyn PriceModel + Send> {
+    match config.bones_price_model {
+        PriceModelKind::RandomWalk => Box::new(RandomWalk {
+            min_change: config.bones_price_min,
+            max_change: config.bones_price_max,
+        }),
+        PriceModelKind::MeanReversion => Box::new(MeanReversion {
+            target: config.bones_price_target,
+            pull_percent: config.bones_price_pull_percent,
+            noise: config.bones_price_noise,
+        }),
+        PriceModelKind::SupplyDemand => Box::new(SupplyDemand {
+            impact_percent: config.bones_demand_impact_percent,
+            max_change: config.bones_demand_max_change,
+            noise: config.bones_price_noise,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_models() {
+        use rand::SeedableRng;
+        let market = Market {
+            price: 40,
+            last_was_increase: Some(true),
+            weekend: false,
+            bought: 0,
+            sold: 0,
+        };
+        let walk = RandomWalk {
+            min_change: 1,
+            max_change: 5,
+        };
+        let walk_prices = |seed| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| walk.next_price(&market, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(walk_prices(7), walk_prices(7));
+        assert!(walk_prices(7).iter().all(|p| (35..=45).contains(p)));
+        assert!(walk_prices(7).iter().all(|p| *p != 40));
+
+        // weekends start over with half the change
+        let weekend = Market {
+            weekend: true,
+            ..market.clone()
+        };
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let price = walk.next_price(&weekend, &mut rng);
+            assert!((WEEKEND_PRICE - 2..=WEEKEND_PRICE + 2).contains(&price));
+        }
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let reversion = MeanReversion {
+            target: 20,
+            pull_percent: 50,
+            noise: 0,
+        };
+        assert_eq!(reversion.next_price(&market, &mut rng), 30);
+        let noisy = MeanReversion {
+            noise: 2,
+            ..reversion
+        };
+        for _ in 0..20 {
+            assert!((28..=32).contains(&noisy.next_price(&market, &mut rng)));
+        }
+
+        let demand = SupplyDemand {
+            impact_percent: 1,
+            max_change: 5,
+            noise: 0,
+        };
+        let traded = |bought, sold| Market {
+            bought,
+            sold,
+            ..market.clone()
+        };
+        assert_eq!(demand.next_price(&traded(10, 0), &mut rng), 44);
+        assert_eq!(demand.next_price(&traded(0, 5), &mut rng), 38);
+        assert_eq!(demand.next_price(&traded(100, 0), &mut rng), 45);
+        assert_eq!(demand.next_price(&traded(3, 3), &mut rng), 40);
+
+        assert_eq!(
+            "mean_reversion".parse::<PriceModelKind>(),
+            Ok(PriceModelKind::MeanReversion)
+        );
+        assert!("sideways".parse::<PriceModelKind>().is_err());
+    }
+}
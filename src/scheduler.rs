@@ -0,0 +1,159 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use tokio::sync::oneshot;
+
+/// When bones decay unless admins set another schedule with `/config`
+pub const BONES_DECAY_SCHEDULE: &str = "0 0 0 * * Sat";
+
+/// Every periodic task Johnny runs. The schedule decides when a job is due, the job
+/// itself may still decide there is nothing to do (e.g. unghosting without a deadline).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, poise::ChoiceParameter)]
+pub enum Job {
+    Lottery,
+    BonesPrice,
+    BonesDecay,
+    RoleDecay,
+    SkewedOdds,
+    Unghost,
+    Egg,
+    CrownTime,
+    RoleRentals,
+    Auctions,
+    RefreshConfig,
+}
+
+impl Job {
+    pub const ALL: [Job; 11] = [
+        Job::RefreshConfig,
+        Job::Lottery,
+        Job::BonesPrice,
+        Job::BonesDecay,
+        Job::RoleDecay,
+        Job::SkewedOdds,
+        Job::Unghost,
+        Job::Egg,
+        Job::CrownTime,
+        Job::RoleRentals,
+        Job::Auctions,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Job::Lottery => "lottery",
+            Job::BonesPrice => "bones_price",
+            Job::BonesDecay => "bones_decay",
+            Job::RoleDecay => "role_decay",
+            Job::SkewedOdds => "skewed_odds",
+            Job::Unghost => "unghost",
+            Job::Egg => "egg",
+            Job::CrownTime => "crown_time",
+            Job::RoleRentals => "role_rentals",
+            Job::Auctions => "auctions",
+            Job::RefreshConfig => "refresh_config",
+        }
+    }
+
+    // cron expressions with a leading seconds field, all in UTC
+    fn cron(&self, dev_env: bool) -> &'static str {
+        match (self, dev_env) {
+            // each lottery has its own draw schedule, see next_draw
+            (Job::Lottery, _) => "0 * * * * *",
+            (Job::BonesPrice, false) => "0 0 0,12 * * *",
+            (Job::BonesPrice, true) => "0 */5 * * * *",
+            // decays on the schedule in the config and warns ahead of it, see bones_decay
+            (Job::BonesDecay, _) => "0 * * * * *",
+            (Job::RoleDecay, _) => "0 */5 * * * *",
+            (Job::SkewedOdds, _) => "0 * * * * *",
+            (Job::Unghost, _) => "0 * * * * *",
+            (Job::Egg, _) => "0 * * * * *",
+            (Job::CrownTime, _) => "0 */5 * * * *",
+            (Job::RoleRentals, _) => "0 * * * * *",
+            (Job::Auctions, _) => "0 * * * * *",
+            (Job::RefreshConfig, _) => "0 * * * * *",
+        }
+    }
+
+    pub fn schedule(&self, dev_env: bool) -> Schedule {
+        Schedule::from_str(self.cron(dev_env)).unwrap()
+    }
+}
+
+/// Requests from `/jobs` to Johnny, who owns the jobs
+#[derive(Debug)]
+pub enum JobCommand {
+    Run(Job, oneshot::Sender<Result<(), String>>),
+    Pause(Job),
+    Resume(Job),
+}
+
+/// The first scheduled time after the job last ran
+pub fn next_run(schedule: &Schedule, last_run: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    schedule.after(&last_run).next()
+}
+
+/// The next draw of a lottery schedule after the given time. None if the expression
+/// doesn't parse or has no more upcoming times.
+pub fn next_draw(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    Schedule::from_str(expression).ok()?.after(&after).next()
+}
+
+/// A job is due once any of its scheduled times has passed since it last ran. Missed
+/// runs (slow ticks, downtime) are caught up with a single run.
+pub fn is_due(schedule: &Schedule, last_run: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    match next_run(schedule, last_run) {
+        Some(next) => next <= now,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_all_schedules_parse() {
+        for job in Job::ALL {
+            job.schedule(false);
+            job.schedule(true);
+        }
+    }
+
+    #[test]
+    fn test_bones_price_is_due_after_noon() {
+        let schedule = Job::BonesPrice.schedule(false);
+        let last_run = Utc.with_ymd_and_hms(2024, 10, 2, 0, 0, 3).unwrap();
+
+        let before = Utc.with_ymd_and_hms(2024, 10, 2, 11, 59, 59).unwrap();
+        assert!(!is_due(&schedule, last_run, before));
+
+        let after = Utc.with_ymd_and_hms(2024, 10, 2, 12, 0, 1).unwrap();
+        assert!(is_due(&schedule, last_run, after));
+    }
+
+    #[test]
+    fn test_weekly_lottery_draw() {
+        // a wednesday evening, the sunday draw is next
+        let now = Utc.with_ymd_and_hms(2024, 10, 2, 19, 0, 0).unwrap();
+        assert_eq!(
+            next_draw("0 0 18 * * Sun", now),
+            Some(Utc.with_ymd_and_hms(2024, 10, 6, 18, 0, 0).unwrap())
+        );
+        assert_eq!(next_draw("every sunday", now), None);
+    }
+
+    #[test]
+    fn test_missed_runs_catch_up() {
+        // bot was down over the weekend, the saturday decay still has to happen
+        let schedule = Schedule::from_str(BONES_DECAY_SCHEDULE).unwrap();
+        let last_run = Utc.with_ymd_and_hms(2024, 10, 4, 23, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 10, 7, 9, 30, 0).unwrap();
+        assert!(is_due(&schedule, last_run, now));
+        assert_eq!(
+            next_run(&schedule, last_run),
+            Some(Utc.with_ymd_and_hms(2024, 10, 5, 0, 0, 0).unwrap())
+        );
+    }
+}